@@ -1,21 +1,69 @@
-use crate::{MINESAVE_DATA_HOME, settings::Settings, utils::report_err};
-use anyhow::{Result, bail};
+use crate::{
+    MINESAVE_DATA_HOME,
+    game::{self, UNSAFE_TAG},
+    level::WorldInfo,
+    players,
+    progress::{TaskBackend, TaskProgressBars},
+    region::{self, Chunk, ChunkPos, Dimension, REGION_DIRS, Region},
+    settings::{RetentionPolicy, Settings},
+    tasks,
+    utils::{DirGuard, copy_dir, report_err},
+};
+use anyhow::{Context, Result, anyhow, bail};
+use chrono::{DateTime, Local, TimeZone};
 use rustic_backend::BackendOptions;
 use rustic_core::{
-    BackupOptions, CommandInput, ConfigOptions, KeyOptions, LocalDestination, LsOptions, PathList,
-    Repository, RepositoryOptions, RestoreOptions, SnapshotOptions, repofile::SnapshotFile,
+    BackupOptions, CommandInput, ConfigOptions, DataId, IdIndex, IndexedStatus, KeyOptions,
+    LocalDestination, LocalSource, LocalSourceFilterOptions, LocalSourceSaveOptions, LsOptions,
+    ParentOptions, PathList, PruneOptions, ReadSource, Repository, RepositoryOptions,
+    RestoreOptions, SnapshotGroupCriterion, SnapshotOptions,
+    repofile::{Node, SnapshotFile},
 };
 use serde::{Deserialize, Serialize};
 use std::{
     cmp::min,
-    collections::{HashMap, HashSet},
-    ffi::OsString,
+    collections::{BTreeMap, HashMap, HashSet},
     fs::{self, File},
-    hash::{DefaultHasher, Hash, Hasher},
-    path::{Path, PathBuf},
-    sync::{LazyLock, Mutex, MutexGuard},
+    hash::{BuildHasher, RandomState},
+    path::{Component, Path, PathBuf},
+    rc::Rc,
+    sync::{Arc, Condvar, LazyLock, Mutex, MutexGuard},
+    time::Duration,
 };
 
+/// Paths that Minecraft, mods and map renderers regenerate by themselves.
+pub const REGENERABLE_PATHS: &[&str] = &[
+    "session.lock",
+    "*.tmp",
+    "logs/",
+    "crash-reports/",
+    "cache/",
+    "DistantHorizons/",
+    "data/DistantHorizons.sqlite*",
+    "bluemap/",
+    "dynmap/",
+    "squaremap/",
+    "journeymap/",
+];
+
+/// A gitignore-style file in a world listing further paths to exclude from its backups.
+pub const IGNORE_FILE: &str = ".minesaveignore";
+
+/// File in a world holding the id of its save, so the save is recognized when the world is moved
+/// or renamed.
+pub const ID_FILE: &str = ".minesave-id";
+
+/// Directory next to the worlds, on the same filesystem, holding staged restores and the
+/// worlds they replaced.
+pub const WORK_DIR: &str = ".minesave";
+
+/// Label of the snapshot taken before a world is restored in place.
+pub const PRE_RESTORE_LABEL: &str = "pre-restore";
+
+/// File content restored between two checks for cancellation, as a rustic restore cannot be
+/// stopped once started.
+const RESTORE_BATCH: u64 = 64 << 20;
+
 #[derive(Debug, Default, Serialize, Deserialize)]
 #[serde(default)]
 pub struct AppState {
@@ -27,7 +75,8 @@ impl AppState {
     pub fn instance() -> MutexGuard<'static, AppState> {
         static INSTANCE: LazyLock<Mutex<AppState>> =
             LazyLock::new(|| Mutex::new(AppState::default()));
-        INSTANCE.lock().unwrap()
+        // A panic while holding the lock leaves the state usable
+        INSTANCE.lock().unwrap_or_else(|e| e.into_inner())
     }
 
     pub fn reload(&mut self) {
@@ -43,111 +92,843 @@ impl AppState {
         }
 
         debug!("rescan_saves");
-        for item in Settings::instance().scan_root.iter() {
-            let save_dirs: HashSet<PathBuf> = walkdir::WalkDir::new(item)
-                .into_iter()
-                .filter_map(|x| x.inspect_err(report_err("Error when visiting dir")).ok())
-                .filter(|x| x.file_type().is_dir())
-                .filter(|x| {
-                    fs::exists(x.path().join("level.dat"))
-                        .inspect_err(report_err("Error when visiting dir "))
-                        .is_ok_and(|x| x)
-                })
-                .filter(|x| x.path().extension() != Some(&OsString::from("recover")))
-                .map(|x| x.into_path())
-                .collect();
-            let add: Vec<PathBuf> = save_dirs.difference(&self.save_dirs).cloned().collect();
-            let delete: HashSet<PathBuf> = self.save_dirs.difference(&save_dirs).cloned().collect();
-            let mut delete_keys = vec![]; // TODO: for GC
-            for (k, v) in self.saves.iter() {
-                if delete.contains(&v.source) {
-                    delete_keys.push(k.clone());
+        let scan_root = { Settings::instance().scan_root.clone() };
+        let found: HashSet<PathBuf> = scan_root
+            .iter()
+            .flat_map(|item| {
+                walkdir::WalkDir::new(item)
+                    .into_iter()
+                    .filter_entry(|x| x.file_name() != WORK_DIR)
+                    .filter_map(|x| x.inspect_err(report_err("Error when visiting dir")).ok())
+                    .filter(|x| x.file_type().is_dir())
+                    .filter(|x| {
+                        fs::exists(x.path().join("level.dat"))
+                            .inspect_err(report_err("Error when visiting dir "))
+                            .is_ok_and(|x| x)
+                    })
+                    .filter(|x| !is_restore_leftover(x.path()))
+                    .map(|x| x.into_path())
+            })
+            .collect();
+        // Worlds still where they were go first, so they keep their id over copies of them
+        let mut dirs: Vec<&PathBuf> = found.iter().collect();
+        dirs.sort_by_key(|x| (!self.saves.values().any(|save| &save.source == *x), *x));
+        let mut claimed = HashSet::new();
+        for dir in dirs {
+            let id = match self.identify(dir, &found, &claimed) {
+                Some(id) if self.saves.contains_key(&id) => {
+                    let save = self.saves.get_mut(&id).unwrap();
+                    if &save.source != dir {
+                        info!(
+                            "save_relocated(id={}, from={:?}, to={:?})",
+                            id, save.source, dir
+                        );
+                        save.source = dir.clone();
+                        save.name = dir_name(dir);
+                    }
+                    if save.archived {
+                        info!("save_unarchived(id={})", id);
+                        save.archived = false;
+                    }
+                    id
                 }
-            }
-            for item in add {
-                let config = SaveBackupConfiguration::new(&item);
-                if let Ok(()) = fs::create_dir_all(MINESAVE_DATA_HOME.join("resources"))
-                    .inspect_err(report_err("Failed to create resources dir"))
-                {
-                    fs::copy(
-                        item.join("icon.png"),
-                        MINESAVE_DATA_HOME
-                            .join("resources")
-                            .join(&config.id)
-                            .with_extension("png"),
-                    )
-                    .inspect_err(report_err("Failed to copy icon to resources"))
+                // An unknown id comes from the marker of a world whose state was lost
+                id => self.add_save(dir, id.unwrap_or_else(new_id)),
+            };
+            if read_id_file(dir).as_ref() != Some(&id) {
+                fs::write(dir.join(ID_FILE), &id)
+                    .inspect_err(report_err("Failed to write id file"))
                     .unwrap_or_default();
-                }
-                self.saves.insert(config.id.clone(), config);
-                self.save_dirs.insert(item);
             }
+            let save = self.saves.get_mut(&id).unwrap();
+            save.refresh_info();
+            claimed.insert(id);
         }
+        self.save_dirs = found;
         self.save().unwrap_or_default()
     }
+    /// Add a save for a world, keeping its repository if one is there already.
+    fn add_save(&mut self, dir: &Path, id: String) -> String {
+        let mut config = SaveBackupConfiguration::new(dir, id);
+        config.init = MINESAVE_DATA_HOME.join("store").join(&config.id).exists();
+        if let Ok(()) = fs::create_dir_all(MINESAVE_DATA_HOME.join("resources"))
+            .inspect_err(report_err("Failed to create resources dir"))
+        {
+            fs::copy(
+                dir.join("icon.png"),
+                MINESAVE_DATA_HOME
+                    .join("resources")
+                    .join(&config.id)
+                    .with_extension("png"),
+            )
+            .inspect_err(report_err("Failed to copy icon to resources"))
+            .unwrap_or_default();
+        }
+        let id = config.id.clone();
+        self.saves.insert(id.clone(), config);
+        id
+    }
+    /// Find the save a world directory belongs to, following moves and renames: by the id file
+    /// in the world, then by the path it had before id files existed, then by its `level.dat`
+    /// among the saves whose world vanished.
+    fn identify(
+        &self,
+        dir: &Path,
+        found: &HashSet<PathBuf>,
+        claimed: &HashSet<String>,
+    ) -> Option<String> {
+        let vanished = |save: &SaveBackupConfiguration| {
+            !found.contains(&save.source) && !claimed.contains(&save.id)
+        };
+        if let Some(id) = read_id_file(dir).filter(|x| !claimed.contains(x)) {
+            match self.saves.get(&id) {
+                // Otherwise this is a copy of a world that is still there
+                Some(save) if save.source == dir || vanished(save) => return Some(id),
+                Some(_) => {}
+                None => return Some(id),
+            }
+        }
+        if let Some(save) = self
+            .saves
+            .values()
+            .find(|x| x.source == dir && !claimed.contains(&x.id))
+        {
+            return Some(save.id.clone());
+        }
+        let info = WorldInfo::read(dir).ok()?;
+        let mut matches = self
+            .saves
+            .values()
+            .filter(|x| vanished(x))
+            .filter(|x| x.info.as_ref().is_some_and(|old| old.same_world(&info)));
+        match (matches.next(), matches.next()) {
+            (Some(save), None) => Some(save.id.clone()),
+            _ => None,
+        }
+    }
+    /// Whether the world of a save was found by the last scan.
+    pub fn is_present(&self, save: &SaveBackupConfiguration) -> bool {
+        self.save_dirs.contains(&save.source)
+    }
+    /// Saves whose world vanished and that were not archived yet, by name.
+    pub fn orphaned(&self) -> Vec<&SaveBackupConfiguration> {
+        let mut saves: Vec<_> = self
+            .saves
+            .values()
+            .filter(|x| !x.archived && !self.is_present(x))
+            .collect();
+        saves.sort_by(|a, b| a.name.cmp(&b.name));
+        saves
+    }
+    /// Ids of the saves whose world is there, the ones backed up and forgotten together.
+    pub fn present_ids(&self) -> Vec<String> {
+        self.saves
+            .values()
+            .filter(|x| self.is_present(x))
+            .map(|x| x.id.clone())
+            .collect()
+    }
+    /// Keep the history of a save whose world vanished, to restore it later.
+    pub fn archive(&mut self, id: &str) -> Result<()> {
+        let save = self.saves.get_mut(id).ok_or(anyhow!("Invaild id"))?;
+        // `is_present`, borrowing only the field it needs
+        if self.save_dirs.contains(&save.source) {
+            bail!(
+                "{} still exists, only vanished worlds can be archived",
+                save.name
+            );
+        }
+        info!("archive_save(id={})", id);
+        save.archived = true;
+        self.save()
+    }
+    /// Copy the repository of a save into `<destination>/minesave-<id>`, along with its
+    /// configuration in `save.json`. Returns the copy.
+    pub fn export(id: &str, destination: &Path) -> Result<PathBuf> {
+        let save = AppState::instance()
+            .saves
+            .get(id)
+            .cloned()
+            .ok_or(anyhow!("Invaild id"))?;
+        let target = destination.join(format!("minesave-{}", id));
+        if target.exists() {
+            bail!("{} already exists", target.display());
+        }
+        let lock = repo_lock(id);
+        let _guard = lock.lock().unwrap_or_else(|e| e.into_inner());
+        let guard = DirGuard::new(target.clone());
+        copy_dir(&MINESAVE_DATA_HOME.join("store").join(id), &target)
+            .inspect_err(report_err("Failed to export repository"))?;
+        serde_json::to_writer_pretty(File::create(target.join("save.json"))?, &save)
+            .inspect_err(report_err("Failed to export save"))?;
+        info!("export_save(id={}, target={:?})", id, target);
+        Ok(guard.keep())
+    }
+    /// Add a save from a copy made by `export`, as archived until its world is restored.
+    /// Returns its id.
+    pub fn import(source: &Path) -> Result<String> {
+        let file = File::open(source.join("save.json"))
+            .inspect_err(report_err("Failed to open exported save"))?;
+        let mut save: SaveBackupConfiguration = serde_json::from_reader(file)
+            .inspect_err(report_err("Failed to read exported save"))?;
+        let id = save.id.clone();
+        if AppState::instance().saves.contains_key(&id) {
+            bail!("{} is already known", save.name);
+        }
+        let store = MINESAVE_DATA_HOME.join("store").join(&id);
+        if store.exists() {
+            bail!("{} already exists", store.display());
+        }
+        let guard = DirGuard::new(store.clone());
+        copy_dir(source, &store).inspect_err(report_err("Failed to import repository"))?;
+        fs::remove_file(store.join("save.json")).unwrap_or_default();
+        save.init = true;
+        save.archived = true;
+        save.trash.clear();
+        let mut state = AppState::instance();
+        state.saves.insert(id.clone(), save);
+        state.save()?;
+        guard.keep();
+        info!("import_save(id={}, source={:?})", id, source);
+        Ok(id)
+    }
+    /// Forget a save whose world vanished, deleting its repository, icon and rosters. Nothing
+    /// calls this without the user asking for it.
+    pub fn delete_save(&mut self, id: &str) -> Result<()> {
+        let save = self.saves.get(id).ok_or(anyhow!("Invaild id"))?;
+        if self.is_present(save) {
+            bail!(
+                "{} still exists, only vanished worlds can be deleted",
+                save.name
+            );
+        }
+        info!("delete_save(id={})", id);
+        let lock = repo_lock(id);
+        let _guard = lock.lock().unwrap_or_else(|e| e.into_inner());
+        let store = MINESAVE_DATA_HOME.join("store").join(id);
+        if store.exists() {
+            fs::remove_dir_all(store).inspect_err(report_err("Failed to delete repository"))?;
+        }
+        players::forget_rosters(id, None);
+        let icon = MINESAVE_DATA_HOME
+            .join("resources")
+            .join(id)
+            .with_extension("png");
+        if icon.exists() {
+            fs::remove_file(icon)
+                .inspect_err(report_err("Failed to delete icon"))
+                .unwrap_or_default();
+        }
+        self.saves.remove(id);
+        self.save()
+    }
+    /// Resolve a save by its id or, failing that, by its unique name.
+    pub fn find_id(&self, key: &str) -> Result<String> {
+        if self.saves.contains_key(key) {
+            return Ok(key.to_string());
+        }
+        let mut matches = self.saves.values().filter(|x| x.name == key);
+        match (matches.next(), matches.next()) {
+            (Some(save), None) => Ok(save.id.clone()),
+            (Some(_), Some(_)) => bail!("Save name '{}' is ambiguous, use its id instead", key),
+            (None, _) => bail!("No save named '{}'", key),
+        }
+    }
+    /// Back up a save. The state is only locked to copy the save out and to write the result
+    /// back, so it stays usable while the repository is written and saves can be backed up in
+    /// parallel, up to `Settings::backup_concurrency` at once.
+    ///
+    /// With `skip_unchanged`, returns `None` instead of writing a snapshot identical to the
+    /// previous one.
+    pub fn backup(
+        id: &str,
+        options: SnapshotOptions,
+        skip_unchanged: bool,
+    ) -> Result<Option<SnapshotFile>> {
+        let mut save = AppState::instance()
+            .saves
+            .get(id)
+            .cloned()
+            .ok_or(anyhow!("Invaild id"))?;
+        let options = if game::check_closed(&save.source, false)? {
+            options.add_tags(UNSAFE_TAG)?
+        } else {
+            options
+        };
+        let _slot = BackupSlot::acquire()?;
+        let res = save.run_backup(options, skip_unchanged);
+        let mut instance = AppState::instance();
+        if let Some(current) = instance.saves.get_mut(id) {
+            current.init |= save.init;
+            if let Ok(snapshot) = &res {
+                current.last_backup = Some(snapshot.time);
+            }
+        }
+        instance.save()?;
+        Ok(Some(res?).filter(|x| !x.id.is_null()))
+    }
+    /// Replace a world by a snapshot of it. A pre-restore snapshot of the world is taken first,
+    /// and the replaced world is kept in the trash until `empty_trash` or `undo_restore`.
+    /// Returns the pre-restore snapshot, unless the world did not change since the last one.
+    pub fn restore_in_place(id: &str, snapshot: SnapshotFile) -> Result<Option<SnapshotFile>> {
+        let source = AppState::instance()
+            .saves
+            .get(id)
+            .ok_or(anyhow!("Invaild id"))?
+            .source
+            .clone();
+        game::check_closed(&source, true)?;
+        let options = SnapshotOptions::default()
+            .label(PRE_RESTORE_LABEL.to_string())
+            .add_tags(PRE_RESTORE_LABEL)?;
+        let safety = AppState::backup(id, options, true)?;
+        let save = AppState::instance()
+            .saves
+            .get(id)
+            .cloned()
+            .ok_or(anyhow!("Invaild id"))?;
+        let trash = save.swap_in(snapshot)?;
+        let mut instance = AppState::instance();
+        if let Some(save) = instance.saves.get_mut(id) {
+            save.trash.push(trash);
+        }
+        instance.save()?;
+        Ok(safety)
+    }
+    /// Restore a snapshot next to the world as a new world, named after the date of the
+    /// snapshot both on disk and in `level.dat`, and add it as a save of its own. Returns the
+    /// id of the new save.
+    pub fn restore_as_copy(id: &str, snapshot: SnapshotFile) -> Result<String> {
+        let save = AppState::instance()
+            .saves
+            .get(id)
+            .cloned()
+            .ok_or(anyhow!("Invaild id"))?;
+        let parent = save
+            .source
+            .parent()
+            .ok_or(anyhow!("{} has no parent", save.source.display()))?;
+        // No colons, which Windows does not allow in file names
+        let destination = parent.join(format!(
+            "{} ({})",
+            save.name,
+            snapshot.time.format("%Y-%m-%d %H-%M-%S")
+        ));
+        if destination.exists() {
+            bail!("{} already exists", destination.display());
+        }
+        let level_name = format!(
+            "{} ({})",
+            save.display_name(),
+            snapshot.time.format("%Y-%m-%d %H:%M")
+        );
+        let restored = {
+            let lock = repo_lock(id);
+            let _guard = lock.lock().unwrap_or_else(|e| e.into_inner());
+            save.restore_into(snapshot, &destination)?
+        };
+        // Otherwise the copy would be taken for the original world
+        if destination.join(ID_FILE).exists() {
+            fs::remove_file(destination.join(ID_FILE))
+                .inspect_err(report_err("Failed to remove id file"))?;
+        }
+        WorldInfo::set_level_name(&destination, &level_name)
+            .inspect_err(|e| warn!("Failed to rename world in level.dat: {:#}", e))?;
+
+        let mut instance = AppState::instance();
+        let copy = instance.add_save(&destination, new_id());
+        fs::write(destination.join(ID_FILE), &copy)
+            .inspect_err(report_err("Failed to write id file"))?;
+        instance.saves.get_mut(&copy).unwrap().refresh_info();
+        instance.save_dirs.insert(destination.clone());
+        instance.save()?;
+        restored.keep();
+        info!(
+            "restore_as_copy(id={}, copy={}, destination={:?})",
+            id, copy, destination
+        );
+        Ok(copy)
+    }
+    /// Replace some paths of a world, relative to it, by their version in a snapshot, after
+    /// taking a pre-restore snapshot of the world. Returns the pre-restore snapshot, unless the
+    /// world did not change since the last one.
+    pub fn restore_paths(
+        id: &str,
+        snapshot: SnapshotFile,
+        paths: &[PathBuf],
+    ) -> Result<Option<SnapshotFile>> {
+        if paths.is_empty() {
+            bail!("No path to restore");
+        }
+        if let Some(path) = paths.iter().find(|x| {
+            x.as_os_str().is_empty() || !x.components().all(|x| matches!(x, Component::Normal(_)))
+        }) {
+            bail!("{} is not a path inside the world", path.display());
+        }
+        let source = AppState::instance()
+            .saves
+            .get(id)
+            .ok_or(anyhow!("Invaild id"))?
+            .source
+            .clone();
+        game::check_closed(&source, true)?;
+        let options = SnapshotOptions::default()
+            .label(PRE_RESTORE_LABEL.to_string())
+            .add_tags(PRE_RESTORE_LABEL)?;
+        let safety = AppState::backup(id, options, true)?;
+        let save = AppState::instance()
+            .saves
+            .get(id)
+            .cloned()
+            .ok_or(anyhow!("Invaild id"))?;
+        save.swap_in_paths(snapshot, paths)?;
+        Ok(safety)
+    }
+
+    /// Put back the inventory, advancements and statistics of one player from a snapshot,
+    /// after taking a pre-restore snapshot of the world. The other players keep their progress.
+    /// Returns the pre-restore snapshot, unless the world did not change since the last one,
+    /// and the files restored.
+    pub fn restore_player(
+        id: &str,
+        snapshot: SnapshotFile,
+        uuid: &str,
+    ) -> Result<(Option<SnapshotFile>, Vec<PathBuf>)> {
+        let save = AppState::instance()
+            .saves
+            .get(id)
+            .cloned()
+            .ok_or(anyhow!("Invaild id"))?;
+        let files: HashSet<PathBuf> = save
+            .list_snapshot(&snapshot)?
+            .into_iter()
+            .filter(|x| !x.dir)
+            .map(|x| x.path)
+            .collect();
+        let paths: Vec<PathBuf> = players::player_files(uuid)
+            .into_iter()
+            .filter(|x| files.contains(x))
+            .collect();
+        if !paths.iter().any(|x| x.starts_with("playerdata")) {
+            bail!("Player {} has no data in snapshot {}", uuid, snapshot.id);
+        }
+        let safety = Self::restore_paths(id, snapshot, &paths)?;
+        info!("restore_player(id={}, uuid={})", id, uuid);
+        Ok((safety, paths))
+    }
+
+    /// Copy chunks of a dimension from a snapshot into the world, after taking a pre-restore
+    /// snapshot of it. Returns the pre-restore snapshot, unless the world did not change since
+    /// the last one, and how many chunks were replaced and removed.
+    pub fn restore_chunks(
+        id: &str,
+        snapshot: SnapshotFile,
+        dimension: Dimension,
+        chunks: &[ChunkPos],
+    ) -> Result<(Option<SnapshotFile>, usize, usize)> {
+        if chunks.is_empty() {
+            bail!("No chunk to restore");
+        }
+        let source = AppState::instance()
+            .saves
+            .get(id)
+            .ok_or(anyhow!("Invaild id"))?
+            .source
+            .clone();
+        game::check_closed(&source, true)?;
+        let options = SnapshotOptions::default()
+            .label(PRE_RESTORE_LABEL.to_string())
+            .add_tags(PRE_RESTORE_LABEL)?;
+        let safety = AppState::backup(id, options, true)?;
+        let save = AppState::instance()
+            .saves
+            .get(id)
+            .cloned()
+            .ok_or(anyhow!("Invaild id"))?;
+        let (replaced, removed) = save.swap_in_chunks(&snapshot, dimension, chunks)?;
+        Ok((safety, replaced, removed))
+    }
+    /// Delete the worlds replaced by in-place restores of a save, and return them.
+    pub fn empty_trash(id: &str) -> Result<Vec<PathBuf>> {
+        let trash = AppState::instance()
+            .saves
+            .get(id)
+            .ok_or(anyhow!("Invaild id"))?
+            .trash
+            .clone();
+        let mut removed = vec![];
+        let res = trash.iter().try_for_each(|path| {
+            if path.exists() {
+                fs::remove_dir_all(path).inspect_err(report_err("Failed to empty trash"))?;
+            }
+            removed.push(path.clone());
+            anyhow::Ok(())
+        });
+        let mut instance = AppState::instance();
+        if let Some(save) = instance.saves.get_mut(id) {
+            save.trash.retain(|x| !removed.contains(x));
+        }
+        instance.save()?;
+        res.map(|()| removed)
+    }
+    /// Put back the world replaced by the last in-place restore of a save, dropping the
+    /// restored one. Returns where the replaced world was kept.
+    pub fn undo_restore(id: &str) -> Result<PathBuf> {
+        let save = AppState::instance()
+            .saves
+            .get(id)
+            .cloned()
+            .ok_or(anyhow!("Invaild id"))?;
+        let Some(trash) = save.trash.last() else {
+            bail!("Nothing to undo for {}", save.name);
+        };
+        game::check_closed(&save.source, true)?;
+        let lock = repo_lock(id);
+        let _guard = lock.lock().unwrap_or_else(|e| e.into_inner());
+        let discarded = save.work_dir().join("discarded").join(&save.id);
+        if discarded.exists() {
+            fs::remove_dir_all(&discarded).inspect_err(report_err("Failed to clean up"))?;
+        }
+        fs::create_dir_all(save.work_dir().join("discarded"))
+            .inspect_err(report_err("Failed to create work dir"))?;
+        fs::rename(&save.source, &discarded).inspect_err(report_err("Failed to move world"))?;
+        if let Err(err) = fs::rename(trash, &save.source) {
+            fs::rename(&discarded, &save.source)
+                .inspect_err(report_err("Failed to move world back"))?;
+            bail!("Failed to put back {}: {}", trash.display(), err);
+        }
+        fs::remove_dir_all(&discarded)
+            .inspect_err(report_err("Failed to remove restored world"))
+            .unwrap_or_default();
+        let mut instance = AppState::instance();
+        if let Some(save) = instance.saves.get_mut(id) {
+            save.trash.pop();
+        }
+        instance.save()?;
+        Ok(trash.clone())
+    }
     pub fn save(&self) -> Result<()> {
         debug!("save_state");
+        // Write a temporary file first, so the state is never left half-written
+        let path = MINESAVE_DATA_HOME.join("state.json");
+        let tmp = path.with_extension("json.tmp");
         serde_json::to_writer(
-            File::create(MINESAVE_DATA_HOME.join("state.json"))
-                .inspect_err(report_err("Failed to save state file"))?,
+            File::create(&tmp).inspect_err(report_err("Failed to save state file"))?,
             self,
         )
         .inspect_err(report_err("Failed to save state file"))?;
+        fs::rename(tmp, path).inspect_err(report_err("Failed to save state file"))?;
         Ok(())
     }
 }
+
+/// A random id for a new save. Ids used to be hashes of the world path, which changed whenever
+/// the world moved.
+fn new_id() -> String {
+    // Every `RandomState` is seeded differently
+    format!("{:016x}", RandomState::new().hash_one(Local::now()))
+}
+
+/// The id written in a world by `AppState::reload`, if it looks valid.
+fn read_id_file(dir: &Path) -> Option<String> {
+    let id = fs::read_to_string(dir.join(ID_FILE)).ok()?;
+    let id = id.trim();
+    (!id.is_empty() && id.chars().all(|x| x.is_ascii_alphanumeric() || x == '-'))
+        .then(|| id.to_string())
+}
+
+/// Whether a directory is left over by a restore, as a `<world>.recover` kept for the user or a
+/// `<world>.partial` of one that crashed, rather than a world of its own.
+fn is_restore_leftover(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|x| x == "recover" || x == "partial")
+}
+
+fn dir_name(path: &Path) -> String {
+    path.file_name()
+        .unwrap_or(path.as_os_str())
+        .to_string_lossy()
+        .to_string()
+}
+
+/// One of the `Settings::backup_concurrency` backups allowed to run at once, across every task.
+struct BackupSlot;
+
+static BACKUP_SLOTS: (Mutex<usize>, Condvar) = (Mutex::new(0), Condvar::new());
+
+impl BackupSlot {
+    /// Wait for a free slot. Gives up if the current task is cancelled meanwhile.
+    fn acquire() -> Result<Self> {
+        let limit = { Settings::instance().backup_concurrency.max(1) } as usize;
+        let (used, freed) = &BACKUP_SLOTS;
+        let mut used = used.lock().unwrap_or_else(|e| e.into_inner());
+        while *used >= limit {
+            tasks::checkpoint()?;
+            used = freed
+                .wait_timeout(used, Duration::from_millis(200))
+                .unwrap_or_else(|e| e.into_inner())
+                .0;
+        }
+        *used += 1;
+        Ok(Self)
+    }
+}
+
+impl Drop for BackupSlot {
+    fn drop(&mut self) {
+        let (used, freed) = &BACKUP_SLOTS;
+        *used.lock().unwrap_or_else(|e| e.into_inner()) -= 1;
+        freed.notify_one();
+    }
+}
+
+/// The files added, removed or of another version in `new`, sorted by path.
+fn compare_files<T: PartialEq>(
+    old: &HashMap<PathBuf, (u64, T)>,
+    new: &HashMap<PathBuf, (u64, T)>,
+) -> Vec<FileChange> {
+    let mut changes: Vec<FileChange> = old
+        .iter()
+        .filter(|(path, file)| new.get(*path) != Some(file))
+        .map(|(path, file)| FileChange {
+            path: path.clone(),
+            before: Some(file.0),
+            after: new.get(path).map(|x| x.0),
+        })
+        .chain(
+            new.iter()
+                .filter(|(path, _)| !old.contains_key(*path))
+                .map(|(path, file)| FileChange {
+                    path: path.clone(),
+                    before: None,
+                    after: Some(file.0),
+                }),
+        )
+        .collect();
+    changes.sort_by(|a, b| a.path.cmp(&b.path));
+    changes
+}
+
+/// A repository opened with the ids of its blobs in memory.
+type Repo = Repository<TaskProgressBars, IndexedStatus<IdIndex, rustic_core::OpenStatus>>;
+
+/// Serializes the commands writing to the repository of a save, while the repositories of
+/// different saves can be written at the same time.
+fn repo_lock(id: &str) -> Arc<Mutex<()>> {
+    static LOCKS: LazyLock<Mutex<HashMap<String, Arc<Mutex<()>>>>> =
+        LazyLock::new(|| Mutex::new(HashMap::new()));
+    LOCKS
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .entry(id.to_string())
+        .or_default()
+        .clone()
+}
+/// How the chunks of an area looked in a snapshot, compared to the snapshot before.
+#[derive(Debug, Clone, Serialize)]
+pub struct AreaHistoryEntry {
+    pub snapshot: String,
+    pub time: DateTime<Local>,
+    pub label: String,
+    /// Chunks saved by the game since the snapshot before, or new in this one
+    pub changed: Vec<ChunkPos>,
+    /// Chunks in the snapshot before, but not in this one
+    pub removed: Vec<ChunkPos>,
+    /// How many chunks of the area are in this snapshot
+    pub present: usize,
+    /// When the game last saved a chunk of the area
+    pub last_saved: Option<DateTime<Local>>,
+}
+
+/// A file added, removed or modified between two versions of a world.
+#[derive(Debug, Clone, Serialize)]
+pub struct FileChange {
+    /// Relative to the world
+    pub path: PathBuf,
+    /// Size before, `None` when the file was added
+    pub before: Option<u64>,
+    /// Size after, `None` when the file was removed
+    pub after: Option<u64>,
+}
+
+/// A file or directory in a snapshot.
+#[derive(Debug, Clone, Serialize)]
+pub struct TreeEntry {
+    /// Relative to the world
+    pub path: PathBuf,
+    pub dir: bool,
+    pub size: u64,
+    pub modified: Option<DateTime<Local>>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SaveBackupConfiguration {
     id: String,
     pub name: String,
     init: bool,
     source: PathBuf,
+    /// Time of the last successful backup
+    pub last_backup: Option<DateTime<Local>>,
+    /// Overrides `Settings::daemon_backup_duration` for this save
+    pub backup_duration: Option<u32>,
+    /// Overrides `Settings::daemon_backup_cron` for this save
+    pub backup_cron: Option<String>,
+    /// Overrides `Settings::retention` for this save
+    pub retention: Option<RetentionPolicy>,
+    /// Patterns excluded from the backups of this save, on top of `Settings::excludes`
+    #[serde(default)]
+    pub excludes: Vec<String>,
+    /// Worlds replaced by in-place restores, oldest first, until the user confirms the restore
+    #[serde(default)]
+    pub trash: Vec<PathBuf>,
+    /// Read from `level.dat` whenever the saves are scanned
+    #[serde(default)]
+    pub info: Option<WorldInfo>,
+    /// The world vanished and the user chose to keep its history
+    #[serde(default)]
+    pub archived: bool,
 }
 impl SaveBackupConfiguration {
-    pub fn new<P: AsRef<Path>>(source: P) -> Self {
-        let mut hasher = DefaultHasher::new();
-        source.as_ref().hash(&mut hasher);
+    pub fn new<P: AsRef<Path>>(source: P, id: String) -> Self {
         Self {
-            id: format!("{:x}", hasher.finish()),
-            name: source
-                .as_ref()
-                .file_name()
-                .unwrap()
-                .to_string_lossy()
-                .to_string(),
+            id,
+            name: dir_name(source.as_ref()),
             init: false,
             source: source.as_ref().to_path_buf(),
+            last_backup: None,
+            backup_duration: None,
+            backup_cron: None,
+            retention: None,
+            excludes: vec![],
+            trash: vec![],
+            info: None,
+            archived: false,
+        }
+    }
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+    pub fn source(&self) -> &Path {
+        &self.source
+    }
+    /// The name of the world in the game, falling back to its directory name.
+    pub fn display_name(&self) -> &str {
+        self.info
+            .as_ref()
+            .and_then(|x| x.level_name.as_deref())
+            .unwrap_or(&self.name)
+    }
+    /// Read `level.dat` again. The previous metadata is kept if it cannot be read.
+    pub fn refresh_info(&mut self) {
+        if let Ok(info) = WorldInfo::read(&self.source)
+            .inspect_err(|e| warn!("Failed to read level.dat of {}: {:#}", self.id, e))
+        {
+            self.info = Some(info);
         }
     }
-    pub fn run_backup(&mut self, snapshot_options: SnapshotOptions) -> Result<()> {
+    /// Back up into the repository of this save. Prefer `AppState::backup`, which writes the
+    /// result back into the state. With `skip_unchanged`, the snapshot is not written if
+    /// nothing changed since the previous one, and is returned with a null id.
+    pub fn run_backup(
+        &mut self,
+        snapshot_options: SnapshotOptions,
+        skip_unchanged: bool,
+    ) -> Result<SnapshotFile> {
         debug!(
             "backup_start(id={}, options={:?})",
             self.id, snapshot_options
         );
+        let lock = repo_lock(&self.id);
+        let _guard = lock.lock().unwrap_or_else(|e| e.into_inner());
         let repo = self.open_repo()?;
         self.init = true;
-        let backup_options = BackupOptions::default();
+        let backup_options = BackupOptions::default()
+            .ignore_filter_opts(self.filter_options())
+            // Labels describe a single snapshot, the previous one is found by path alone
+            .parent_opts(
+                ParentOptions::default()
+                    .group_by(SnapshotGroupCriterion::new().hostname(true).paths(true))
+                    .skip_if_unchanged(skip_unchanged),
+            );
         let source = PathList::from_string(
             self.source
                 .to_str()
                 .expect("Character in path is not UTF-8"),
         )
         .inspect_err(report_err("Failed to parse source path"))?;
-        let _file = repo
-            .backup(
-                &backup_options,
-                &source,
-                snapshot_options
-                    .to_snapshot()
-                    .inspect_err(report_err("Bad snapshot options"))?,
-            )
-            .inspect_err(report_err("Failed to create backup"))?;
+        let snapshot_file = snapshot_options
+            .to_snapshot()
+            .inspect_err(report_err("Bad snapshot options"))?;
+        // Read before the files are backed up, so the roster is no newer than the snapshot
+        let roster = players::roster(&self.source)
+            .inspect_err(|err| warn!("Failed to read players: {:#}", err))
+            .ok();
+        let snapshot = tasks::or_cancelled(
+            repo.backup(&backup_options, &source, snapshot_file)
+                .inspect_err(report_err("Failed to create backup")),
+        )?;
+        self.last_backup = Some(snapshot.time);
+        if let Some(roster) = roster.filter(|_| !snapshot.id.is_null()) {
+            players::record_roster(&self.id, &snapshot, &roster);
+        }
 
         debug!(
             "backup_finish(id={}, option={:?})",
             self.id, snapshot_options
         );
-        Ok(())
+        Ok(snapshot)
+    }
+
+    /// Patterns from the built-in list, the settings and this save, in that order.
+    pub fn excludes(&self, settings: &Settings) -> Vec<String> {
+        let regenerable: &[&str] = if settings.include_regenerable {
+            &[]
+        } else {
+            REGENERABLE_PATHS
+        };
+        regenerable
+            .iter()
+            .map(|x| x.to_string())
+            .chain(settings.excludes.iter().cloned())
+            .chain(self.excludes.iter().cloned())
+            .collect()
+    }
+
+    /// Which files of the world are backed up.
+    fn filter_options(&self) -> LocalSourceFilterOptions {
+        LocalSourceFilterOptions::default()
+            .globs(self.exclude_globs(&Settings::instance()))
+            .custom_ignorefiles(vec![IGNORE_FILE.to_string()])
+    }
+
+    /// Turn the exclude patterns into rustic's override globs. Like in `.gitignore`, a pattern
+    /// containing a slash is relative to the world directory, others match at any depth.
+    fn exclude_globs(&self, settings: &Settings) -> Vec<String> {
+        let root = self
+            .source
+            .to_string_lossy()
+            .replace(std::path::MAIN_SEPARATOR, "/");
+        let root: String = root
+            .trim_matches('/')
+            .chars()
+            .map(|c| match c {
+                '*' | '?' | '[' | ']' | '{' | '}' | '\\' => format!("[{}]", c),
+                c => c.to_string(),
+            })
+            .collect();
+        self.excludes(settings)
+            .iter()
+            .map(|x| x.trim())
+            .filter(|x| !x.is_empty() && !x.starts_with('#'))
+            .map(|x| {
+                let path = x.trim_end_matches('/');
+                if path.starts_with("**/") || !path.contains('/') {
+                    format!("!{}", x)
+                } else {
+                    format!("!/{}/{}", root, x.trim_start_matches('/'))
+                }
+            })
+            .collect()
     }
 
     pub fn list_backups(&self) -> Result<Vec<SnapshotFile>> {
@@ -161,49 +942,658 @@ impl SaveBackupConfiguration {
             .inspect_err(report_err("Failed to list snapshots"))?)
     }
 
+    /// Forget the snapshots not kept by the retention policy of this save, and return them.
+    /// With `dry_run` nothing is deleted.
+    pub fn forget(&self, prune: bool, dry_run: bool) -> Result<Vec<SnapshotFile>> {
+        let policy = self
+            .retention
+            .clone()
+            .unwrap_or_else(|| Settings::instance().retention.clone());
+        if !self.init || policy.is_empty() {
+            return Ok(vec![]);
+        }
+        debug!("forget_start(id={}, policy={:?})", self.id, policy);
+        let lock = repo_lock(&self.id);
+        let _guard = lock.lock().unwrap_or_else(|e| e.into_inner());
+        let repo = self.open_repo()?;
+        let forget: Vec<SnapshotFile> = repo
+            .get_forget_snapshots(
+                &policy.to_keep_options()?,
+                SnapshotGroupCriterion::new(),
+                |_| true,
+            )
+            .inspect_err(report_err("Failed to apply retention policy"))?
+            .0
+            .into_iter()
+            .flat_map(|x| x.snapshots)
+            .filter(|x| !x.keep)
+            .map(|x| x.snapshot)
+            .collect();
+        if dry_run || forget.is_empty() {
+            return Ok(forget);
+        }
+        repo.delete_snapshots(&forget.iter().map(|x| x.id).collect::<Vec<_>>())
+            .inspect_err(report_err("Failed to delete snapshots"))?;
+        players::forget_rosters(&self.id, Some(&forget));
+        if prune {
+            tasks::checkpoint()?;
+            let prune_options = PruneOptions::default();
+            let plan = repo
+                .prune_plan(&prune_options)
+                .inspect_err(report_err("Failed to plan pruning"))?;
+            tasks::or_cancelled(
+                repo.prune(&prune_options, plan)
+                    .inspect_err(report_err("Failed to prune")),
+            )?;
+        }
+        debug!("forget_finish(id={}, count={})", self.id, forget.len());
+        Ok(forget)
+    }
+
+    pub fn find_snapshot(&self, id: &str) -> Result<SnapshotFile> {
+        if !self.init {
+            bail!("Repo is not initalized");
+        }
+        let repo = self.open_repo()?;
+        Ok(repo
+            .get_snapshot_from_str(id, |_| true)
+            .inspect_err(report_err("Failed to find snapshot"))?)
+    }
+
+    /// Restore a snapshot into `<world>.recover`, which must not exist yet.
     pub fn recover(&self, snapshot: SnapshotFile) -> Result<()> {
+        game::check_closed(&self.source, true)?;
+        let lock = repo_lock(&self.id);
+        let _guard = lock.lock().unwrap_or_else(|e| e.into_inner());
+        self.restore_into(snapshot, &self.source.with_added_extension("recover"))?
+            .keep();
+        Ok(())
+    }
+
+    /// Restore a snapshot as a new directory `destination`, wherever the world was backed up
+    /// from.
+    pub fn restore_to(&self, snapshot: SnapshotFile, destination: &Path) -> Result<()> {
+        if destination.exists() {
+            bail!("{} already exists", destination.display());
+        }
+        let lock = repo_lock(&self.id);
+        let _guard = lock.lock().unwrap_or_else(|e| e.into_inner());
+        self.restore_into(snapshot, destination)?.keep();
+        info!("restore_to(id={}, destination={:?})", self.id, destination);
+        Ok(())
+    }
+
+    fn work_dir(&self) -> PathBuf {
+        self.source.parent().unwrap_or(&self.source).join(WORK_DIR)
+    }
+
+    /// Restore into a staging directory, then swap it with the world. Returns where the
+    /// replaced world was moved.
+    fn swap_in(&self, snapshot: SnapshotFile) -> Result<PathBuf> {
+        let lock = repo_lock(&self.id);
+        let _guard = lock.lock().unwrap_or_else(|e| e.into_inner());
+        let staging = self.work_dir().join("staging").join(&self.id);
+        if staging.exists() {
+            // Left over by a restore that crashed
+            fs::remove_dir_all(&staging).inspect_err(report_err("Failed to clean up"))?;
+        }
+        let trash = self.work_dir().join("trash").join(format!(
+            "{}-{}",
+            self.name,
+            Local::now().format("%Y%m%d%H%M%S")
+        ));
+        fs::create_dir_all(self.work_dir().join("trash"))
+            .inspect_err(report_err("Failed to create work dir"))?;
+        fs::create_dir_all(self.work_dir().join("staging"))
+            .inspect_err(report_err("Failed to create work dir"))?;
+        let staged = self.restore_into(snapshot, &staging)?;
+
+        // Both renames stay on one filesystem, the world is only missing in between
+        tasks::checkpoint()?;
+        fs::rename(&self.source, &trash).inspect_err(report_err("Failed to move world"))?;
+        if let Err(err) = fs::rename(&staging, &self.source) {
+            fs::rename(&trash, &self.source)
+                .inspect_err(report_err("Failed to move world back"))?;
+            bail!("Failed to move restored world into place: {}", err);
+        }
+        staged.keep();
+        info!("restore_in_place(id={}, trash={:?})", self.id, trash);
+        Ok(trash)
+    }
+
+    /// Restore a snapshot into `target` and check that every file came back whole. The
+    /// returned guard removes `target` unless kept.
+    fn restore_into(&self, snapshot: SnapshotFile, target: &Path) -> Result<DirGuard> {
+        Ok(self.restore_path_into(snapshot, Path::new(""), target)?.0)
+    }
+
+    /// Restore `path` of a snapshot, relative to the world, into the new directory `target`:
+    /// its content if it is a directory, itself if it is a file. Returns the guard removing
+    /// `target` unless kept, and where `path` was restored.
+    fn restore_path_into(
+        &self,
+        snapshot: SnapshotFile,
+        path: &Path,
+        target: &Path,
+    ) -> Result<(DirGuard, PathBuf)> {
+        if fs::symlink_metadata(target).is_ok() {
+            bail!("{} already exists", target.display());
+        }
         let repo = self
             .open_repo()?
             .to_indexed()
             .inspect_err(report_err("Failed to index repo fully"))?;
 
+        // Restored next to `target` and moved there once complete, so that failing or being
+        // cancelled only removes what this restore created
+        let partial = target.with_added_extension("partial");
+        if let Some(parent) = partial.parent() {
+            fs::create_dir_all(parent).inspect_err(report_err("Failed to create destination"))?;
+        }
+        if partial.exists() {
+            // Left over by a restore that crashed
+            fs::remove_dir_all(&partial).inspect_err(report_err("Failed to clean up"))?;
+        }
+        fs::create_dir(&partial)
+            .with_context(|| format!("Failed to create {}", partial.display()))?;
+        let guard = DirGuard::new(partial.clone());
+
         let opts = RestoreOptions::default();
-        let dest = LocalDestination::new(
-            &self
-                .source
-                .with_added_extension("recover")
-                .to_str()
-                .expect("Not a vaild UTF-8"),
-            true,
-            false,
-        )
-        .inspect_err(report_err("Failed to create destination"))?;
+        let dest = LocalDestination::new(partial.to_str().expect("Not a vaild UTF-8"), true, false)
+            .inspect_err(report_err("Failed to create destination"))?;
 
+        let mut recorded = self.snapshot_root(&snapshot);
+        if !path.as_os_str().is_empty() {
+            recorded.push(path);
+        }
         let node = repo
-            .node_from_path(snapshot.tree, &self.source)
+            .node_from_path(snapshot.tree, &recorded)
             .inspect_err(report_err("Failed to find node from backup storage"))?;
+        let restored = match (node.is_dir(), path.file_name()) {
+            (false, Some(name)) => target.join(name),
+            _ => target.to_path_buf(),
+        };
         let ls_opts = LsOptions::default();
-        let node_streamer = repo
+        let nodes: Vec<(PathBuf, Node)> = repo
             .ls(&node, &ls_opts)
-            .inspect_err(report_err("Failed to open node_streamer"))?;
+            .inspect_err(report_err("Failed to open node_streamer"))?
+            .collect::<Result<_, _>>()
+            .inspect_err(report_err("Failed to list snapshot"))?;
 
-        let restore_infos = repo
-            .prepare_restore(&opts, node_streamer.clone(), &dest, false)
-            .inspect_err(report_err("Failed to prepare recovery"))?;
+        // Batches of files in the order listed, which rustic expects. Directories go with the
+        // first batch to be created, and with the last to get their metadata back.
+        let mut batch_of = Vec::with_capacity(nodes.len());
+        let (mut count, mut size) = (0, 0);
+        for (_, node) in &nodes {
+            if !node.is_dir() {
+                if count == 0 || size >= RESTORE_BATCH {
+                    count += 1;
+                    size = 0;
+                }
+                size += node.meta.size;
+            }
+            batch_of.push(count.max(1) - 1);
+        }
+        let count = count.max(1);
+        let file_size = |(_, node): &(PathBuf, Node)| {
+            if node.is_dir() { 0 } else { node.meta.size }
+        };
+        let task = tasks::current().unwrap_or_default();
+        task.start_counter(
+            "restoring file contents...",
+            nodes.iter().map(file_size).sum(),
+            true,
+        );
+        let res = (0..count).try_for_each(|i| {
+            tasks::checkpoint()?;
+            let batch = nodes
+                .iter()
+                .zip(&batch_of)
+                .filter(|((_, node), batch)| {
+                    if node.is_dir() {
+                        i == 0 || i == count - 1
+                    } else {
+                        **batch == i
+                    }
+                })
+                .map(|x| x.0);
+            let restore_infos = repo
+                .prepare_restore(&opts, batch.clone().cloned().map(Ok), &dest, false)
+                .inspect_err(report_err("Failed to prepare recovery"))?;
+            repo.restore(restore_infos, &opts, batch.clone().cloned().map(Ok), &dest)?;
+            task.inc(batch.map(file_size).sum());
+            anyhow::Ok(())
+        });
+        task.stop_counter();
+        res?;
 
-        repo.restore(restore_infos, &opts, node_streamer, &dest)?;
+        for (path, node) in &nodes {
+            if !node.is_file() {
+                continue;
+            }
+            let size = fs::metadata(partial.join(path)).map(|x| x.len()).ok();
+            if size != Some(node.meta.size) {
+                bail!("Restored file {} is missing or damaged", path.display());
+            }
+        }
+        fs::rename(&partial, target)
+            .inspect_err(report_err("Failed to move restore into place"))?;
+        guard.keep();
+        Ok((DirGuard::new(target.to_path_buf()), restored))
+    }
 
+    /// Where the world was when the snapshot was taken. The world may have moved since, or not
+    /// exist at all anymore.
+    fn snapshot_root(&self, snapshot: &SnapshotFile) -> PathBuf {
+        snapshot
+            .paths
+            .iter()
+            .next()
+            .map(PathBuf::from)
+            .unwrap_or_else(|| self.source.clone())
+    }
+
+    /// Every file and directory in a snapshot, relative to the world.
+    pub fn list_snapshot(&self, snapshot: &SnapshotFile) -> Result<Vec<TreeEntry>> {
+        let repo = self.open_repo()?;
+        let node = repo
+            .node_from_path(snapshot.tree, &self.snapshot_root(snapshot))
+            .inspect_err(report_err("Failed to find node from backup storage"))?;
+        repo.ls(&node, &LsOptions::default())
+            .inspect_err(report_err("Failed to open node_streamer"))?
+            .map(|item| {
+                let (path, node) = item.inspect_err(report_err("Failed to list snapshot"))?;
+                Ok(TreeEntry {
+                    dir: node.is_dir(),
+                    size: node.meta.size,
+                    modified: node.meta.mtime,
+                    path,
+                })
+            })
+            .collect()
+    }
+
+    /// The files in a snapshot, relative to the world, with what tells their versions apart.
+    fn snapshot_files<T>(
+        &self,
+        repo: &Repo,
+        snapshot: &SnapshotFile,
+        version: impl Fn(Node) -> T,
+    ) -> Result<HashMap<PathBuf, T>> {
+        let node = repo
+            .node_from_path(snapshot.tree, &self.snapshot_root(snapshot))
+            .inspect_err(report_err("Failed to find node from backup storage"))?;
+        let mut files = HashMap::new();
+        for item in repo
+            .ls(&node, &LsOptions::default())
+            .inspect_err(report_err("Failed to open node_streamer"))?
+        {
+            let (path, node) = item.inspect_err(report_err("Failed to list snapshot"))?;
+            if node.is_file() {
+                files.insert(path, version(node));
+            }
+        }
+        Ok(files)
+    }
+
+    /// The files that differ between two snapshots, relative to the world, by path.
+    pub fn changed_files(&self, from: &SnapshotFile, to: &SnapshotFile) -> Result<Vec<FileChange>> {
+        let repo = self.open_repo()?;
+        let content = |node: Node| (node.meta.size, node.content.unwrap_or_default());
+        Ok(compare_files(
+            &self.snapshot_files(&repo, from, content)?,
+            &self.snapshot_files(&repo, to, content)?,
+        ))
+    }
+
+    /// The files of the world that differ from a snapshot, by path. Excluded files are left out,
+    /// and like for backups, a file of the same size and modification time is unchanged.
+    pub fn changed_files_live(&self, snapshot: &SnapshotFile) -> Result<Vec<FileChange>> {
+        if !self.source.is_dir() {
+            bail!("World {} does not exist", self.source.display());
+        }
+        let repo = self.open_repo()?;
+        let metadata = |node: Node| (node.meta.size, node.meta.mtime);
+        let old = self.snapshot_files(&repo, snapshot, metadata)?;
+        let source = LocalSource::new(
+            LocalSourceSaveOptions::default(),
+            &self.filter_options(),
+            &[&self.source],
+        )
+        .inspect_err(report_err("Failed to open world"))?;
+        let mut new = HashMap::new();
+        for entry in source.entries() {
+            let entry = entry.inspect_err(report_err("Failed to list world"))?;
+            if !entry.node.is_file() {
+                continue;
+            }
+            if let Ok(path) = entry.path.strip_prefix(&self.source) {
+                new.insert(path.to_path_buf(), metadata(entry.node));
+            }
+        }
+        Ok(compare_files(&old, &new))
+    }
+
+    /// The content of files in a snapshot, relative to the world, `None` for those not in it.
+    pub fn read_files(
+        &self,
+        snapshot: &SnapshotFile,
+        paths: &[PathBuf],
+    ) -> Result<Vec<Option<Vec<u8>>>> {
+        let mut files = vec![];
+        self.for_each_files(std::slice::from_ref(snapshot), paths, |_, data| {
+            files = data
+                .into_iter()
+                .map(|x| x.map(Rc::unwrap_or_clone))
+                .collect();
+            Ok(())
+        })?;
+        Ok(files)
+    }
+
+    /// Call `f` with the index of each snapshot and the content of `paths` in it, like
+    /// `read_files`. Files unchanged since the previous snapshot are only read once.
+    pub fn for_each_files(
+        &self,
+        snapshots: &[SnapshotFile],
+        paths: &[PathBuf],
+        mut f: impl FnMut(usize, Vec<Option<Rc<Vec<u8>>>>) -> Result<()>,
+    ) -> Result<()> {
+        let repo = self
+            .open_repo()?
+            .to_indexed()
+            .inspect_err(report_err("Failed to index repo fully"))?;
+        let mut previous: HashMap<&Path, (Vec<DataId>, Rc<Vec<u8>>)> = HashMap::new();
+        for (i, snapshot) in snapshots.iter().enumerate() {
+            tasks::checkpoint()?;
+            let root = self.snapshot_root(snapshot);
+            let files = paths
+                .iter()
+                .map(|path| {
+                    // Unlike `node_from_path`, tells a missing path from a failure to read
+                    let found = repo
+                        .find_nodes_from_path([snapshot.tree], &root.join(path))
+                        .inspect_err(report_err("Failed to find node from backup storage"))?;
+                    let Some(node) = found.matches[0].map(|x| &found.nodes[x]) else {
+                        return Ok(None);
+                    };
+                    if !node.is_file() {
+                        return Ok(None);
+                    }
+                    let content = node.content.clone().unwrap_or_default();
+                    if let Some((_, data)) = previous.get(&**path).filter(|x| x.0 == content) {
+                        return Ok(Some(data.clone()));
+                    }
+                    let mut data = vec![];
+                    repo.dump(node, &mut data)
+                        .inspect_err(report_err("Failed to read file from snapshot"))?;
+                    let data = Rc::new(data);
+                    previous.insert(path, (content, data.clone()));
+                    Ok(Some(data))
+                })
+                .collect::<Result<_>>()?;
+            f(i, files)?;
+        }
+        Ok(())
+    }
+
+    /// Call `f` with the index of each snapshot, the path of each file picked by `select` that
+    /// was added or changed since the snapshot before, and its content, `None` once removed.
+    /// Unlike `for_each_files`, files are not kept in memory, to go through whole worlds.
+    pub fn for_each_changed_file(
+        &self,
+        snapshots: &[SnapshotFile],
+        select: impl Fn(&Path) -> bool,
+        mut f: impl FnMut(usize, &Path, Option<Vec<u8>>) -> Result<()>,
+    ) -> Result<()> {
+        let repo = self
+            .open_repo()?
+            .to_indexed()
+            .inspect_err(report_err("Failed to index repo fully"))?;
+        let mut previous: HashMap<PathBuf, Vec<DataId>> = HashMap::new();
+        for (i, snapshot) in snapshots.iter().enumerate() {
+            let node = repo
+                .node_from_path(snapshot.tree, &self.snapshot_root(snapshot))
+                .inspect_err(report_err("Failed to find node from backup storage"))?;
+            let mut current = HashMap::new();
+            for item in repo
+                .ls(&node, &LsOptions::default())
+                .inspect_err(report_err("Failed to open node_streamer"))?
+            {
+                let (path, node) = item.inspect_err(report_err("Failed to list snapshot"))?;
+                if !node.is_file() || !select(&path) {
+                    continue;
+                }
+                let content = node.content.clone().unwrap_or_default();
+                if previous.get(&path) != Some(&content) {
+                    tasks::checkpoint()?;
+                    let mut data = vec![];
+                    repo.dump(&node, &mut data)
+                        .inspect_err(report_err("Failed to read file from snapshot"))?;
+                    f(i, &path, Some(data))?;
+                }
+                current.insert(path, content);
+            }
+            for path in previous.keys() {
+                if !current.contains_key(path) {
+                    f(i, path, None)?;
+                }
+            }
+            previous = current;
+        }
         Ok(())
     }
 
-    fn open_repo(
+    /// How the chunks of an area changed over `snapshots`, oldest first. A chunk changed when
+    /// the time the game saved it or its content differs from the snapshot before.
+    pub fn area_history(
         &self,
-    ) -> Result<
-        Repository<
-            rustic_core::NoProgressBars,
-            rustic_core::IndexedStatus<rustic_core::IdIndex, rustic_core::OpenStatus>,
-        >,
-    > {
+        snapshots: &[SnapshotFile],
+        dimension: Dimension,
+        chunks: &[ChunkPos],
+    ) -> Result<Vec<AreaHistoryEntry>> {
+        let mut files: Vec<PathBuf> = chunks
+            .iter()
+            .map(|x| dimension.dir().join("region").join(x.region_file()))
+            .collect();
+        files.sort();
+        files.dedup();
+        let mut states: HashMap<ChunkPos, (u32, u64)> = HashMap::new();
+        let mut entries = vec![];
+        self.for_each_files(snapshots, &files, |i, data| {
+            let regions: HashMap<&PathBuf, Region> = files
+                .iter()
+                .zip(data)
+                .map(|(file, data)| {
+                    let region = data.as_deref().map(|x| Region::parse(x)).transpose()?;
+                    anyhow::Ok((file, region.unwrap_or_default()))
+                })
+                .collect::<Result<_>>()?;
+            let snapshot = &snapshots[i];
+            let mut entry = AreaHistoryEntry {
+                snapshot: snapshot.id.to_string(),
+                time: snapshot.time,
+                label: snapshot.label.clone(),
+                changed: vec![],
+                removed: vec![],
+                present: 0,
+                last_saved: None,
+            };
+            for pos in chunks {
+                let file = dimension.dir().join("region").join(pos.region_file());
+                let state = regions[&file]
+                    .get(*pos)
+                    .map(|x| (x.timestamp, x.content_hash()));
+                match (states.get(pos), state) {
+                    (Some(_), None) => entry.removed.push(*pos),
+                    (old, Some(state)) if old != Some(&state) => entry.changed.push(*pos),
+                    _ => {}
+                }
+                if let Some(state) = state {
+                    entry.present += 1;
+                    let saved = Local.timestamp_opt(state.0.into(), 0).single();
+                    entry.last_saved = entry.last_saved.max(saved);
+                    states.insert(*pos, state);
+                } else {
+                    states.remove(pos);
+                }
+            }
+            entries.push(entry);
+            Ok(())
+        })?;
+        Ok(entries)
+    }
+
+    /// Copy chunks of a dimension from a snapshot into the region files of the world, leaving
+    /// the other chunks alone. Chunks missing from the snapshot are removed, so the game
+    /// generates them again. Returns how many chunks were replaced and removed.
+    fn swap_in_chunks(
+        &self,
+        snapshot: &SnapshotFile,
+        dimension: Dimension,
+        chunks: &[ChunkPos],
+    ) -> Result<(usize, usize)> {
+        let lock = repo_lock(&self.id);
+        let _guard = lock.lock().unwrap_or_else(|e| e.into_inner());
+        let mut by_file: BTreeMap<PathBuf, Vec<ChunkPos>> = BTreeMap::new();
+        for dir in REGION_DIRS {
+            for pos in chunks {
+                by_file
+                    .entry(dimension.dir().join(dir).join(pos.region_file()))
+                    .or_default()
+                    .push(*pos);
+            }
+        }
+        let files: Vec<PathBuf> = by_file.keys().cloned().collect();
+        let old = self.read_files(snapshot, &files)?;
+
+        // Build every region file before writing any
+        let mut written = vec![];
+        let mut external = vec![];
+        let (mut replaced, mut removed) = (0, 0);
+        for ((file, chunks), old) in by_file.iter().zip(old) {
+            tasks::checkpoint()?;
+            let live_path = self.source.join(file);
+            if old.is_none() && !live_path.exists() {
+                continue;
+            }
+            let old = old
+                .as_deref()
+                .map(Region::parse)
+                .transpose()?
+                .unwrap_or_default();
+            let mut live = Region::read(&live_path)?;
+            let mut changed = false;
+            for pos in chunks {
+                let chunk = old.get(*pos).cloned();
+                if chunk.as_ref().is_some_and(Chunk::is_external) {
+                    external.push(file.with_file_name(region::external_file(*pos)));
+                }
+                let present = chunk.is_some();
+                if live.set(*pos, chunk) {
+                    changed = true;
+                    if present {
+                        replaced += 1;
+                    } else {
+                        removed += 1;
+                    }
+                }
+            }
+            if changed {
+                written.push((live_path, live));
+            }
+        }
+        let external_data = self.read_files(snapshot, &external)?;
+
+        tasks::checkpoint()?;
+        for (path, region) in written {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            region
+                .write(&path)
+                .inspect_err(|e| warn!("Failed to write {:?}: {:#}", path, e))?;
+        }
+        for (path, data) in external.iter().zip(external_data) {
+            if let Some(data) = data {
+                fs::write(self.source.join(path), data)
+                    .inspect_err(report_err("Failed to write external chunk"))?;
+            }
+        }
+        info!(
+            "restore_chunks(id={}, dimension={}, replaced={}, removed={})",
+            self.id, dimension, replaced, removed
+        );
+        Ok((replaced, removed))
+    }
+
+    /// Replace some paths of the world, relative to it, by their version in a snapshot. Every
+    /// path is restored into a staging directory before the world is touched, and the replaced
+    /// paths are deleted once all are in place.
+    fn swap_in_paths(&self, snapshot: SnapshotFile, paths: &[PathBuf]) -> Result<()> {
+        let lock = repo_lock(&self.id);
+        let _guard = lock.lock().unwrap_or_else(|e| e.into_inner());
+        let staging = self.work_dir().join("staging").join(&self.id);
+        let discarded = self.work_dir().join("discarded").join(&self.id);
+        for dir in [&staging, &discarded] {
+            if dir.exists() {
+                // Left over by a restore that crashed
+                fs::remove_dir_all(dir).inspect_err(report_err("Failed to clean up"))?;
+            }
+            fs::create_dir_all(dir).inspect_err(report_err("Failed to create work dir"))?;
+        }
+        let _staged = DirGuard::new(staging.clone());
+        let discarded_guard = DirGuard::new(discarded.clone());
+        let mut staged = vec![];
+        for (i, path) in paths.iter().enumerate() {
+            let (guard, restored) =
+                self.restore_path_into(snapshot.clone(), path, &staging.join(i.to_string()))?;
+            staged.push((guard, restored));
+        }
+
+        tasks::checkpoint()?;
+        let mut moved: Vec<(PathBuf, Option<PathBuf>)> = vec![];
+        let res =
+            paths
+                .iter()
+                .zip(&staged)
+                .enumerate()
+                .try_for_each(|(i, (path, (_, restored)))| {
+                    let live = self.source.join(path);
+                    let old = discarded.join(i.to_string());
+                    let old = if fs::symlink_metadata(&live).is_ok() {
+                        fs::rename(&live, &old)?;
+                        Some(old)
+                    } else {
+                        None
+                    };
+                    moved.push((live.clone(), old));
+                    if let Some(parent) = live.parent() {
+                        fs::create_dir_all(parent)?;
+                    }
+                    fs::rename(restored, &live)
+                });
+        if let Err(err) = res {
+            // Put the world back as it was, keeping the replaced paths if that fails
+            let discarded = discarded_guard.keep();
+            for (live, old) in moved.into_iter().rev() {
+                if fs::symlink_metadata(&live).is_ok_and(|x| x.is_dir()) {
+                    fs::remove_dir_all(&live).unwrap_or_default();
+                } else {
+                    fs::remove_file(&live).unwrap_or_default();
+                }
+                if let Some(old) = old {
+                    fs::rename(&old, &live).inspect_err(report_err("Failed to move world back"))?;
+                }
+            }
+            fs::remove_dir_all(&discarded).unwrap_or_default();
+            bail!("Failed to move restored paths into place: {}", err);
+        }
+        info!("restore_paths(id={}, paths={:?})", self.id, paths);
+        Ok(())
+    }
+
+    fn open_repo(&self) -> Result<Repo> {
         let settings = { Settings::instance().clone() };
         let backends = BackendOptions::default()
             .repo_hot(
@@ -224,6 +1614,7 @@ impl SaveBackupConfiguration {
             )
             .to_backends()
             .inspect_err(report_err("Failed to init backend"))?;
+        let backends = TaskBackend::wrap(backends, tasks::current());
         let mut repo_options = RepositoryOptions::default();
         if settings.password.is_none() && settings.password_cmd.is_none() {
             warn!("Neither password nor password command is configured");
@@ -247,9 +1638,16 @@ impl SaveBackupConfiguration {
             rustic_core::max_compression_level(),
             settings.compression_level,
         ));
-        let repo = Repository::new(&repo_options, &backends)
-            .inspect_err(report_err("Failed to create backup storage instance"))?;
-        let repo = if !self.init {
+        let repo =
+            Repository::new_with_progress(&repo_options, &backends, tasks::current_progress())
+                .inspect_err(report_err("Failed to create backup storage instance"))?;
+        // `init` may be stale when a backup was interrupted right after creating the repository
+        let exists = self.init
+            || repo
+                .config_id()
+                .inspect_err(report_err("Failed to read backup storage"))?
+                .is_some();
+        let repo = if !exists {
             info!("storage_init(id={})", self.id);
             repo.init(&key_options, &config_options)
                 .inspect_err(report_err("Failed to init backup storage"))?
@@ -260,6 +1658,82 @@ impl SaveBackupConfiguration {
         let repo = repo
             .to_indexed_ids()
             .inspect_err(report_err("Failed to index repo"))?;
+        tasks::checkpoint()?;
         Ok(repo)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn restore_leftovers_are_not_saves() {
+        assert!(is_restore_leftover(Path::new("saves/World.recover")));
+        assert!(is_restore_leftover(Path::new("saves/World.partial")));
+        assert!(!is_restore_leftover(Path::new("saves/World")));
+        assert!(!is_restore_leftover(Path::new("saves/World.v2")));
+        assert!(!is_restore_leftover(Path::new("saves/partial")));
+    }
+
+    fn globs(source: &str, settings: &Settings, excludes: &[&str]) -> Vec<String> {
+        let mut save = SaveBackupConfiguration::new(source, "id".to_string());
+        save.excludes = excludes.iter().map(|x| x.to_string()).collect();
+        save.exclude_globs(settings)
+    }
+
+    #[test]
+    fn regenerable_paths_are_excluded_by_default() {
+        let globs = globs("/home/steve/saves/World", &Settings::default(), &[]);
+        assert_eq!(globs.len(), REGENERABLE_PATHS.len());
+        assert!(globs.contains(&"!session.lock".to_string()));
+        assert!(globs.contains(&"!*.tmp".to_string()));
+        assert!(globs.contains(&"!logs/".to_string()));
+        assert!(
+            globs.contains(&"!/home/steve/saves/World/data/DistantHorizons.sqlite*".to_string())
+        );
+    }
+
+    #[test]
+    fn user_excludes_follow_gitignore() {
+        let settings = Settings {
+            include_regenerable: true,
+            excludes: vec![
+                "# old backups".to_string(),
+                " ".to_string(),
+                "backups/old/".to_string(),
+            ],
+            ..Default::default()
+        };
+        assert_eq!(
+            globs(
+                "/home/steve/saves/World",
+                &settings,
+                &["**/*.bak", "/poi/", "*.png"]
+            ),
+            [
+                "!/home/steve/saves/World/backups/old/",
+                "!**/*.bak",
+                "!/home/steve/saves/World/poi/",
+                "!*.png",
+            ]
+        );
+    }
+
+    #[test]
+    fn included_regenerable_paths_leave_user_excludes() {
+        let settings = Settings {
+            include_regenerable: true,
+            ..Default::default()
+        };
+        assert!(globs("/home/steve/saves/World", &settings, &[]).is_empty());
+        assert_eq!(
+            globs(
+                "/home/steve/saves/World [1]",
+                &settings,
+                &["stats/old.json"]
+            ),
+            ["!/home/steve/saves/World [[]1[]]/stats/old.json"]
+        );
+    }
+}
@@ -0,0 +1,261 @@
+use std::collections::BTreeMap;
+
+use anyhow::{Result, anyhow, bail};
+
+use crate::nbt::Tag;
+
+/// Block states that are empty space.
+const AIR: &[&str] = &["minecraft:air", "minecraft:cave_air", "minecraft:void_air"];
+
+/// First data version whose block states do not span two longs (20w17a).
+const NON_SPANNING_VERSION: i64 = 2529;
+
+/// Blocks in a section
+const SECTION_BLOCKS: usize = 4096;
+
+/// A 16×16×16 cube of blocks, as indices into its palette of block states, ordered by y, then
+/// z, then x.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Section {
+    /// Block states like `minecraft:oak_door[facing=north,open=true]`
+    pub palette: Vec<String>,
+    pub blocks: Vec<u16>,
+}
+
+impl Section {
+    pub fn state(&self, index: usize) -> &str {
+        &self.palette[self.blocks[index] as usize]
+    }
+}
+
+/// What a chunk holds, decoded from its NBT, for the anvil format of 1.13 and later.
+#[derive(Debug, Clone, Default)]
+pub struct ChunkContent {
+    /// By height of the section
+    pub sections: BTreeMap<i32, Section>,
+    pub block_entities: Vec<Tag>,
+    /// Only kept in the chunk before 1.17, later versions use `entities/` region files
+    pub entities: Vec<Tag>,
+}
+
+impl ChunkContent {
+    pub fn from_nbt(root: &Tag) -> Result<Self> {
+        let version = root.get("DataVersion").and_then(Tag::as_i64).unwrap_or(0);
+        // Chunks were wrapped in a `Level` compound, with capitalized names, until 1.18
+        let (level, sections_key, entities_key) = match root.get("Level") {
+            Some(level) => (level, "Sections", "TileEntities"),
+            None => (root, "sections", "block_entities"),
+        };
+        let mut sections = BTreeMap::new();
+        for section in list(level, sections_key) {
+            let y = section
+                .get("Y")
+                .and_then(Tag::as_i64)
+                .ok_or(anyhow!("Section without height"))?;
+            let (palette, data, spanning) = match section.get("block_states") {
+                Some(states) => (list(states, "palette"), states.get("data"), false),
+                None => (
+                    list(section, "Palette"),
+                    section.get("BlockStates"),
+                    version < NON_SPANNING_VERSION,
+                ),
+            };
+            // Sections of light only
+            if palette.is_empty() {
+                continue;
+            }
+            let palette: Vec<String> = palette.iter().map(state_string).collect();
+            let blocks = match data {
+                Some(Tag::LongArray(data)) if palette.len() > 1 => {
+                    unpack(data, palette.len(), spanning)?
+                }
+                _ => vec![0; SECTION_BLOCKS],
+            };
+            sections.insert(y as i32, Section { palette, blocks });
+        }
+        Ok(Self {
+            sections,
+            block_entities: list(level, entities_key).to_vec(),
+            entities: list(level, "Entities").to_vec(),
+        })
+    }
+}
+
+/// Entities of a chunk from an `entities/` region file.
+pub fn entities(root: &Tag) -> Vec<Tag> {
+    list(root, "Entities").to_vec()
+}
+
+/// Block entities of a chunk from a `region/` file, without decoding its blocks.
+pub fn block_entities(root: &Tag) -> &[Tag] {
+    match root.get("Level") {
+        Some(level) => list(level, "TileEntities"),
+        None => list(root, "block_entities"),
+    }
+}
+
+fn list<'a>(tag: &'a Tag, key: &str) -> &'a [Tag] {
+    tag.get(key).and_then(Tag::as_list).unwrap_or_default()
+}
+
+/// `name[key=value,...]` of a palette entry.
+fn state_string(entry: &Tag) -> String {
+    let name = entry
+        .get("Name")
+        .and_then(Tag::as_str)
+        .unwrap_or("minecraft:air");
+    match entry.get("Properties") {
+        Some(Tag::Compound(properties)) if !properties.is_empty() => {
+            let properties: Vec<String> = properties
+                .iter()
+                .map(|(key, value)| format!("{}={}", key, value.as_str().unwrap_or_default()))
+                .collect();
+            format!("{}[{}]", name, properties.join(","))
+        }
+        _ => name.to_string(),
+    }
+}
+
+/// Palette indices packed into longs, with at least 4 bits per block. Before 20w17a an index
+/// could continue in the next long.
+fn unpack(data: &[i64], palette_len: usize, spanning: bool) -> Result<Vec<u16>> {
+    let bits = (usize::BITS - (palette_len - 1).leading_zeros()).max(4) as usize;
+    let mask = (1u64 << bits) - 1;
+    let per_long = 64 / bits;
+    let needed = if spanning {
+        (SECTION_BLOCKS * bits).div_ceil(64)
+    } else {
+        SECTION_BLOCKS.div_ceil(per_long)
+    };
+    if data.len() < needed {
+        bail!(
+            "Section has {} longs of block states, not {}",
+            data.len(),
+            needed
+        );
+    }
+    (0..SECTION_BLOCKS)
+        .map(|i| {
+            let value = if spanning {
+                let bit = i * bits;
+                let (long, offset) = (bit / 64, bit % 64);
+                let mut value = data[long] as u64 >> offset;
+                if offset + bits > 64 {
+                    value |= (data[long + 1] as u64) << (64 - offset);
+                }
+                value & mask
+            } else {
+                (data[i / per_long] as u64 >> (i % per_long * bits)) & mask
+            };
+            if value as usize >= palette_len {
+                bail!("Block state {} is not in the palette", value);
+            }
+            Ok(value as u16)
+        })
+        .collect()
+}
+
+/// The block of a state, without its properties.
+pub fn block_name(state: &str) -> &str {
+    state.split('[').next().unwrap_or(state)
+}
+
+pub fn is_air(state: &str) -> bool {
+    AIR.contains(&block_name(state))
+}
+
+/// The `id` of a block entity or entity.
+pub fn id(tag: &Tag) -> &str {
+    tag.get("id").and_then(Tag::as_str).unwrap_or("unknown")
+}
+
+pub fn block_entity_pos(tag: &Tag) -> Option<(i64, i64, i64)> {
+    let coord = |key| tag.get(key).and_then(Tag::as_i64);
+    Some((coord("x")?, coord("y")?, coord("z")?))
+}
+
+/// The UUID of an entity, as an int array since 1.16 and as two longs before.
+pub fn entity_uuid(tag: &Tag) -> Option<String> {
+    match tag.get("UUID") {
+        Some(Tag::IntArray(x)) => Some(
+            x.iter()
+                .map(|x| format!("{:08x}", *x as u32))
+                .collect::<String>(),
+        ),
+        _ => {
+            let most = tag.get("UUIDMost").and_then(Tag::as_i64)?;
+            let least = tag.get("UUIDLeast").and_then(Tag::as_i64)?;
+            Some(format!("{:016x}{:016x}", most as u64, least as u64))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pack(values: &[u16], bits: usize, spanning: bool) -> Vec<i64> {
+        let mut data = vec![];
+        for (i, value) in values.iter().map(|x| *x as u64).enumerate() {
+            let (long, offset) = if spanning {
+                (i * bits / 64, i * bits % 64)
+            } else {
+                (i / (64 / bits), i % (64 / bits) * bits)
+            };
+            data.resize(data.len().max(long + 2), 0u64);
+            data[long] |= value << offset;
+            if offset + bits > 64 {
+                data[long + 1] |= value >> (64 - offset);
+            }
+        }
+        let len = if spanning {
+            (values.len() * bits).div_ceil(64)
+        } else {
+            values.len().div_ceil(64 / bits)
+        };
+        data.truncate(len);
+        data.into_iter().map(|x| x as i64).collect()
+    }
+
+    fn blocks(palette_len: usize) -> Vec<u16> {
+        (0..SECTION_BLOCKS)
+            .map(|x| (x * 7 % palette_len) as u16)
+            .collect()
+    }
+
+    #[test]
+    fn unpacks_at_least_four_bits() {
+        let values = blocks(2);
+        let data = pack(&values, 4, false);
+        assert_eq!(data.len(), 256);
+        assert_eq!(unpack(&data, 2, false).unwrap(), values);
+        assert_eq!(unpack(&data, 2, true).unwrap(), values);
+    }
+
+    #[test]
+    fn unpacks_non_spanning_longs() {
+        // 6 bits leave 4 unused at the end of each long
+        let values = blocks(33);
+        let data = pack(&values, 6, false);
+        assert_eq!(data.len(), 410);
+        assert_eq!(unpack(&data, 33, false).unwrap(), values);
+    }
+
+    #[test]
+    fn unpacks_spanning_longs() {
+        let values = blocks(33);
+        let data = pack(&values, 6, true);
+        assert_eq!(data.len(), 384);
+        assert_eq!(unpack(&data, 33, true).unwrap(), values);
+        assert!(unpack(&data, 33, false).is_err());
+    }
+
+    #[test]
+    fn refuses_missing_longs_and_states() {
+        let mut values = blocks(17);
+        let data = pack(&values, 5, true);
+        assert!(unpack(&data[..data.len() - 1], 17, true).is_err());
+        values[100] = 20;
+        assert!(unpack(&pack(&values, 5, true), 17, true).is_err());
+    }
+}
@@ -0,0 +1,1033 @@
+use std::{
+    io::{self, IsTerminal},
+    path::PathBuf,
+    process::ExitCode,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread,
+    time::Duration,
+};
+
+use anyhow::{Result, bail};
+use clap::{Parser, Subcommand};
+use rustic_core::SnapshotOptions;
+
+use crate::{
+    backup::AppState,
+    cli::report::{
+        AreaHistory, BlockChanges, ChunksRestored, ExcludeList, Failure, FileDiff, ForgetResult,
+        ForgetResults, GcResult, GcResults, ItemsFound, Outcome, PlayerRestored, PlayerRoster,
+        Restored, RetentionInfo, RosterHistory, SaveList, ScheduleList, SnapshotList, SnapshotTree,
+        TaskResult, TaskResults, TaskStatus, TrashEntry, TrashList, print,
+    },
+    diff,
+    items::{self, ItemQuery},
+    players,
+    region::{Area, ChunkPos, Dimension},
+    scheduler,
+    settings::RetentionPolicy,
+    tasks::{self, TaskInfo},
+    utils::report_err,
+};
+
+mod report;
+
+#[derive(Debug, Parser, Clone)]
+#[command(version, about)]
+pub struct Cli {
+    /// Print results as JSON documents
+    #[arg(long, global = true)]
+    pub json: bool,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand, Clone)]
+pub enum Command {
+    /// Back up all saves periodically
+    Daemon,
+    /// List all detected saves, and the archived ones
+    List,
+    /// Back up a save, or every save if none is given
+    Backup {
+        /// Id or name of the save
+        save: Option<String>,
+        #[arg(short, long)]
+        label: Option<String>,
+        #[arg(short, long)]
+        description: Option<String>,
+    },
+    /// List the snapshots of a save
+    Snapshots {
+        /// Id or name of the save
+        save: String,
+    },
+    /// Restore a snapshot into `<save>.recover`, in place of the world or into a new directory
+    Restore {
+        /// Id or name of the save
+        save: String,
+        /// Snapshot id (or a unique prefix of it), or `latest`
+        snapshot: String,
+        /// Replace the world, after taking a pre-restore snapshot of it. The replaced world is
+        /// kept until `minesave trash --empty`
+        #[arg(long)]
+        in_place: bool,
+        /// Restore into this new directory instead, which also works for archived saves
+        #[arg(long, value_name = "DIR", conflicts_with = "in_place")]
+        to: Option<PathBuf>,
+        /// Restore next to the world as a new save, renamed after the date of the snapshot
+        #[arg(long, conflicts_with_all = ["in_place", "to"])]
+        copy: bool,
+        /// Only replace this file or directory of the world, relative to it, after taking a
+        /// pre-restore snapshot. Can be repeated
+        #[arg(long, value_name = "PATH", conflicts_with_all = ["in_place", "to", "copy"])]
+        path: Vec<PathBuf>,
+    },
+    /// Copy chunks from a snapshot into the world, leaving the other chunks alone
+    RestoreChunks {
+        /// Id or name of the save
+        save: String,
+        /// Snapshot id (or a unique prefix of it), or `latest`
+        snapshot: String,
+        /// Every chunk touched by this rectangle of blocks, given as `x1,z1:x2,z2`
+        #[arg(long, required_unless_present = "chunk")]
+        area: Option<Area>,
+        /// A chunk, in chunk coordinates `x,z`. Can be repeated
+        #[arg(long)]
+        chunk: Vec<ChunkPos>,
+        #[arg(long, default_value = "overworld")]
+        dim: Dimension,
+    },
+    /// Put back the inventory, advancements and statistics of one player from a snapshot,
+    /// after taking a pre-restore snapshot. The other players keep their progress
+    RestorePlayer {
+        /// Id or name of the save
+        save: String,
+        /// Snapshot id (or a unique prefix of it), or `latest`
+        snapshot: String,
+        /// UUID of the player, or their name as found in `usercache.json`
+        player: String,
+    },
+    /// Show which snapshots changed an area, to find the last one before some damage
+    ChunkHistory {
+        /// Id or name of the save
+        save: String,
+        /// Every chunk touched by this rectangle of blocks, given as `x1,z1:x2,z2`
+        #[arg(long, required_unless_present = "chunk")]
+        area: Option<Area>,
+        /// A chunk, in chunk coordinates `x,z`. Can be repeated
+        #[arg(long)]
+        chunk: Vec<ChunkPos>,
+        #[arg(long, default_value = "overworld")]
+        dim: Dimension,
+    },
+    /// Show the files added, removed and modified between two snapshots, or since a snapshot
+    Diff {
+        /// Id or name of the save
+        save: String,
+        /// The older snapshot
+        from: String,
+        /// The newer snapshot
+        #[arg(required_unless_present = "live")]
+        to: Option<String>,
+        /// Compare with the world as it is now
+        #[arg(long, conflicts_with = "to")]
+        live: bool,
+    },
+    /// Show the blocks, block entities and entities that changed between two snapshots
+    DiffBlocks {
+        /// Id or name of the save
+        save: String,
+        /// The older snapshot
+        from: String,
+        /// The newer snapshot
+        to: String,
+        /// Only compare this dimension. Can be repeated, all are compared if omitted
+        #[arg(long)]
+        dim: Vec<Dimension>,
+        /// Only compare the chunks touched by this rectangle of blocks, given as `x1,z1:x2,z2`
+        #[arg(long)]
+        area: Option<Area>,
+        /// Only compare this chunk, in chunk coordinates `x,z`. Can be repeated
+        #[arg(long)]
+        chunk: Vec<ChunkPos>,
+        /// Print one line per chunk and type as CSV
+        #[arg(long, conflicts_with = "json")]
+        csv: bool,
+    },
+    /// List the players of a world with when they were last seen, their play time and position
+    Players {
+        /// Id or name of the save
+        save: String,
+        /// The players in this snapshot instead of the world as it is now
+        #[arg(long)]
+        snapshot: Option<String>,
+        /// The players in every snapshot, oldest first, to see how they changed
+        #[arg(long, conflicts_with = "snapshot")]
+        history: bool,
+    },
+    /// Look for an item in the player inventories, ender chests and containers of every
+    /// snapshot, to find the last one still holding it
+    FindItem {
+        /// Id or name of the save
+        save: String,
+        /// Item id, like `elytra` or `minecraft:elytra`
+        item: String,
+        /// Only count items whose custom name contains this, in any case
+        #[arg(long)]
+        name: Option<String>,
+        /// Only count items with this enchantment, like `mending`
+        #[arg(long)]
+        enchantment: Option<String>,
+    },
+    /// List the files and directories of a snapshot
+    Ls {
+        /// Id or name of the save
+        save: String,
+        /// Snapshot id (or a unique prefix of it), or `latest`
+        snapshot: String,
+        /// Directory to list, relative to the world
+        dir: Option<PathBuf>,
+        /// List everything below the directory too
+        #[arg(short, long)]
+        recursive: bool,
+    },
+    /// Add a save copied by `minesave gc --export`, as an archived save
+    Import {
+        /// The `minesave-<id>` directory of the copy
+        dir: PathBuf,
+    },
+    /// List the saves whose world vanished, and archive, export or delete their history
+    Gc {
+        /// Id or name of the save, every orphaned save is processed if omitted
+        save: Option<String>,
+        /// Keep the history as an archived save, which can still be restored
+        #[arg(long, conflicts_with = "delete")]
+        archive: bool,
+        /// Copy the repositories into this directory
+        #[arg(long, value_name = "DIR")]
+        export: Option<PathBuf>,
+        /// Delete the repositories, after exporting them if `--export` is given
+        #[arg(long, requires = "yes")]
+        delete: bool,
+        /// Confirm `--delete`
+        #[arg(long)]
+        yes: bool,
+    },
+    /// Show the worlds replaced by in-place restores, and delete or put them back
+    Trash {
+        /// Id or name of the save, all saves are processed if omitted
+        save: Option<String>,
+        /// Delete the replaced worlds, confirming the restores
+        #[arg(long)]
+        empty: bool,
+        /// Put back the world replaced by the last in-place restore
+        #[arg(long, requires = "save", conflicts_with = "empty")]
+        undo: bool,
+    },
+    /// Show when saves are backed up by the daemon, or change it for one save
+    Schedule {
+        /// Id or name of the save, all saves are shown if omitted
+        save: Option<String>,
+        /// Back up every given number of seconds
+        #[arg(long, requires = "save", conflicts_with = "cron")]
+        interval: Option<u32>,
+        /// Back up at the times matched by a cron expression
+        #[arg(long, requires = "save")]
+        cron: Option<String>,
+        /// Use the global schedule again
+        #[arg(long, requires = "save", conflicts_with_all = ["interval", "cron"])]
+        reset: bool,
+    },
+    /// Forget the snapshots not kept by the retention policy
+    Forget {
+        /// Id or name of the save, all saves are processed if omitted
+        save: Option<String>,
+        /// Also remove the data no longer used by any snapshot
+        #[arg(long)]
+        prune: bool,
+        /// Only list the snapshots that would be forgotten
+        #[arg(long, conflicts_with = "prune")]
+        dry_run: bool,
+    },
+    /// Show the retention policy of a save, or override it with the given rules
+    Retention {
+        /// Id or name of the save
+        save: String,
+        #[command(flatten)]
+        policy: RetentionPolicy,
+        /// Use the global retention policy again
+        #[arg(long)]
+        reset: bool,
+    },
+    /// Show the paths excluded from the backups of a save, or change its own patterns
+    Excludes {
+        /// Id or name of the save
+        save: String,
+        /// Glob or gitignore-style patterns to add
+        #[arg(long)]
+        add: Vec<String>,
+        /// Patterns to remove
+        #[arg(long)]
+        remove: Vec<String>,
+    },
+}
+
+impl Command {
+    pub fn run(self, json: bool) -> ExitCode {
+        if !matches!(self, Command::Daemon) {
+            ctrlc::set_handler(|| {
+                static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+                if INTERRUPTED.swap(true, Ordering::AcqRel) {
+                    std::process::exit(130);
+                }
+                eprintln!("\nCancelling, press Ctrl-C again to quit immediately");
+                tasks::cancel_all();
+            })
+            .inspect_err(report_err("Failed to set Ctrl-C handler"))
+            .unwrap_or_default();
+        }
+        match self.execute(json) {
+            Ok(outcome) => outcome.into(),
+            Err(err) => {
+                print(
+                    &Failure {
+                        error: format!("{:#}", err),
+                    },
+                    json,
+                );
+                Outcome::Fatal.into()
+            }
+        }
+    }
+
+    fn execute(self, json: bool) -> Result<Outcome> {
+        match self {
+            Command::Daemon => crate::daemon(),
+            Command::List => {
+                let instance = AppState::instance();
+                let mut saves: Vec<_> = instance
+                    .saves
+                    .values()
+                    .filter(|x| x.archived || instance.is_present(x))
+                    .collect();
+                saves.sort_by(|a, b| a.name.cmp(&b.name));
+                print(
+                    &SaveList {
+                        saves: saves.into_iter().map(Into::into).collect(),
+                    },
+                    json,
+                );
+            }
+            Command::Backup {
+                save,
+                label,
+                description,
+            } => {
+                let options = SnapshotOptions::default()
+                    .label(label)
+                    .description(description);
+                // Saves backed up together are only snapshotted when they changed
+                let (for_id, ids, skip_unchanged) = {
+                    let instance = AppState::instance();
+                    match save {
+                        Some(key) => {
+                            let id = instance.find_id(&key)?;
+                            (Some(id.clone()), vec![id], false)
+                        }
+                        None => (None, instance.present_ids(), true),
+                    }
+                };
+                let mut results: Vec<_> = with_progress(
+                    json,
+                    t!("pages.saves.backup").to_string(),
+                    TaskInfo::Backup {
+                        for_id,
+                        options: options.clone(),
+                    },
+                    || Ok(tasks::backup_many(ids, options.clone(), skip_unchanged)),
+                )?
+                .into_iter()
+                .map(|(id, res)| {
+                    let (status, snapshot, error) = match res {
+                        Ok(Some(snapshot)) => {
+                            (TaskStatus::Succeeded, Some(snapshot.id.to_string()), None)
+                        }
+                        Ok(None) => (TaskStatus::Unchanged, None, None),
+                        Err(err) => (TaskStatus::of(&err), None, Some(format!("{:#}", err))),
+                    };
+                    TaskResult {
+                        name: AppState::instance().saves[&id].name.clone(),
+                        save: id,
+                        status,
+                        snapshot,
+                        error,
+                    }
+                })
+                .collect();
+                results.sort_by(|a, b| a.name.cmp(&b.name));
+                let results = TaskResults { results };
+                print(&results, json);
+                return Ok(results.outcome());
+            }
+            Command::Snapshots { save } => {
+                let save = {
+                    let instance = AppState::instance();
+                    instance.saves[&instance.find_id(&save)?].clone()
+                };
+                print(
+                    &SnapshotList {
+                        save: save.id().to_string(),
+                        snapshots: save.list_backups()?.iter().map(Into::into).collect(),
+                    },
+                    json,
+                );
+            }
+            Command::Restore {
+                save,
+                snapshot,
+                in_place: _,
+                to: Some(destination),
+                copy: _,
+                path: _,
+            } => {
+                let save = {
+                    let instance = AppState::instance();
+                    instance.saves[&instance.find_id(&save)?].clone()
+                };
+                let id = save.id().to_string();
+                let snapshot = save.find_snapshot(&snapshot)?;
+                let snapshot_id = snapshot.id.to_string();
+                with_progress(
+                    json,
+                    format!("{}: {}", t!("pages.saves.recover"), save.name),
+                    TaskInfo::RestoreTo {
+                        id: id.clone(),
+                        snapshot: Box::new(snapshot.clone()),
+                        destination: destination.clone(),
+                    },
+                    || save.restore_to(snapshot, &destination),
+                )?;
+                print(
+                    &Restored {
+                        save: id,
+                        snapshot: snapshot_id,
+                        destination,
+                        pre_restore_snapshot: None,
+                        replaced: None,
+                    },
+                    json,
+                );
+            }
+            Command::Restore {
+                save,
+                snapshot,
+                in_place: _,
+                to: None,
+                copy: true,
+                path: _,
+            } => {
+                let save = {
+                    let instance = AppState::instance();
+                    instance.saves[&instance.find_id(&save)?].clone()
+                };
+                let id = save.id().to_string();
+                let snapshot = save.find_snapshot(&snapshot)?;
+                let snapshot_id = snapshot.id.to_string();
+                let copy = with_progress(
+                    json,
+                    format!("{}: {}", t!("pages.saves.recover"), save.name),
+                    TaskInfo::RestoreCopy {
+                        id: id.clone(),
+                        snapshot: Box::new(snapshot.clone()),
+                    },
+                    || AppState::restore_as_copy(&id, snapshot),
+                )?;
+                print(
+                    &Restored {
+                        save: copy.clone(),
+                        snapshot: snapshot_id,
+                        destination: AppState::instance().saves[&copy].source().to_path_buf(),
+                        pre_restore_snapshot: None,
+                        replaced: None,
+                    },
+                    json,
+                );
+            }
+            Command::Restore {
+                save,
+                snapshot,
+                in_place: _,
+                to: None,
+                copy: false,
+                path: paths,
+            } if !paths.is_empty() => {
+                let save = {
+                    let instance = AppState::instance();
+                    instance.saves[&instance.find_id(&save)?].clone()
+                };
+                let id = save.id().to_string();
+                let snapshot = save.find_snapshot(&snapshot)?;
+                let snapshot_id = snapshot.id.to_string();
+                let pre_restore = with_progress(
+                    json,
+                    format!("{}: {}", t!("pages.saves.recover"), save.name),
+                    TaskInfo::RestorePaths {
+                        id: id.clone(),
+                        snapshot: Box::new(snapshot.clone()),
+                        paths: paths.clone(),
+                    },
+                    || AppState::restore_paths(&id, snapshot, &paths),
+                )?;
+                print(
+                    &Restored {
+                        save: id,
+                        snapshot: snapshot_id,
+                        destination: save.source().to_path_buf(),
+                        pre_restore_snapshot: pre_restore.map(|x| x.id.to_string()),
+                        replaced: None,
+                    },
+                    json,
+                );
+            }
+            Command::Restore {
+                save,
+                snapshot,
+                in_place,
+                to: None,
+                copy: false,
+                path: _,
+            } => {
+                let save = {
+                    let instance = AppState::instance();
+                    instance.saves[&instance.find_id(&save)?].clone()
+                };
+                let id = save.id().to_string();
+                if save.archived {
+                    bail!("{} is archived, restore it with --to", save.name);
+                }
+                let snapshot = save.find_snapshot(&snapshot)?;
+                let snapshot_id = snapshot.id.to_string();
+                let pre_restore = with_progress(
+                    json,
+                    format!("{}: {}", t!("pages.saves.recover"), save.name),
+                    TaskInfo::Recover {
+                        id: id.clone(),
+                        snapshot: Box::new(snapshot.clone()),
+                        in_place,
+                    },
+                    || {
+                        if in_place {
+                            AppState::restore_in_place(&id, snapshot)
+                        } else {
+                            save.recover(snapshot).map(|()| None)
+                        }
+                    },
+                )?;
+                print(
+                    &Restored {
+                        save: id.clone(),
+                        snapshot: snapshot_id,
+                        destination: if in_place {
+                            save.source().to_path_buf()
+                        } else {
+                            save.source().with_added_extension("recover")
+                        },
+                        pre_restore_snapshot: pre_restore.map(|x| x.id.to_string()),
+                        replaced: in_place
+                            .then(|| AppState::instance().saves[&id].trash.last().cloned())
+                            .flatten(),
+                    },
+                    json,
+                );
+            }
+            Command::RestoreChunks {
+                save,
+                snapshot,
+                area,
+                mut chunk,
+                dim,
+            } => {
+                let save = {
+                    let instance = AppState::instance();
+                    instance.saves[&instance.find_id(&save)?].clone()
+                };
+                let id = save.id().to_string();
+                let snapshot = save.find_snapshot(&snapshot)?;
+                let snapshot_id = snapshot.id.to_string();
+                chunk.extend(area.map(|x| x.chunks()).unwrap_or_default());
+                chunk.sort();
+                chunk.dedup();
+                let (pre_restore, replaced, removed) = with_progress(
+                    json,
+                    format!("{}: {}", t!("pages.saves.recover"), save.name),
+                    TaskInfo::RestoreChunks {
+                        id: id.clone(),
+                        snapshot: Box::new(snapshot.clone()),
+                        dimension: dim,
+                        chunks: chunk.clone(),
+                    },
+                    || AppState::restore_chunks(&id, snapshot, dim, &chunk),
+                )?;
+                print(
+                    &ChunksRestored {
+                        save: id,
+                        snapshot: snapshot_id,
+                        dimension: dim,
+                        replaced,
+                        removed,
+                        pre_restore_snapshot: pre_restore.map(|x| x.id.to_string()),
+                    },
+                    json,
+                );
+            }
+            Command::RestorePlayer {
+                save,
+                snapshot,
+                player,
+            } => {
+                let save = {
+                    let instance = AppState::instance();
+                    instance.saves[&instance.find_id(&save)?].clone()
+                };
+                let id = save.id().to_string();
+                let uuid = players::resolve(save.source(), &player)?;
+                let snapshot = save.find_snapshot(&snapshot)?;
+                let snapshot_id = snapshot.id.to_string();
+                let (pre_restore, paths) = with_progress(
+                    json,
+                    format!("{}: {}", t!("pages.saves.recover"), save.name),
+                    TaskInfo::RestorePlayer {
+                        id: id.clone(),
+                        snapshot: Box::new(snapshot.clone()),
+                        player: uuid.clone(),
+                    },
+                    || AppState::restore_player(&id, snapshot, &uuid),
+                )?;
+                print(
+                    &PlayerRestored {
+                        save: id,
+                        snapshot: snapshot_id,
+                        player: uuid,
+                        paths,
+                        pre_restore_snapshot: pre_restore.map(|x| x.id.to_string()),
+                    },
+                    json,
+                );
+            }
+            Command::ChunkHistory {
+                save,
+                area,
+                mut chunk,
+                dim,
+            } => {
+                let save = {
+                    let instance = AppState::instance();
+                    instance.saves[&instance.find_id(&save)?].clone()
+                };
+                chunk.extend(area.map(|x| x.chunks()).unwrap_or_default());
+                chunk.sort();
+                chunk.dedup();
+                let mut snapshots = save.list_backups()?;
+                snapshots.sort_by_key(|x| x.time);
+                print(
+                    &AreaHistory {
+                        save: save.id().to_string(),
+                        dimension: dim,
+                        chunks: chunk.len(),
+                        entries: save.area_history(&snapshots, dim, &chunk)?,
+                    },
+                    json,
+                );
+            }
+            Command::Diff {
+                save,
+                from,
+                to,
+                live: _,
+            } => {
+                let save = {
+                    let instance = AppState::instance();
+                    instance.saves[&instance.find_id(&save)?].clone()
+                };
+                let from = save.find_snapshot(&from)?;
+                let (to, changes) = match to {
+                    Some(to) => {
+                        let to = save.find_snapshot(&to)?;
+                        let changes = save.changed_files(&from, &to)?;
+                        (Some(to.id.to_string()), changes)
+                    }
+                    None => (None, save.changed_files_live(&from)?),
+                };
+                print(
+                    &FileDiff {
+                        save: save.id().to_string(),
+                        from: from.id.to_string(),
+                        to,
+                        changes,
+                    },
+                    json,
+                );
+            }
+            Command::DiffBlocks {
+                save,
+                from,
+                to,
+                mut dim,
+                area,
+                mut chunk,
+                csv,
+            } => {
+                let save = {
+                    let instance = AppState::instance();
+                    instance.saves[&instance.find_id(&save)?].clone()
+                };
+                let from = save.find_snapshot(&from)?;
+                let to = save.find_snapshot(&to)?;
+                if dim.is_empty() {
+                    dim = Dimension::ALL.to_vec();
+                }
+                chunk.extend(area.map(|x| x.chunks()).unwrap_or_default());
+                let area = (!chunk.is_empty()).then_some(&*chunk);
+                let changes = BlockChanges {
+                    save: save.id().to_string(),
+                    from: from.id.to_string(),
+                    to: to.id.to_string(),
+                    diff: diff::diff_blocks(&save, &from, &to, &dim, area)?,
+                };
+                if csv {
+                    changes.print_csv();
+                } else {
+                    print(&changes, json);
+                }
+            }
+            Command::Players {
+                save,
+                snapshot,
+                history,
+            } => {
+                let save = {
+                    let instance = AppState::instance();
+                    instance.saves[&instance.find_id(&save)?].clone()
+                };
+                if history {
+                    let mut snapshots = save.list_backups()?;
+                    snapshots.sort_by_key(|x| x.time);
+                    print(
+                        &RosterHistory {
+                            save: save.id().to_string(),
+                            snapshots: players::snapshot_rosters(&save, &snapshots)?,
+                        },
+                        json,
+                    );
+                } else {
+                    let (snapshot, players) = match snapshot {
+                        Some(snapshot) => {
+                            let snapshot = save.find_snapshot(&snapshot)?;
+                            let roster = players::snapshot_rosters(&save, &[snapshot])?;
+                            let roster =
+                                roster.into_iter().next().expect("One roster per snapshot");
+                            (Some(roster.snapshot), roster.players)
+                        }
+                        None => (None, players::roster(save.source())?),
+                    };
+                    print(
+                        &PlayerRoster {
+                            save: save.id().to_string(),
+                            snapshot,
+                            players,
+                        },
+                        json,
+                    );
+                }
+            }
+            Command::FindItem {
+                save,
+                item,
+                name,
+                enchantment,
+            } => {
+                let save = {
+                    let instance = AppState::instance();
+                    instance.saves[&instance.find_id(&save)?].clone()
+                };
+                let query = ItemQuery::new(&item, name.as_deref(), enchantment.as_deref());
+                let mut snapshots = save.list_backups()?;
+                snapshots.sort_by_key(|x| x.time);
+                print(
+                    &ItemsFound {
+                        save: save.id().to_string(),
+                        item: query.id.clone(),
+                        search: items::search_items(&save, &snapshots, &query)?,
+                    },
+                    json,
+                );
+            }
+            Command::Ls {
+                save,
+                snapshot,
+                dir,
+                recursive,
+            } => {
+                let save = {
+                    let instance = AppState::instance();
+                    instance.saves[&instance.find_id(&save)?].clone()
+                };
+                let snapshot = save.find_snapshot(&snapshot)?;
+                let dir = dir.unwrap_or_default();
+                let entries = save
+                    .list_snapshot(&snapshot)?
+                    .into_iter()
+                    .filter(|x| {
+                        if recursive {
+                            x.path.starts_with(&dir) && x.path != dir
+                        } else {
+                            x.path.parent() == Some(&dir)
+                        }
+                    })
+                    .collect();
+                print(
+                    &SnapshotTree {
+                        snapshot: snapshot.id.to_string(),
+                        entries,
+                    },
+                    json,
+                );
+            }
+            Command::Import { dir } => {
+                let id = AppState::import(&dir)?;
+                let entry = (&AppState::instance().saves[&id]).into();
+                print(&SaveList { saves: vec![entry] }, json);
+            }
+            Command::Gc {
+                save,
+                archive,
+                export,
+                delete,
+                yes: _,
+            } => {
+                let saves: Vec<_> = {
+                    let instance = AppState::instance();
+                    match save {
+                        Some(key) => {
+                            let save = instance.saves[&instance.find_id(&key)?].clone();
+                            if instance.is_present(&save) {
+                                bail!("{} still exists", save.name);
+                            }
+                            vec![save]
+                        }
+                        None => instance.orphaned().into_iter().cloned().collect(),
+                    }
+                };
+                let mut results = vec![];
+                for save in saves {
+                    let id = save.id().to_string();
+                    let mut result = GcResult {
+                        save: id.clone(),
+                        name: save.name.clone(),
+                        source: save.source().to_path_buf(),
+                        archived: save.archived,
+                        exported: None,
+                        deleted: false,
+                        error: None,
+                    };
+                    let res = (|| {
+                        if archive {
+                            AppState::instance().archive(&id)?;
+                            result.archived = true;
+                        }
+                        if let Some(destination) = &export {
+                            result.exported = Some(AppState::export(&id, destination)?);
+                        }
+                        if delete {
+                            AppState::instance().delete_save(&id)?;
+                            result.deleted = true;
+                        }
+                        anyhow::Ok(())
+                    })();
+                    result.error = res.err().map(|x| format!("{:#}", x));
+                    results.push(result);
+                }
+                let results = GcResults { results };
+                print(&results, json);
+                return Ok(results.outcome());
+            }
+            Command::Trash { save, empty, undo } => {
+                let ids = {
+                    let instance = AppState::instance();
+                    match save {
+                        Some(key) => vec![instance.find_id(&key)?],
+                        None => instance.saves.keys().cloned().collect(),
+                    }
+                };
+                let mut saves = vec![];
+                for id in ids {
+                    let removed = if empty {
+                        AppState::empty_trash(&id)?
+                    } else {
+                        vec![]
+                    };
+                    let put_back = if undo {
+                        Some(AppState::undo_restore(&id)?)
+                    } else {
+                        None
+                    };
+                    let save = AppState::instance().saves[&id].clone();
+                    saves.push(TrashEntry {
+                        save: id,
+                        name: save.name,
+                        trash: save.trash,
+                        removed,
+                        put_back,
+                    });
+                }
+                saves.sort_by(|a, b| a.name.cmp(&b.name));
+                print(&TrashList { saves }, json);
+            }
+            Command::Schedule {
+                save,
+                interval,
+                cron,
+                reset,
+            } => {
+                let mut instance = AppState::instance();
+                let ids = match save {
+                    Some(key) => vec![instance.find_id(&key)?],
+                    None => instance.saves.keys().cloned().collect(),
+                };
+                if let Some(expr) = &cron {
+                    scheduler::Schedule::cron(expr)?;
+                }
+                if reset || interval.is_some() || cron.is_some() {
+                    for id in &ids {
+                        let save = instance.saves.get_mut(id).unwrap();
+                        save.backup_duration = interval;
+                        save.backup_cron = cron.clone();
+                    }
+                    instance.save()?;
+                }
+                let mut saves: Vec<_> = ids.iter().map(|x| &instance.saves[x]).collect();
+                saves.sort_by(|a, b| a.name.cmp(&b.name));
+                print(
+                    &ScheduleList {
+                        saves: saves.into_iter().map(Into::into).collect(),
+                    },
+                    json,
+                );
+            }
+            Command::Forget {
+                save,
+                prune,
+                dry_run,
+            } => {
+                let saves: Vec<_> = {
+                    let instance = AppState::instance();
+                    match save {
+                        Some(key) => vec![instance.saves[&instance.find_id(&key)?].clone()],
+                        // The history of vanished worlds is only dropped on request, see `gc`
+                        None => instance
+                            .present_ids()
+                            .iter()
+                            .map(|x| instance.saves[x].clone())
+                            .collect(),
+                    }
+                };
+                let mut results = vec![];
+                for save in saves {
+                    let res = with_progress(
+                        json,
+                        format!("{}: {}", t!("auto-forget"), save.name),
+                        TaskInfo::Forget {
+                            id: save.id().to_string(),
+                            prune,
+                        },
+                        || save.forget(prune, dry_run),
+                    );
+                    let (status, forgotten, error) = match res {
+                        Ok(forgotten) => (TaskStatus::Succeeded, forgotten, None),
+                        Err(err) => (TaskStatus::of(&err), vec![], Some(format!("{:#}", err))),
+                    };
+                    results.push(ForgetResult {
+                        save: save.id().to_string(),
+                        name: save.name.clone(),
+                        status,
+                        forgotten: forgotten.iter().map(Into::into).collect(),
+                        error,
+                    });
+                }
+                let results = ForgetResults { dry_run, results };
+                print(&results, json);
+                return Ok(results.outcome());
+            }
+            Command::Retention {
+                save,
+                policy,
+                reset,
+            } => {
+                let mut instance = AppState::instance();
+                let id = instance.find_id(&save)?;
+                let save = instance.saves.get_mut(&id).unwrap();
+                if reset {
+                    save.retention = None;
+                } else if !policy.is_empty() {
+                    policy.to_keep_options()?;
+                    save.retention = Some(policy);
+                }
+                let info = RetentionInfo::from(&*save);
+                instance.save()?;
+                print(&info, json);
+            }
+            Command::Excludes { save, add, remove } => {
+                let mut instance = AppState::instance();
+                let id = instance.find_id(&save)?;
+                let save = instance.saves.get_mut(&id).unwrap();
+                save.excludes.retain(|x| !remove.contains(x));
+                for pattern in add {
+                    if !save.excludes.contains(&pattern) {
+                        save.excludes.push(pattern);
+                    }
+                }
+                let list = ExcludeList::from(&*save);
+                instance.save()?;
+                print(&list, json);
+            }
+        }
+        Ok(Outcome::Success)
+    }
+}
+
+/// Run `f` as a tracked task, drawing its progress on stderr when that is a terminal.
+fn with_progress<T>(
+    json: bool,
+    name: String,
+    task_info: TaskInfo,
+    f: impl FnOnce() -> Result<T>,
+) -> Result<T> {
+    let done = Arc::new(AtomicBool::new(false));
+    let renderer = (!json && io::stderr().is_terminal()).then(|| {
+        let done = done.clone();
+        thread::spawn(move || {
+            while !done.load(Ordering::Acquire) {
+                if let Some(task) = tasks::list().into_iter().rev().find(|x| x.running) {
+                    eprint!("\r\x1b[K{}: {}", task.name, task.progress);
+                }
+                thread::sleep(Duration::from_millis(200));
+            }
+            eprint!("\r\x1b[K");
+        })
+    });
+    let (id, res) = tasks::track(name, task_info, f);
+    done.store(true, Ordering::Release);
+    if let Some(renderer) = renderer {
+        renderer.join().unwrap_or_default();
+    }
+    // On stderr even with `--json`, which keeps stdout for the report
+    if let Some(task) = tasks::list().into_iter().find(|x| x.id == id) {
+        for warning in &task.warnings {
+            eprintln!("Warning: {}", warning);
+        }
+    }
+    res
+}
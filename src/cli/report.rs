@@ -0,0 +1,966 @@
+use std::{path::PathBuf, process::ExitCode, time::Duration};
+
+use rustic_core::repofile::SnapshotFile;
+use serde::Serialize;
+
+use crate::{
+    backup::{AreaHistoryEntry, FileChange, IGNORE_FILE, SaveBackupConfiguration, TreeEntry},
+    diff::BlockDiff,
+    items::ItemSearch,
+    level::WorldInfo,
+    players::{PlayerInfo, SnapshotRoster},
+    region::Dimension,
+    scheduler,
+    settings::{RetentionPolicy, Settings},
+    tasks::Cancelled,
+};
+
+/// Output of a command, printed as plain text or, with `--json`, as a JSON document.
+pub trait Report: Serialize {
+    fn print_text(&self);
+}
+
+pub fn print<R: Report>(report: &R, json: bool) {
+    if json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(report).expect("Reports are always serializable")
+        );
+    } else {
+        report.print_text();
+    }
+}
+
+/// How a command ended, reported through the exit code.
+///
+/// `2` is left out because clap uses it for usage errors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Success,
+    Fatal,
+    PartialFailure,
+    NothingToDo,
+}
+
+impl From<Outcome> for ExitCode {
+    fn from(value: Outcome) -> Self {
+        match value {
+            Outcome::Success => ExitCode::SUCCESS,
+            Outcome::Fatal => ExitCode::from(1),
+            Outcome::PartialFailure => ExitCode::from(3),
+            Outcome::NothingToDo => ExitCode::from(4),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Failure {
+    pub error: String,
+}
+
+impl Report for Failure {
+    fn print_text(&self) {
+        eprintln!("Error: {}", self.error);
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct SaveEntry {
+    pub id: String,
+    pub name: String,
+    pub source: PathBuf,
+    pub last_backup: Option<String>,
+    pub next_backup: Option<String>,
+    /// Read from `level.dat`
+    pub info: Option<WorldInfo>,
+    /// The world is gone, `source` is where it was last seen
+    pub archived: bool,
+}
+
+impl From<&SaveBackupConfiguration> for SaveEntry {
+    fn from(value: &SaveBackupConfiguration) -> Self {
+        Self {
+            id: value.id().to_string(),
+            name: value.name.clone(),
+            source: value.source().to_path_buf(),
+            last_backup: value.last_backup.map(|x| x.to_rfc3339()),
+            next_backup: scheduler::next_run(value).map(|x| x.to_rfc3339()),
+            info: value.info.clone(),
+            archived: value.archived,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct SaveList {
+    pub saves: Vec<SaveEntry>,
+}
+
+impl Report for SaveList {
+    fn print_text(&self) {
+        for save in &self.saves {
+            let info = save.info.clone().unwrap_or_default();
+            println!(
+                "{}\t{}\t{}\t{}\t{}{}",
+                save.id,
+                save.name,
+                info.level_name.as_deref().unwrap_or("-"),
+                info.version_name.as_deref().unwrap_or("-"),
+                save.source.display(),
+                if save.archived { " (archived)" } else { "" }
+            );
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ScheduleEntry {
+    pub save: String,
+    pub name: String,
+    pub schedule: Option<String>,
+    pub last_backup: Option<String>,
+    pub next_backup: Option<String>,
+}
+
+impl From<&SaveBackupConfiguration> for ScheduleEntry {
+    fn from(value: &SaveBackupConfiguration) -> Self {
+        let settings = { Settings::instance().clone() };
+        Self {
+            save: value.id().to_string(),
+            name: value.name.clone(),
+            schedule: scheduler::Schedule::of(value, &settings)
+                .ok()
+                .map(|x| x.to_string()),
+            last_backup: value.last_backup.map(|x| x.to_rfc3339()),
+            next_backup: scheduler::next_run(value).map(|x| x.to_rfc3339()),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ScheduleList {
+    pub saves: Vec<ScheduleEntry>,
+}
+
+impl Report for ScheduleList {
+    fn print_text(&self) {
+        for save in &self.saves {
+            println!(
+                "{}\t{}\tlast: {}\tnext: {}",
+                save.name,
+                save.schedule.as_deref().unwrap_or("invalid"),
+                save.last_backup.as_deref().unwrap_or("never"),
+                save.next_backup.as_deref().unwrap_or("never"),
+            );
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct SnapshotSizes {
+    pub files: u64,
+    pub files_new: u64,
+    pub files_changed: u64,
+    pub files_unmodified: u64,
+    pub bytes: u64,
+    pub data_added: u64,
+    pub data_added_packed: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SnapshotEntry {
+    pub id: String,
+    pub time: String,
+    pub label: String,
+    pub description: Option<String>,
+    pub tags: Vec<String>,
+    pub summary: Option<SnapshotSizes>,
+}
+
+impl From<&SnapshotFile> for SnapshotEntry {
+    fn from(value: &SnapshotFile) -> Self {
+        Self {
+            id: value.id.to_string(),
+            time: value.time.to_rfc3339(),
+            label: value.label.clone(),
+            description: value.description.clone(),
+            tags: value.tags.iter().cloned().collect(),
+            summary: value.summary.as_ref().map(|x| SnapshotSizes {
+                files: x.total_files_processed,
+                files_new: x.files_new,
+                files_changed: x.files_changed,
+                files_unmodified: x.files_unmodified,
+                bytes: x.total_bytes_processed,
+                data_added: x.data_added,
+                data_added_packed: x.data_added_packed,
+            }),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct SnapshotList {
+    pub save: String,
+    pub snapshots: Vec<SnapshotEntry>,
+}
+
+impl Report for SnapshotList {
+    fn print_text(&self) {
+        for snapshot in &self.snapshots {
+            println!(
+                "{}\t{}\t{}",
+                &snapshot.id[..8],
+                snapshot.time,
+                snapshot.label
+            );
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TaskStatus {
+    Succeeded,
+    /// Skipped because nothing changed since the previous snapshot
+    Unchanged,
+    Failed,
+    Cancelled,
+}
+
+impl TaskStatus {
+    pub fn of(err: &anyhow::Error) -> Self {
+        if err.is::<Cancelled>() {
+            TaskStatus::Cancelled
+        } else {
+            TaskStatus::Failed
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct TaskResult {
+    pub save: String,
+    pub name: String,
+    pub status: TaskStatus,
+    pub snapshot: Option<String>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TaskResults {
+    pub results: Vec<TaskResult>,
+}
+
+impl TaskResults {
+    pub fn outcome(&self) -> Outcome {
+        let failed = self
+            .results
+            .iter()
+            .filter(|x| !matches!(x.status, TaskStatus::Succeeded | TaskStatus::Unchanged))
+            .count();
+        if self
+            .results
+            .iter()
+            .all(|x| x.status == TaskStatus::Unchanged)
+        {
+            Outcome::NothingToDo
+        } else if failed == self.results.len() {
+            Outcome::Fatal
+        } else if failed > 0 {
+            Outcome::PartialFailure
+        } else {
+            Outcome::Success
+        }
+    }
+}
+
+impl Report for TaskResults {
+    fn print_text(&self) {
+        for result in &self.results {
+            match result.status {
+                TaskStatus::Succeeded => println!(
+                    "{} ({}): {}",
+                    result.name,
+                    result.save,
+                    result.snapshot.as_deref().unwrap_or("done")
+                ),
+                TaskStatus::Unchanged => println!("{} ({}): unchanged", result.name, result.save),
+                TaskStatus::Failed | TaskStatus::Cancelled => println!(
+                    "{} ({}): failed: {}",
+                    result.name,
+                    result.save,
+                    result.error.as_deref().unwrap_or_default()
+                ),
+            }
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct SnapshotTree {
+    pub snapshot: String,
+    pub entries: Vec<TreeEntry>,
+}
+
+impl Report for SnapshotTree {
+    fn print_text(&self) {
+        for entry in &self.entries {
+            if entry.dir {
+                println!("-\t{}/", entry.path.display());
+            } else {
+                println!("{}\t{}", entry.size, entry.path.display());
+            }
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct FileDiff {
+    pub save: String,
+    pub from: String,
+    /// `None` when compared with the world as it is now
+    pub to: Option<String>,
+    pub changes: Vec<FileChange>,
+}
+
+impl Report for FileDiff {
+    fn print_text(&self) {
+        let to = match &self.to {
+            Some(to) => to[..8].to_string(),
+            None => "the world".to_string(),
+        };
+        println!(
+            "{} file(s) changed between {} and {}",
+            self.changes.len(),
+            &self.from[..8],
+            to
+        );
+        for change in &self.changes {
+            let (sign, size) = match (change.before, change.after) {
+                (None, Some(after)) => ("+", after.to_string()),
+                (Some(before), None) => ("-", before.to_string()),
+                (before, after) => {
+                    let delta =
+                        after.unwrap_or_default() as i64 - before.unwrap_or_default() as i64;
+                    ("~", format!("{:+}", delta))
+                }
+            };
+            println!("{}\t{}\t{}", sign, size, change.path.display());
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ItemsFound {
+    pub save: String,
+    pub item: String,
+    #[serde(flatten)]
+    pub search: ItemSearch,
+}
+
+impl Report for ItemsFound {
+    fn print_text(&self) {
+        for snapshot in &self.search.snapshots {
+            println!(
+                "{}\t{}\t{}\t{}",
+                &snapshot.snapshot[..8],
+                snapshot.time.format("%Y-%m-%d %H:%M:%S"),
+                snapshot.label,
+                snapshot.total()
+            );
+            for found in &snapshot.found {
+                println!("\t{}\t{}", found.count, found.location);
+            }
+        }
+        if let Some(last) = self
+            .search
+            .snapshots
+            .iter()
+            .rev()
+            .find(|x| !x.found.is_empty())
+        {
+            println!("Last snapshot with {}: {}", self.item, &last.snapshot[..8]);
+        } else {
+            println!("No snapshot has {}", self.item);
+        }
+        for skipped in &self.search.skipped {
+            eprintln!("Skipped {}", skipped);
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct BlockChanges {
+    pub save: String,
+    pub from: String,
+    pub to: String,
+    #[serde(flatten)]
+    pub diff: BlockDiff,
+}
+
+impl BlockChanges {
+    /// One line per chunk and counted type.
+    pub fn print_csv(&self) {
+        for line in self.csv_lines() {
+            println!("{}", line);
+        }
+    }
+
+    fn csv_lines(&self) -> Vec<String> {
+        let mut lines = vec!["dimension,chunk_x,chunk_z,status,kind,change,id,count".to_string()];
+        for chunk in &self.diff.chunks {
+            let prefix = format!(
+                "{},{},{},{}",
+                chunk.dimension,
+                chunk.chunk.x,
+                chunk.chunk.z,
+                csv_value(&chunk.status)
+            );
+            if chunk.counts.is_empty() {
+                lines.push(format!("{},,,,", prefix));
+            }
+            for count in &chunk.counts {
+                lines.push(format!(
+                    "{},{},{},{},{}",
+                    prefix,
+                    csv_value(&count.kind),
+                    csv_value(&count.change),
+                    count.id,
+                    count.count
+                ));
+            }
+        }
+        lines
+    }
+}
+
+/// How serde names a unit variant.
+fn csv_value(value: &impl Serialize) -> String {
+    serde_json::to_value(value)
+        .ok()
+        .and_then(|x| x.as_str().map(str::to_string))
+        .unwrap_or_default()
+}
+
+impl Report for BlockChanges {
+    fn print_text(&self) {
+        println!(
+            "{} chunk(s) changed between {} and {}",
+            self.diff.chunks.len(),
+            &self.from[..8],
+            &self.to[..8]
+        );
+        for chunk in &self.diff.chunks {
+            println!(
+                "{} {}\t{}",
+                chunk.dimension,
+                chunk.chunk,
+                csv_value(&chunk.status)
+            );
+            for count in &chunk.counts {
+                println!(
+                    "\t{} {} {}\t{}",
+                    csv_value(&count.change),
+                    csv_value(&count.kind),
+                    count.id,
+                    count.count
+                );
+            }
+        }
+        for skipped in &self.diff.skipped {
+            eprintln!("Skipped {}", skipped);
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct AreaHistory {
+    pub save: String,
+    pub dimension: Dimension,
+    /// How many chunks the area has
+    pub chunks: usize,
+    /// Oldest first
+    pub entries: Vec<AreaHistoryEntry>,
+}
+
+impl Report for AreaHistory {
+    fn print_text(&self) {
+        for entry in &self.entries {
+            let change = if entry.changed.is_empty() && entry.removed.is_empty() {
+                "unchanged".to_string()
+            } else {
+                format!(
+                    "{} changed, {} removed",
+                    entry.changed.len(),
+                    entry.removed.len()
+                )
+            };
+            println!(
+                "{}\t{}\t{}\t{}/{} chunk(s)\t{}{}",
+                &entry.snapshot[..8],
+                entry.time.format("%Y-%m-%d %H:%M:%S"),
+                entry.label,
+                entry.present,
+                self.chunks,
+                change,
+                entry
+                    .last_saved
+                    .map(|x| format!(" (last saved {})", x.format("%Y-%m-%d %H:%M:%S")))
+                    .unwrap_or_default()
+            );
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChunksRestored {
+    pub save: String,
+    pub snapshot: String,
+    pub dimension: Dimension,
+    /// Chunks copied from the snapshot
+    pub replaced: usize,
+    /// Chunks missing from the snapshot, removed to be generated again
+    pub removed: usize,
+    pub pre_restore_snapshot: Option<String>,
+}
+
+impl Report for ChunksRestored {
+    fn print_text(&self) {
+        println!(
+            "Replaced {} and removed {} chunk(s) of the {}",
+            self.replaced, self.removed, self.dimension
+        );
+        if let Some(snapshot) = &self.pre_restore_snapshot {
+            println!("Pre-restore snapshot: {}", snapshot);
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct PlayerRoster {
+    pub save: String,
+    /// `None` for the world as it is now
+    pub snapshot: Option<String>,
+    pub players: Vec<PlayerInfo>,
+}
+
+impl Report for PlayerRoster {
+    fn print_text(&self) {
+        print_players(&self.players, "");
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct RosterHistory {
+    pub save: String,
+    /// Oldest first
+    pub snapshots: Vec<SnapshotRoster>,
+}
+
+impl Report for RosterHistory {
+    fn print_text(&self) {
+        for snapshot in &self.snapshots {
+            println!(
+                "{}\t{}\t{}\t{} player(s)",
+                &snapshot.snapshot[..8],
+                snapshot.time.format("%Y-%m-%d %H:%M:%S"),
+                snapshot.label,
+                snapshot.players.len()
+            );
+            print_players(&snapshot.players, "\t");
+        }
+    }
+}
+
+/// One line per player: name, UUID, last seen, play time, then dimension and position.
+fn print_players(players: &[PlayerInfo], indent: &str) {
+    for player in players {
+        println!(
+            "{}{}\t{}\t{}\t{}\t{}{}",
+            indent,
+            player.name.as_deref().unwrap_or("-"),
+            player.uuid,
+            player.last_seen.map_or("-".to_string(), |x| x
+                .format("%Y-%m-%d %H:%M:%S")
+                .to_string()),
+            player.play_time.map_or("-".to_string(), |x| {
+                humantime::format_duration(Duration::from_secs(x)).to_string()
+            }),
+            player.dimension.as_deref().unwrap_or("-"),
+            player
+                .position
+                .map(|[x, y, z]| format!(" {:.0},{:.0},{:.0}", x, y, z))
+                .unwrap_or_default()
+        );
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct PlayerRestored {
+    pub save: String,
+    pub snapshot: String,
+    /// UUID of the player
+    pub player: String,
+    /// Files put back, relative to the world
+    pub paths: Vec<PathBuf>,
+    pub pre_restore_snapshot: Option<String>,
+}
+
+impl Report for PlayerRestored {
+    fn print_text(&self) {
+        println!(
+            "Restored player {} from {}",
+            self.player,
+            &self.snapshot[..8]
+        );
+        for path in &self.paths {
+            println!("\t{}", path.display());
+        }
+        if let Some(snapshot) = &self.pre_restore_snapshot {
+            println!("Pre-restore snapshot: {}", snapshot);
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Restored {
+    pub save: String,
+    pub snapshot: String,
+    pub destination: PathBuf,
+    /// Snapshot of the world taken before restoring in place, unless it did not change
+    pub pre_restore_snapshot: Option<String>,
+    /// Where the world replaced by an in-place restore is kept
+    pub replaced: Option<PathBuf>,
+}
+
+impl Report for Restored {
+    fn print_text(&self) {
+        println!("Restored into {}", self.destination.display());
+        if let Some(snapshot) = &self.pre_restore_snapshot {
+            println!("Pre-restore snapshot: {}", snapshot);
+        }
+        if let Some(path) = &self.replaced {
+            println!(
+                "The replaced world is kept in {} until `minesave trash --empty`",
+                path.display()
+            );
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct GcResult {
+    pub save: String,
+    pub name: String,
+    /// Where the world was
+    pub source: PathBuf,
+    pub archived: bool,
+    /// Copy of the repository made by this command
+    pub exported: Option<PathBuf>,
+    pub deleted: bool,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct GcResults {
+    pub results: Vec<GcResult>,
+}
+
+impl GcResults {
+    pub fn outcome(&self) -> Outcome {
+        let failed = self.results.iter().filter(|x| x.error.is_some()).count();
+        if self.results.is_empty() {
+            Outcome::NothingToDo
+        } else if failed == self.results.len() {
+            Outcome::Fatal
+        } else if failed > 0 {
+            Outcome::PartialFailure
+        } else {
+            Outcome::Success
+        }
+    }
+}
+
+impl Report for GcResults {
+    fn print_text(&self) {
+        for result in &self.results {
+            let status = if let Some(error) = &result.error {
+                format!("failed: {}", error)
+            } else if result.deleted {
+                "deleted".to_string()
+            } else if result.archived {
+                "archived".to_string()
+            } else {
+                "orphaned".to_string()
+            };
+            println!(
+                "{} ({})\t{}\t{}",
+                result.name,
+                result.save,
+                result.source.display(),
+                status
+            );
+            if let Some(path) = &result.exported {
+                println!("\texported to {}", path.display());
+            }
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct TrashEntry {
+    pub save: String,
+    pub name: String,
+    /// Worlds replaced by in-place restores and still kept
+    pub trash: Vec<PathBuf>,
+    /// Worlds deleted by this command
+    pub removed: Vec<PathBuf>,
+    /// World put back in place by this command
+    pub put_back: Option<PathBuf>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TrashList {
+    pub saves: Vec<TrashEntry>,
+}
+
+impl Report for TrashList {
+    fn print_text(&self) {
+        for save in &self.saves {
+            if save.trash.is_empty() && save.removed.is_empty() && save.put_back.is_none() {
+                continue;
+            }
+            println!("{} ({})", save.name, save.save);
+            for path in &save.removed {
+                println!("\tremoved {}", path.display());
+            }
+            if let Some(path) = &save.put_back {
+                println!("\tput back {}", path.display());
+            }
+            for path in &save.trash {
+                println!("\t{}", path.display());
+            }
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ForgetResult {
+    pub save: String,
+    pub name: String,
+    pub status: TaskStatus,
+    pub forgotten: Vec<SnapshotEntry>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ForgetResults {
+    pub dry_run: bool,
+    pub results: Vec<ForgetResult>,
+}
+
+impl ForgetResults {
+    pub fn outcome(&self) -> Outcome {
+        let failed = self
+            .results
+            .iter()
+            .filter(|x| x.status != TaskStatus::Succeeded)
+            .count();
+        if failed == 0 && self.results.iter().all(|x| x.forgotten.is_empty()) {
+            Outcome::NothingToDo
+        } else if failed == self.results.len() {
+            Outcome::Fatal
+        } else if failed > 0 {
+            Outcome::PartialFailure
+        } else {
+            Outcome::Success
+        }
+    }
+}
+
+impl Report for ForgetResults {
+    fn print_text(&self) {
+        for result in &self.results {
+            if let Some(error) = &result.error {
+                println!("{} ({}): failed: {}", result.name, result.save, error);
+                continue;
+            }
+            println!(
+                "{} ({}): {} {} snapshot(s)",
+                result.name,
+                result.save,
+                if self.dry_run {
+                    "would forget"
+                } else {
+                    "forgot"
+                },
+                result.forgotten.len()
+            );
+            for snapshot in &result.forgotten {
+                println!(
+                    "\t{}\t{}\t{}",
+                    &snapshot.id[..8],
+                    snapshot.time,
+                    snapshot.label
+                );
+            }
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct RetentionInfo {
+    pub save: String,
+    pub name: String,
+    /// Whether the save has its own policy instead of the global one
+    pub overridden: bool,
+    pub policy: RetentionPolicy,
+}
+
+impl From<&SaveBackupConfiguration> for RetentionInfo {
+    fn from(value: &SaveBackupConfiguration) -> Self {
+        Self {
+            save: value.id().to_string(),
+            name: value.name.clone(),
+            overridden: value.retention.is_some(),
+            policy: value
+                .retention
+                .clone()
+                .unwrap_or_else(|| Settings::instance().retention.clone()),
+        }
+    }
+}
+
+impl Report for RetentionInfo {
+    fn print_text(&self) {
+        println!(
+            "{} ({}){}",
+            self.name,
+            self.save,
+            if self.overridden {
+                ""
+            } else {
+                ": global policy"
+            }
+        );
+        if self.policy.is_empty() {
+            println!("\tkeep everything");
+        }
+        for (rule, value) in [
+            ("keep-last", self.policy.keep_last),
+            ("keep-hourly", self.policy.keep_hourly),
+            ("keep-daily", self.policy.keep_daily),
+            ("keep-weekly", self.policy.keep_weekly),
+            ("keep-monthly", self.policy.keep_monthly),
+        ] {
+            if let Some(value) = value {
+                println!("\t{}: {}", rule, value);
+            }
+        }
+        if let Some(value) = &self.policy.keep_within {
+            println!("\tkeep-within: {}", value);
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ExcludeList {
+    pub save: String,
+    pub name: String,
+    /// Patterns of this save only
+    pub own: Vec<String>,
+    /// Every pattern applied to this save, including built-in and global ones
+    pub effective: Vec<String>,
+    /// The gitignore-style file read from the world, if it exists
+    pub ignore_file: Option<PathBuf>,
+}
+
+impl From<&SaveBackupConfiguration> for ExcludeList {
+    fn from(value: &SaveBackupConfiguration) -> Self {
+        let ignore_file = value.source().join(IGNORE_FILE);
+        Self {
+            save: value.id().to_string(),
+            name: value.name.clone(),
+            own: value.excludes.clone(),
+            effective: value.excludes(&Settings::instance()),
+            ignore_file: ignore_file.exists().then_some(ignore_file),
+        }
+    }
+}
+
+impl Report for ExcludeList {
+    fn print_text(&self) {
+        println!("{} ({})", self.name, self.save);
+        for pattern in &self.effective {
+            println!(
+                "\t{}{}",
+                pattern,
+                if self.own.contains(pattern) {
+                    ""
+                } else {
+                    " (global)"
+                }
+            );
+        }
+        if let Some(path) = &self.ignore_file {
+            println!("\tand the patterns in {}", path.display());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        diff::{Change, ChunkDiff, Count, Kind},
+        region::ChunkPos,
+    };
+
+    #[test]
+    fn block_changes_as_csv() {
+        let changes = BlockChanges {
+            save: "save".to_string(),
+            from: "0123456789".to_string(),
+            to: "9876543210".to_string(),
+            diff: BlockDiff {
+                chunks: vec![
+                    ChunkDiff {
+                        dimension: Dimension::Overworld,
+                        chunk: ChunkPos { x: -1, z: 2 },
+                        status: Change::Changed,
+                        counts: vec![
+                            Count {
+                                kind: Kind::Block,
+                                change: Change::Removed,
+                                id: "minecraft:stone".to_string(),
+                                count: 12,
+                            },
+                            Count {
+                                kind: Kind::BlockEntity,
+                                change: Change::Added,
+                                id: "minecraft:chest".to_string(),
+                                count: 1,
+                            },
+                        ],
+                    },
+                    ChunkDiff {
+                        dimension: Dimension::Nether,
+                        chunk: ChunkPos { x: 0, z: 0 },
+                        status: Change::Added,
+                        counts: vec![],
+                    },
+                ],
+                skipped: vec![],
+            },
+        };
+        assert_eq!(
+            changes.csv_lines(),
+            [
+                "dimension,chunk_x,chunk_z,status,kind,change,id,count",
+                "overworld,-1,2,changed,block,removed,minecraft:stone,12",
+                "overworld,-1,2,changed,block_entity,added,minecraft:chest,1",
+                "nether,0,0,added,,,,",
+            ]
+        );
+    }
+}
@@ -0,0 +1,484 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    path::PathBuf,
+};
+
+use anyhow::Result;
+use rustic_core::repofile::SnapshotFile;
+use serde::Serialize;
+
+use crate::{
+    backup::SaveBackupConfiguration,
+    blocks::{self, ChunkContent},
+    nbt::Tag,
+    region::{Chunk, ChunkPos, Dimension, Region},
+    tasks,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Kind {
+    Block,
+    BlockEntity,
+    Entity,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Change {
+    Added,
+    Removed,
+    Changed,
+}
+
+/// How many blocks, block entities or entities of one type changed in a chunk.
+#[derive(Debug, Clone, Serialize)]
+pub struct Count {
+    pub kind: Kind,
+    pub change: Change,
+    /// Block or entity type, like `minecraft:chest`
+    pub id: String,
+    pub count: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ChunkDiff {
+    pub dimension: Dimension,
+    pub chunk: ChunkPos,
+    /// Whether the chunk was generated or deleted as a whole, in which case its blocks are not
+    /// counted, or changed
+    pub status: Change,
+    pub counts: Vec<Count>,
+}
+
+/// What changed in the chunks of a world between two snapshots.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct BlockDiff {
+    pub chunks: Vec<ChunkDiff>,
+    /// Chunks that could not be decoded, with the reason
+    pub skipped: Vec<String>,
+}
+
+impl BlockDiff {
+    /// Counts summed over all chunks.
+    pub fn totals(&self) -> Vec<Count> {
+        let mut totals: BTreeMap<(Kind, Change, &str), u64> = BTreeMap::new();
+        for count in self.chunks.iter().flat_map(|x| &x.counts) {
+            *totals
+                .entry((count.kind, count.change, &count.id))
+                .or_default() += count.count;
+        }
+        totals
+            .into_iter()
+            .map(|((kind, change, id), count)| Count {
+                kind,
+                change,
+                id: id.to_string(),
+                count,
+            })
+            .collect()
+    }
+}
+
+/// Compare the blocks, block entities and entities of two snapshots of a world, only decoding
+/// the region files that changed between them. Blocks count as added or removed by type, and as
+/// changed when only their state changed, like an opened door. Entities move all the time, so
+/// only added and removed ones are counted. `area` limits the comparison to some chunks.
+pub fn diff_blocks(
+    save: &SaveBackupConfiguration,
+    from: &SnapshotFile,
+    to: &SnapshotFile,
+    dimensions: &[Dimension],
+    area: Option<&[ChunkPos]>,
+) -> Result<BlockDiff> {
+    let area: Option<HashSet<ChunkPos>> = area.map(|x| x.iter().copied().collect());
+    let mut files: Vec<(Dimension, bool, PathBuf, Vec<ChunkPos>)> = vec![];
+    for change in save.changed_files(from, to)? {
+        for dimension in dimensions {
+            for (dir, entities) in [("region", false), ("entities", true)] {
+                if change.path.parent() != Some(&dimension.dir().join(dir)) {
+                    continue;
+                }
+                let Some(mut chunks) = change
+                    .path
+                    .file_name()
+                    .and_then(|x| ChunkPos::in_region_file(&x.to_string_lossy()))
+                else {
+                    continue;
+                };
+                if let Some(area) = &area {
+                    chunks.retain(|x| area.contains(x));
+                }
+                if !chunks.is_empty() {
+                    files.push((*dimension, entities, change.path.clone(), chunks));
+                }
+            }
+        }
+    }
+    let paths: Vec<PathBuf> = files.iter().map(|x| x.2.clone()).collect();
+    let old = save.read_files(from, &paths)?;
+    let new = save.read_files(to, &paths)?;
+
+    let mut diff = BlockDiff::default();
+    let mut chunks: BTreeMap<(Dimension, ChunkPos), Counter> = BTreeMap::new();
+    for ((dimension, entities, path, positions), (old, new)) in
+        files.iter().zip(old.into_iter().zip(new))
+    {
+        tasks::checkpoint()?;
+        let parse = |data: Option<Vec<u8>>| {
+            data.as_deref()
+                .map(Region::parse)
+                .transpose()
+                .map(Option::unwrap_or_default)
+        };
+        let (old, new) = match (parse(old), parse(new)) {
+            (Ok(old), Ok(new)) => (old, new),
+            (Err(err), _) | (_, Err(err)) => {
+                diff.skipped.push(format!("{}: {:#}", path.display(), err));
+                continue;
+            }
+        };
+        for pos in positions {
+            let (old, new) = (old.get(*pos), new.get(*pos));
+            if old == new {
+                continue;
+            }
+            let res = if *entities {
+                compare_entities(old, new)
+            } else {
+                compare_chunks(old, new)
+            };
+            match res {
+                Ok(counter) => {
+                    let entry = chunks.entry((*dimension, *pos)).or_default();
+                    // Chunks generated or deleted as a whole are told by their blocks
+                    if counter.status.is_some() && (entry.status.is_none() || !*entities) {
+                        entry.status = counter.status;
+                    }
+                    for (key, count) in counter.counts {
+                        *entry.counts.entry(key).or_default() += count;
+                    }
+                }
+                Err(err) => diff
+                    .skipped
+                    .push(format!("{} {}: {:#}", dimension, pos, err)),
+            }
+        }
+    }
+    diff.chunks = chunks
+        .into_iter()
+        .filter(|(_, x)| x.status.is_some())
+        .map(|((dimension, chunk), counter)| ChunkDiff {
+            dimension,
+            chunk,
+            status: counter.status.unwrap_or(Change::Changed),
+            counts: counter
+                .counts
+                .into_iter()
+                .map(|((kind, change, id), count)| Count {
+                    kind,
+                    change,
+                    id,
+                    count,
+                })
+                .collect(),
+        })
+        .collect();
+    Ok(diff)
+}
+
+#[derive(Debug, Default)]
+struct Counter {
+    /// `None` while nothing changed
+    status: Option<Change>,
+    counts: BTreeMap<(Kind, Change, String), u64>,
+}
+
+impl Counter {
+    fn add(&mut self, kind: Kind, change: Change, id: &str) {
+        self.status = Some(Change::Changed);
+        *self
+            .counts
+            .entry((kind, change, id.to_string()))
+            .or_default() += 1;
+    }
+}
+
+/// Compare two versions of a chunk from a `region/` file.
+fn compare_chunks(old: Option<&Chunk>, new: Option<&Chunk>) -> Result<Counter> {
+    let (old, new) = match (old, new) {
+        (Some(old), Some(new)) => (old, new),
+        (old, _) => {
+            let status = if old.is_some() {
+                Change::Removed
+            } else {
+                Change::Added
+            };
+            return Ok(Counter {
+                status: Some(status),
+                ..Default::default()
+            });
+        }
+    };
+    let old = ChunkContent::from_nbt(&old.nbt()?)?;
+    let new = ChunkContent::from_nbt(&new.nbt()?)?;
+    let mut counter = Counter::default();
+
+    let heights: BTreeSet<i32> = old
+        .sections
+        .keys()
+        .chain(new.sections.keys())
+        .copied()
+        .collect();
+    for y in heights {
+        let (a, b) = (old.sections.get(&y), new.sections.get(&y));
+        if a == b {
+            continue;
+        }
+        for i in 0..4096 {
+            let a = a.map_or("minecraft:air", |x| x.state(i));
+            let b = b.map_or("minecraft:air", |x| x.state(i));
+            if a == b {
+                continue;
+            }
+            let (name_a, name_b) = (blocks::block_name(a), blocks::block_name(b));
+            if name_a == name_b {
+                counter.add(Kind::Block, Change::Changed, name_b);
+                continue;
+            }
+            if !blocks::is_air(a) {
+                counter.add(Kind::Block, Change::Removed, name_a);
+            }
+            if !blocks::is_air(b) {
+                counter.add(Kind::Block, Change::Added, name_b);
+            }
+        }
+    }
+
+    let by_pos = |tags: &[Tag]| -> HashMap<(i64, i64, i64), Tag> {
+        tags.iter()
+            .filter_map(|x| Some((blocks::block_entity_pos(x)?, x.clone())))
+            .collect()
+    };
+    let (a, b) = (by_pos(&old.block_entities), by_pos(&new.block_entities));
+    for (pos, tag) in &a {
+        match b.get(pos) {
+            None => counter.add(Kind::BlockEntity, Change::Removed, blocks::id(tag)),
+            Some(other) if blocks::id(other) != blocks::id(tag) => {
+                counter.add(Kind::BlockEntity, Change::Removed, blocks::id(tag));
+                counter.add(Kind::BlockEntity, Change::Added, blocks::id(other));
+            }
+            Some(other) if other != tag => {
+                counter.add(Kind::BlockEntity, Change::Changed, blocks::id(other))
+            }
+            Some(_) => {}
+        }
+    }
+    for (pos, tag) in &b {
+        if !a.contains_key(pos) {
+            counter.add(Kind::BlockEntity, Change::Added, blocks::id(tag));
+        }
+    }
+
+    count_entities(&mut counter, &old.entities, &new.entities);
+    Ok(counter)
+}
+
+/// Compare two versions of a chunk from an `entities/` file.
+fn compare_entities(old: Option<&Chunk>, new: Option<&Chunk>) -> Result<Counter> {
+    let decode = |chunk: Option<&Chunk>| -> Result<Vec<Tag>> {
+        Ok(match chunk {
+            Some(chunk) => blocks::entities(&chunk.nbt()?),
+            None => vec![],
+        })
+    };
+    let mut counter = Counter::default();
+    count_entities(&mut counter, &decode(old)?, &decode(new)?);
+    Ok(counter)
+}
+
+fn count_entities(counter: &mut Counter, old: &[Tag], new: &[Tag]) {
+    let by_uuid = |tags: &[Tag]| -> HashMap<String, String> {
+        tags.iter()
+            .filter_map(|x| Some((blocks::entity_uuid(x)?, blocks::id(x).to_string())))
+            .collect()
+    };
+    let (a, b) = (by_uuid(old), by_uuid(new));
+    for (uuid, id) in &a {
+        if !b.contains_key(uuid) {
+            counter.add(Kind::Entity, Change::Removed, id);
+        }
+    }
+    for (uuid, id) in &b {
+        if !a.contains_key(uuid) {
+            counter.add(Kind::Entity, Change::Added, id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compound(entries: Vec<(&str, Tag)>) -> Tag {
+        Tag::Compound(
+            entries
+                .into_iter()
+                .map(|(k, v)| (k.to_string(), v))
+                .collect(),
+        )
+    }
+
+    fn block_entity(id: &str, pos: i32, items: i32) -> Tag {
+        compound(vec![
+            ("id", Tag::String(id.to_string())),
+            ("x", Tag::Int(pos)),
+            ("y", Tag::Int(64)),
+            ("z", Tag::Int(pos)),
+            ("Items", Tag::Int(items)),
+        ])
+    }
+
+    fn entity(id: &str, uuid: i32) -> Tag {
+        compound(vec![
+            ("id", Tag::String(id.to_string())),
+            ("UUID", Tag::IntArray(vec![0, 0, 0, uuid])),
+        ])
+    }
+
+    /// A chunk of one section filled with `block`, with optional properties.
+    fn chunk(block: &str, properties: &[(&str, &str)], block_entities: Vec<Tag>) -> Chunk {
+        let properties = properties
+            .iter()
+            .map(|(k, v)| (*k, Tag::String(v.to_string())))
+            .collect();
+        let state = compound(vec![
+            ("Name", Tag::String(block.to_string())),
+            ("Properties", compound(properties)),
+        ]);
+        let section = compound(vec![
+            ("Y", Tag::Byte(0)),
+            (
+                "block_states",
+                compound(vec![("palette", Tag::List(vec![state]))]),
+            ),
+        ]);
+        Chunk::uncompressed(&compound(vec![
+            ("DataVersion", Tag::Int(3465)),
+            ("sections", Tag::List(vec![section])),
+            ("block_entities", Tag::List(block_entities)),
+        ]))
+    }
+
+    fn counts(counter: &Counter) -> Vec<(Kind, Change, &str, u64)> {
+        counter
+            .counts
+            .iter()
+            .map(|((kind, change, id), count)| (*kind, *change, id.as_str(), *count))
+            .collect()
+    }
+
+    #[test]
+    fn whole_chunks_added_or_removed() {
+        let stone = chunk("minecraft:stone", &[], vec![]);
+        let added = compare_chunks(None, Some(&stone)).unwrap();
+        assert_eq!(added.status, Some(Change::Added));
+        assert!(added.counts.is_empty());
+        let removed = compare_chunks(Some(&stone), None).unwrap();
+        assert_eq!(removed.status, Some(Change::Removed));
+        assert!(removed.counts.is_empty());
+    }
+
+    #[test]
+    fn unchanged_chunk_counts_nothing() {
+        let stone = chunk(
+            "minecraft:stone",
+            &[],
+            vec![block_entity("minecraft:chest", 1, 3)],
+        );
+        let counter = compare_chunks(Some(&stone), Some(&stone)).unwrap();
+        assert_eq!(counter.status, None);
+        assert!(counter.counts.is_empty());
+    }
+
+    #[test]
+    fn blocks_replaced_or_changed_in_state() {
+        let old = chunk("minecraft:stone", &[], vec![]);
+        let new = chunk("minecraft:dirt", &[], vec![]);
+        let counter = compare_chunks(Some(&old), Some(&new)).unwrap();
+        assert_eq!(counter.status, Some(Change::Changed));
+        assert_eq!(
+            counts(&counter),
+            [
+                (Kind::Block, Change::Added, "minecraft:dirt", 4096),
+                (Kind::Block, Change::Removed, "minecraft:stone", 4096),
+            ]
+        );
+
+        let closed = chunk("minecraft:oak_door", &[("open", "false")], vec![]);
+        let open = chunk("minecraft:oak_door", &[("open", "true")], vec![]);
+        let counter = compare_chunks(Some(&closed), Some(&open)).unwrap();
+        assert_eq!(
+            counts(&counter),
+            [(Kind::Block, Change::Changed, "minecraft:oak_door", 4096)]
+        );
+
+        // Air is neither added nor removed
+        let air = chunk("minecraft:air", &[], vec![]);
+        let counter = compare_chunks(Some(&air), Some(&old)).unwrap();
+        assert_eq!(
+            counts(&counter),
+            [(Kind::Block, Change::Added, "minecraft:stone", 4096)]
+        );
+    }
+
+    #[test]
+    fn block_entities_by_position() {
+        let old = chunk(
+            "minecraft:stone",
+            &[],
+            vec![
+                block_entity("minecraft:chest", 1, 3),
+                block_entity("minecraft:furnace", 2, 0),
+                block_entity("minecraft:barrel", 3, 0),
+            ],
+        );
+        let new = chunk(
+            "minecraft:stone",
+            &[],
+            vec![
+                block_entity("minecraft:chest", 1, 4),
+                block_entity("minecraft:hopper", 3, 0),
+                block_entity("minecraft:sign", 4, 0),
+            ],
+        );
+        let counter = compare_chunks(Some(&old), Some(&new)).unwrap();
+        assert_eq!(
+            counts(&counter),
+            [
+                (Kind::BlockEntity, Change::Added, "minecraft:hopper", 1),
+                (Kind::BlockEntity, Change::Added, "minecraft:sign", 1),
+                (Kind::BlockEntity, Change::Removed, "minecraft:barrel", 1),
+                (Kind::BlockEntity, Change::Removed, "minecraft:furnace", 1),
+                (Kind::BlockEntity, Change::Changed, "minecraft:chest", 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn entities_added_and_removed() {
+        let mut counter = Counter::default();
+        count_entities(
+            &mut counter,
+            &[entity("minecraft:cow", 1), entity("minecraft:pig", 2)],
+            &[entity("minecraft:cow", 1), entity("minecraft:sheep", 3)],
+        );
+        assert_eq!(
+            counts(&counter),
+            [
+                (Kind::Entity, Change::Added, "minecraft:sheep", 1),
+                (Kind::Entity, Change::Removed, "minecraft:pig", 1),
+            ]
+        );
+    }
+}
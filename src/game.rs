@@ -0,0 +1,217 @@
+use std::{fs::OpenOptions, path::Path, thread, time::Duration};
+
+use anyhow::{Result, bail};
+
+use crate::{
+    settings::{OpenWorldPolicy, Settings},
+    tasks,
+};
+
+/// Tag of the snapshots taken while the world was open in the game.
+pub const UNSAFE_TAG: &str = "unsafe";
+
+/// How often a world open in the game is checked again with `OpenWorldPolicy::Wait`.
+const WAIT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Why the world looks open in Minecraft, if it does.
+pub fn world_in_use(source: &Path) -> Option<String> {
+    if session_locked(source) {
+        return Some("session.lock is locked".to_string());
+    }
+    java_process_using(source).map(|pid| format!("Java process {} uses the instance", pid))
+}
+
+/// Apply `Settings::open_world_policy` before touching a world. Returns whether the world is
+/// open and the backup goes on anyway, so the snapshot should be tagged `UNSAFE_TAG`.
+///
+/// A restore cannot be marked unsafe, so `OpenWorldPolicy::Unsafe` refuses to restore.
+pub fn check_closed(source: &Path, restore: bool) -> Result<bool> {
+    let policy = { Settings::instance().open_world_policy };
+    let mut waiting = false;
+    while let Some(reason) = world_in_use(source) {
+        match policy {
+            OpenWorldPolicy::Wait => {
+                if !waiting {
+                    info!("wait_world_closed(source={:?}, reason={})", source, reason);
+                    waiting = true;
+                }
+                tasks::checkpoint()?;
+                thread::sleep(WAIT_INTERVAL);
+            }
+            OpenWorldPolicy::Warn => {
+                tasks::warn(format!(
+                    "{} is open in Minecraft ({}), the result may be inconsistent",
+                    source.display(),
+                    reason
+                ));
+                return Ok(false);
+            }
+            OpenWorldPolicy::Unsafe if !restore => return Ok(true),
+            OpenWorldPolicy::Unsafe | OpenWorldPolicy::Refuse => {
+                bail!("{} is open in Minecraft: {}", source.display(), reason)
+            }
+        }
+    }
+    Ok(false)
+}
+
+/// Minecraft keeps a lock on `session.lock` while the world is loaded. Java takes it with
+/// `fcntl`, which `flock` based locking does not see, so it is queried with `F_GETLK`.
+#[cfg(unix)]
+fn session_locked(source: &Path) -> bool {
+    use std::os::fd::AsRawFd;
+
+    let Ok(file) = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(source.join("session.lock"))
+    else {
+        return false;
+    };
+    // SAFETY: `flock` is plain data and `F_GETLK` only writes into it
+    let mut lock: libc::flock = unsafe { std::mem::zeroed() };
+    lock.l_type = libc::F_WRLCK as _;
+    lock.l_whence = libc::SEEK_SET as _;
+    let res = unsafe { libc::fcntl(file.as_raw_fd(), libc::F_GETLK, &mut lock) };
+    res == 0 && i32::from(lock.l_type) != libc::F_UNLCK
+}
+
+#[cfg(not(unix))]
+fn session_locked(source: &Path) -> bool {
+    let Ok(file) = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(source.join("session.lock"))
+    else {
+        return false;
+    };
+    matches!(file.try_lock(), Err(std::fs::TryLockError::WouldBlock))
+}
+
+/// A Java process running the instance of the world, found by its working directory or its
+/// `--gameDir`.
+#[cfg(target_os = "linux")]
+fn java_process_using(source: &Path) -> Option<u32> {
+    use std::fs;
+
+    let source = source.canonicalize().ok()?;
+    let root = instance_root(&source)?;
+    fs::read_dir("/proc").ok()?.flatten().find_map(|entry| {
+        let pid: u32 = entry.file_name().to_str()?.parse().ok()?;
+        let cmdline = fs::read(entry.path().join("cmdline")).ok()?;
+        let args: Vec<String> = cmdline
+            .split(|x| *x == 0)
+            .filter(|x| !x.is_empty())
+            .map(|x| String::from_utf8_lossy(x).into_owned())
+            .collect();
+        let is_java = Path::new(args.first()?)
+            .file_name()
+            .is_some_and(|x| x.to_string_lossy().starts_with("java"));
+        if !is_java {
+            return None;
+        }
+        let cwd = fs::read_link(entry.path().join("cwd")).ok();
+        runs_instance(root, cwd.as_deref(), &args).then_some(pid)
+    })
+}
+
+/// The directory the game runs in to play the world: the instance holding `saves/<world>`, or
+/// the server directory holding the world.
+#[cfg(target_os = "linux")]
+fn instance_root(world: &Path) -> Option<&Path> {
+    let parent = world.parent()?;
+    if parent.file_name()? == "saves" {
+        parent.parent()
+    } else {
+        Some(parent)
+    }
+}
+
+/// Whether a Java process with this working directory and arguments runs in `root`.
+#[cfg(target_os = "linux")]
+fn runs_instance(root: &Path, cwd: Option<&Path>, args: &[String]) -> bool {
+    // `--gameDir=<path>` as well as `--gameDir <path>`
+    let game_dir =
+        args.iter()
+            .enumerate()
+            .find_map(|(i, x)| match x.strip_prefix("--gameDir")? {
+                "" => args.get(i + 1).map(Path::new),
+                value => value.strip_prefix('=').map(Path::new),
+            });
+    cwd.into_iter()
+        .chain(game_dir)
+        .any(|x| x.is_absolute() && x.canonicalize().as_deref().unwrap_or(x) == root)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn java_process_using(_source: &Path) -> Option<u32> {
+    None
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn instance_root_of_worlds() {
+        assert_eq!(
+            instance_root(Path::new("/home/steve/.minecraft/saves/World")),
+            Some(Path::new("/home/steve/.minecraft"))
+        );
+        assert_eq!(
+            instance_root(Path::new("/srv/survival/world")),
+            Some(Path::new("/srv/survival"))
+        );
+    }
+
+    #[test]
+    fn processes_running_the_instance() {
+        let root = Path::new("/home/steve/.minecraft");
+        let args = |x: &[&str]| x.iter().map(|x| x.to_string()).collect::<Vec<_>>();
+        let client = args(&["java", "-Xmx4G", "net.minecraft.client.main.Main"]);
+        assert!(runs_instance(root, Some(root), &client));
+        assert!(runs_instance(
+            root,
+            Some(Path::new("/home/steve")),
+            &args(&["java", "--gameDir", "/home/steve/.minecraft"])
+        ));
+        assert!(runs_instance(
+            root,
+            None,
+            &args(&["java", "--gameDir=/home/steve/.minecraft/"])
+        ));
+        // Other instances and the directories around this one
+        assert!(!runs_instance(
+            root,
+            Some(Path::new("/home/steve/instances/modded")),
+            &client
+        ));
+        assert!(!runs_instance(
+            root,
+            Some(Path::new("/home/steve")),
+            &client
+        ));
+        assert!(!runs_instance(root, Some(Path::new("/")), &client));
+        assert!(!runs_instance(
+            root,
+            Some(Path::new("/home/steve/.minecraft/saves")),
+            &client
+        ));
+        // Paths that are not the game directory
+        assert!(!runs_instance(
+            root,
+            None,
+            &args(&["java", "-Djava.library.path=/home/steve/.minecraft"])
+        ));
+        assert!(!runs_instance(
+            root,
+            None,
+            &args(&["java", "--assetsDir", "/home/steve/.minecraft"])
+        ));
+        assert!(!runs_instance(
+            root,
+            None,
+            &args(&["java", "--gameDir", "minecraft"])
+        ));
+    }
+}
@@ -0,0 +1,557 @@
+use std::{
+    collections::BTreeMap,
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+use chrono::{DateTime, Local};
+use rustic_core::repofile::SnapshotFile;
+use serde::Serialize;
+
+use crate::{
+    backup::SaveBackupConfiguration,
+    blocks,
+    nbt::{self, Tag},
+    region::{ChunkPos, Dimension, Region},
+};
+
+/// Keys of block entities holding a single item: decorated pots, jukeboxes and lecterns.
+const SINGLE_ITEMS: &[&str] = &["item", "RecordItem", "Book"];
+
+/// What to look for. Ids without a namespace are taken from `minecraft:`.
+#[derive(Debug, Clone)]
+pub struct ItemQuery {
+    pub id: String,
+    /// Part of the custom name, in any case
+    pub name: Option<String>,
+    pub enchantment: Option<String>,
+}
+
+impl ItemQuery {
+    pub fn new(id: &str, name: Option<&str>, enchantment: Option<&str>) -> Self {
+        Self {
+            id: namespaced(id),
+            name: name.map(str::to_lowercase),
+            enchantment: enchantment.map(namespaced),
+        }
+    }
+
+    fn matches(&self, item: &Tag) -> bool {
+        if blocks::id(item) != self.id {
+            return false;
+        }
+        if let Some(name) = &self.name {
+            // A JSON text before 1.21.5, a text component after
+            let custom_name = item
+                .path(&["tag", "display", "Name"])
+                .or(item.path(&["components", "minecraft:custom_name"]));
+            if !custom_name.is_some_and(|x| text(x).to_lowercase().contains(name)) {
+                return false;
+            }
+        }
+        if let Some(enchantment) = &self.enchantment
+            && !enchantments(item).contains(&enchantment.as_str())
+        {
+            return false;
+        }
+        true
+    }
+}
+
+fn namespaced(id: &str) -> String {
+    let id = id.trim();
+    if id.contains(':') {
+        id.to_string()
+    } else {
+        format!("minecraft:{}", id)
+    }
+}
+
+/// Where items were found.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Location {
+    /// Inventory, armor and offhand of a player, by UUID
+    Inventory {
+        player: String,
+    },
+    EnderChest {
+        player: String,
+    },
+    /// A block entity like a chest, barrel or shulker box
+    Container {
+        dimension: Dimension,
+        block: String,
+        x: i64,
+        y: i64,
+        z: i64,
+    },
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Location::Inventory { player } => write!(f, "inventory of {}", player),
+            Location::EnderChest { player } => write!(f, "ender chest of {}", player),
+            Location::Container {
+                dimension,
+                block,
+                x,
+                y,
+                z,
+            } => write!(f, "{} at {} {},{},{}", block, dimension, x, y, z),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Found {
+    #[serde(flatten)]
+    pub location: Location,
+    /// Counting the items in shulker boxes and bundles held there
+    pub count: u64,
+}
+
+/// The items matching a query in one snapshot.
+#[derive(Debug, Clone, Serialize)]
+pub struct SnapshotItems {
+    pub snapshot: String,
+    pub time: DateTime<Local>,
+    pub label: String,
+    pub found: Vec<Found>,
+}
+
+impl SnapshotItems {
+    pub fn total(&self) -> u64 {
+        self.found.iter().map(|x| x.count).sum()
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ItemSearch {
+    /// In the order of the snapshots searched
+    pub snapshots: Vec<SnapshotItems>,
+    /// Files and chunks that could not be decoded, with the reason
+    pub skipped: Vec<String>,
+}
+
+/// Look for items in the player inventories and ender chests of `playerdata/`, and in the
+/// containers of every dimension, in each of `snapshots`. Only the files that changed since the
+/// snapshot before are decoded, so `snapshots` should be sorted by time.
+pub fn search_items(
+    save: &SaveBackupConfiguration,
+    snapshots: &[SnapshotFile],
+    query: &ItemQuery,
+) -> Result<ItemSearch> {
+    let region_dirs: Vec<(Dimension, PathBuf)> = Dimension::ALL
+        .iter()
+        .map(|x| (*x, x.dir().join("region")))
+        .collect();
+    let dimension_of = |path: &Path| {
+        region_dirs
+            .iter()
+            .find(|(_, dir)| path.parent() == Some(dir))
+            .map(|x| x.0)
+    };
+    let select = |path: &Path| {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        if path.parent() == Some(Path::new("playerdata")) {
+            name.ends_with(".dat")
+        } else {
+            name.ends_with(".mca") && dimension_of(path).is_some()
+        }
+    };
+
+    let mut search = ItemSearch::default();
+    // What each file held, from the index of the snapshot it changed in
+    let mut versions: BTreeMap<PathBuf, Vec<(usize, Vec<Found>)>> = BTreeMap::new();
+    save.for_each_changed_file(snapshots, select, |i, path, data| {
+        let mut found = vec![];
+        if let Some(data) = data {
+            let res = match dimension_of(path) {
+                Some(dimension) => search_region(
+                    path,
+                    &data,
+                    dimension,
+                    query,
+                    &mut found,
+                    &mut search.skipped,
+                ),
+                None => search_player(path, &data, query, &mut found),
+            };
+            if let Err(err) = res {
+                search.skipped.push(format!(
+                    "{} in {}: {:#}",
+                    path.display(),
+                    &snapshots[i].id.to_string()[..8],
+                    err
+                ));
+            }
+        }
+        versions
+            .entry(path.to_path_buf())
+            .or_default()
+            .push((i, found));
+        Ok(())
+    })?;
+
+    for (i, snapshot) in snapshots.iter().enumerate() {
+        let mut found: Vec<Found> = versions
+            .values()
+            .filter_map(|x| x.iter().rev().find(|(since, _)| *since <= i))
+            .flat_map(|(_, found)| found.iter().cloned())
+            .collect();
+        found.sort_by(|a, b| a.location.cmp(&b.location));
+        search.snapshots.push(SnapshotItems {
+            snapshot: snapshot.id.to_string(),
+            time: snapshot.time,
+            label: snapshot.label.clone(),
+            found,
+        });
+    }
+    Ok(search)
+}
+
+fn search_player(path: &Path, data: &[u8], query: &ItemQuery, out: &mut Vec<Found>) -> Result<()> {
+    let player = path
+        .file_stem()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string();
+    let (_, root) = nbt::read_compressed(data)?;
+    let mut inventory = count(list(&root, "Inventory"), query);
+    // Armor and offhand moved out of the inventory in 1.21.5
+    if let Some(Tag::Compound(equipment)) = root.get("equipment") {
+        inventory += count(equipment.iter().map(|x| &x.1), query);
+    }
+    let ender_chest = count(list(&root, "EnderItems"), query);
+    for (count, location) in [
+        (
+            inventory,
+            Location::Inventory {
+                player: player.clone(),
+            },
+        ),
+        (ender_chest, Location::EnderChest { player }),
+    ] {
+        if count > 0 {
+            out.push(Found { location, count });
+        }
+    }
+    Ok(())
+}
+
+/// Chunks that cannot be decoded are added to `skipped`, the others are still searched.
+fn search_region(
+    path: &Path,
+    data: &[u8],
+    dimension: Dimension,
+    query: &ItemQuery,
+    out: &mut Vec<Found>,
+    skipped: &mut Vec<String>,
+) -> Result<()> {
+    let region = Region::parse(data)?;
+    let positions = path
+        .file_name()
+        .and_then(|x| ChunkPos::in_region_file(&x.to_string_lossy()))
+        .unwrap_or_default();
+    for pos in positions {
+        let Some(chunk) = region.get(pos) else {
+            continue;
+        };
+        let root = match chunk.nbt() {
+            Ok(root) => root,
+            Err(err) => {
+                skipped.push(format!("{} {}: {:#}", dimension, pos, err));
+                continue;
+            }
+        };
+        for entity in blocks::block_entities(&root) {
+            let items = list(entity, "Items")
+                .iter()
+                .chain(SINGLE_ITEMS.iter().filter_map(|x| entity.get(x)));
+            let count = count(items, query);
+            if count == 0 {
+                continue;
+            }
+            let Some((x, y, z)) = blocks::block_entity_pos(entity) else {
+                continue;
+            };
+            out.push(Found {
+                location: Location::Container {
+                    dimension,
+                    block: blocks::id(entity).to_string(),
+                    x,
+                    y,
+                    z,
+                },
+                count,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// How many of the items match, along with the matching items they hold.
+fn count<'a>(items: impl IntoIterator<Item = &'a Tag>, query: &ItemQuery) -> u64 {
+    items
+        .into_iter()
+        .map(|item| {
+            let own = if query.matches(item) {
+                // `Count` was a byte until 1.20.5
+                item.get("count")
+                    .or(item.get("Count"))
+                    .and_then(Tag::as_i64)
+                    .unwrap_or(1)
+                    .max(0) as u64
+            } else {
+                0
+            };
+            own + count(contents(item), query)
+        })
+        .sum()
+}
+
+/// The items held by a shulker box or bundle item.
+fn contents(item: &Tag) -> Vec<&Tag> {
+    let old = [
+        item.path(&["tag", "BlockEntityTag", "Items"]),
+        item.path(&["tag", "Items"]),
+    ];
+    let bundle = item.path(&["components", "minecraft:bundle_contents"]);
+    let mut items: Vec<&Tag> = old
+        .into_iter()
+        .chain([bundle])
+        .flatten()
+        .flat_map(|x| x.as_list().unwrap_or_default())
+        .collect();
+    // Slots of a container component wrap their item
+    if let Some(slots) = item
+        .path(&["components", "minecraft:container"])
+        .and_then(Tag::as_list)
+    {
+        items.extend(slots.iter().filter_map(|x| x.get("item")));
+    }
+    items
+}
+
+/// Enchantments of an item, or stored in an enchanted book.
+fn enchantments(item: &Tag) -> Vec<&str> {
+    let mut ids = vec![];
+    for key in ["Enchantments", "StoredEnchantments"] {
+        ids.extend(
+            item.path(&["tag", key])
+                .and_then(Tag::as_list)
+                .unwrap_or_default()
+                .iter()
+                .filter_map(|x| x.get("id").and_then(Tag::as_str)),
+        );
+    }
+    for key in ["minecraft:enchantments", "minecraft:stored_enchantments"] {
+        let Some(component) = item.path(&["components", key]) else {
+            continue;
+        };
+        // Levels were wrapped in `levels` until 1.21.5
+        if let Some(Tag::Compound(levels)) = component.get("levels").or(Some(component)) {
+            ids.extend(levels.iter().map(|x| x.0.as_str()));
+        }
+    }
+    ids
+}
+
+/// The text of a text component, with its `extra` parts.
+fn text(tag: &Tag) -> String {
+    match tag {
+        Tag::String(x) => x.clone(),
+        Tag::List(x) => x.iter().map(text).collect(),
+        Tag::Compound(x) => x
+            .iter()
+            .filter(|x| x.0 == "text" || x.0 == "extra")
+            .map(|x| text(&x.1))
+            .collect(),
+        _ => String::new(),
+    }
+}
+
+fn list<'a>(tag: &'a Tag, key: &str) -> &'a [Tag] {
+    tag.get(key).and_then(Tag::as_list).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compound(entries: Vec<(&str, Tag)>) -> Tag {
+        Tag::Compound(
+            entries
+                .into_iter()
+                .map(|(k, v)| (k.to_string(), v))
+                .collect(),
+        )
+    }
+
+    fn string(value: &str) -> Tag {
+        Tag::String(value.to_string())
+    }
+
+    /// An item stack, with its count as a byte the way it was stored until 1.20.5.
+    fn old_item(id: &str, count: i8, tag: Vec<(&str, Tag)>) -> Tag {
+        compound(vec![
+            ("id", string(id)),
+            ("Count", Tag::Byte(count)),
+            ("tag", compound(tag)),
+        ])
+    }
+
+    fn item(id: &str, count: i32, components: Vec<(&str, Tag)>) -> Tag {
+        compound(vec![
+            ("id", string(id)),
+            ("count", Tag::Int(count)),
+            ("components", compound(components)),
+        ])
+    }
+
+    #[test]
+    fn counts_stacks_of_matching_items() {
+        let query = ItemQuery::new("diamond", None, None);
+        let items = [
+            item("minecraft:diamond", 5, vec![]),
+            old_item("minecraft:diamond", 3, vec![]),
+            item("minecraft:emerald", 7, vec![]),
+            compound(vec![("id", string("minecraft:diamond"))]),
+            item("minecraft:diamond", -2, vec![]),
+        ];
+        assert_eq!(count(&items, &query), 9);
+        assert_eq!(count(&[], &query), 0);
+    }
+
+    #[test]
+    fn counts_items_in_shulker_boxes_and_bundles() {
+        let query = ItemQuery::new("minecraft:diamond", None, None);
+        let old_box = old_item(
+            "minecraft:shulker_box",
+            1,
+            vec![(
+                "BlockEntityTag",
+                compound(vec![(
+                    "Items",
+                    Tag::List(vec![old_item("minecraft:diamond", 64, vec![])]),
+                )]),
+            )],
+        );
+        let slot = |item| compound(vec![("slot", Tag::Int(0)), ("item", item)]);
+        let bundle = item(
+            "minecraft:bundle",
+            1,
+            vec![(
+                "minecraft:bundle_contents",
+                Tag::List(vec![item("minecraft:diamond", 2, vec![])]),
+            )],
+        );
+        let new_box = item(
+            "minecraft:shulker_box",
+            1,
+            vec![(
+                "minecraft:container",
+                Tag::List(vec![
+                    slot(item("minecraft:diamond", 10, vec![])),
+                    slot(bundle),
+                ]),
+            )],
+        );
+        assert_eq!(count(&[old_box, new_box], &query), 76);
+    }
+
+    #[test]
+    fn reads_enchantments_of_every_version() {
+        let enchantment = |id| compound(vec![("id", string(id)), ("lvl", Tag::Short(1))]);
+        let old_sword = old_item(
+            "minecraft:diamond_sword",
+            1,
+            vec![(
+                "Enchantments",
+                Tag::List(vec![enchantment("minecraft:sharpness")]),
+            )],
+        );
+        assert_eq!(enchantments(&old_sword), ["minecraft:sharpness"]);
+        let old_book = old_item(
+            "minecraft:enchanted_book",
+            1,
+            vec![(
+                "StoredEnchantments",
+                Tag::List(vec![enchantment("minecraft:mending")]),
+            )],
+        );
+        assert_eq!(enchantments(&old_book), ["minecraft:mending"]);
+
+        let levels = compound(vec![
+            ("minecraft:sharpness", Tag::Int(5)),
+            ("minecraft:looting", Tag::Int(3)),
+        ]);
+        let sword = item(
+            "minecraft:diamond_sword",
+            1,
+            vec![(
+                "minecraft:enchantments",
+                compound(vec![("levels", levels.clone())]),
+            )],
+        );
+        assert_eq!(
+            enchantments(&sword),
+            ["minecraft:sharpness", "minecraft:looting"]
+        );
+        // Without the `levels` wrapper since 1.21.5
+        let book = item(
+            "minecraft:enchanted_book",
+            1,
+            vec![("minecraft:stored_enchantments", levels)],
+        );
+        assert_eq!(
+            enchantments(&book),
+            ["minecraft:sharpness", "minecraft:looting"]
+        );
+        assert!(enchantments(&item("minecraft:diamond_sword", 1, vec![])).is_empty());
+    }
+
+    #[test]
+    fn queries_by_name_and_enchantment() {
+        let sword = item(
+            "minecraft:diamond_sword",
+            1,
+            vec![
+                (
+                    "minecraft:custom_name",
+                    compound(vec![
+                        ("text", string("Old ")),
+                        ("extra", Tag::List(vec![string("Faithful")])),
+                    ]),
+                ),
+                (
+                    "minecraft:enchantments",
+                    compound(vec![("minecraft:mending", Tag::Int(1))]),
+                ),
+            ],
+        );
+        let items = [sword];
+        let query = |name, enchantment| ItemQuery::new("diamond_sword", name, enchantment);
+        assert_eq!(count(&items, &query(Some("faithful"), None)), 1);
+        assert_eq!(
+            count(&items, &query(Some("old faithful"), Some("mending"))),
+            1
+        );
+        assert_eq!(count(&items, &query(Some("excalibur"), None)), 0);
+        assert_eq!(count(&items, &query(None, Some("sharpness"))), 0);
+
+        // A JSON text before 1.21.5
+        let old_sword = old_item(
+            "minecraft:diamond_sword",
+            1,
+            vec![(
+                "display",
+                compound(vec![("Name", string(r#"{"text":"Old Faithful"}"#))]),
+            )],
+        );
+        assert_eq!(count(&[old_sword], &query(Some("FAITHFUL"), None)), 1);
+    }
+}
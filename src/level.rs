@@ -0,0 +1,116 @@
+use std::path::Path;
+
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, Local, TimeZone};
+use serde::{Deserialize, Serialize};
+
+use crate::nbt::{self, Tag};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GameMode {
+    Survival,
+    Creative,
+    Adventure,
+    Spectator,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Difficulty {
+    Peaceful,
+    Easy,
+    Normal,
+    Hard,
+}
+
+/// What `level.dat` says about a world. Fields missing from older or newer versions of the
+/// format are left empty.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WorldInfo {
+    pub level_name: Option<String>,
+    pub game_mode: Option<GameMode>,
+    pub difficulty: Option<Difficulty>,
+    pub hardcore: bool,
+    pub data_version: Option<i32>,
+    /// Version of the game that last saved the world, e.g. `1.20.4`
+    pub version_name: Option<String>,
+    pub last_played: Option<DateTime<Local>>,
+    pub seed: Option<i64>,
+    /// Ticks since the world was created, 24000 per day
+    pub day_time: Option<i64>,
+    pub datapacks: Vec<String>,
+}
+
+impl WorldInfo {
+    /// Read `level.dat` in the world directory `source`.
+    pub fn read(source: &Path) -> Result<Self> {
+        let (_, root) = nbt::read_file(&source.join("level.dat"))?;
+        Self::from_nbt(&root)
+    }
+
+    /// Rename the world in `level.dat`, which is the name shown in Minecraft's world list.
+    pub fn set_level_name(source: &Path, name: &str) -> Result<()> {
+        let path = source.join("level.dat");
+        let (root_name, mut root) = nbt::read_file(&path)?;
+        root.get_mut("Data")
+            .ok_or(anyhow!("level.dat has no Data"))?
+            .insert("LevelName", Tag::String(name.to_string()));
+        nbt::write_file(&path, &root_name, &root)
+    }
+
+    /// Whether both describe the same world, possibly at another time. Only the seed and name
+    /// are compared, the rest changes while playing.
+    pub fn same_world(&self, other: &WorldInfo) -> bool {
+        self.seed.is_some() && self.seed == other.seed && self.level_name == other.level_name
+    }
+
+    pub fn from_nbt(root: &Tag) -> Result<Self> {
+        let data = root.get("Data").ok_or(anyhow!("level.dat has no Data"))?;
+        let int = |keys: &[&str]| data.path(keys).and_then(Tag::as_i64);
+        let string = |keys: &[&str]| data.path(keys).and_then(Tag::as_str).map(str::to_string);
+        Ok(Self {
+            level_name: string(&["LevelName"]),
+            game_mode: int(&["GameType"]).and_then(|x| match x {
+                0 => Some(GameMode::Survival),
+                1 => Some(GameMode::Creative),
+                2 => Some(GameMode::Adventure),
+                3 => Some(GameMode::Spectator),
+                _ => None,
+            }),
+            difficulty: int(&["Difficulty"])
+                .or_else(|| {
+                    // Newer versions keep it by name next to the hardcore flag
+                    let name = string(&["difficulty_settings", "difficulty"])?;
+                    ["peaceful", "easy", "normal", "hard"]
+                        .iter()
+                        .position(|x| *x == name)
+                        .map(|x| x as i64)
+                })
+                .and_then(|x| match x {
+                    0 => Some(Difficulty::Peaceful),
+                    1 => Some(Difficulty::Easy),
+                    2 => Some(Difficulty::Normal),
+                    3 => Some(Difficulty::Hard),
+                    _ => None,
+                }),
+            hardcore: int(&["hardcore"])
+                .or_else(|| int(&["difficulty_settings", "hardcore"]))
+                .is_some_and(|x| x != 0),
+            data_version: int(&["DataVersion"]).and_then(|x| x.try_into().ok()),
+            version_name: string(&["Version", "Name"]),
+            last_played: int(&["LastPlayed"]).and_then(|x| Local.timestamp_millis_opt(x).single()),
+            // Moved into the world generation settings by 1.16
+            seed: int(&["WorldGenSettings", "seed"]).or_else(|| int(&["RandomSeed"])),
+            day_time: int(&["DayTime"]),
+            datapacks: data
+                .path(&["DataPacks", "Enabled"])
+                .and_then(Tag::as_list)
+                .unwrap_or_default()
+                .iter()
+                .filter_map(|x| x.as_str().map(str::to_string))
+                .collect(),
+        })
+    }
+}
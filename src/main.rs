@@ -5,23 +5,29 @@ extern crate rust_i18n;
 extern crate log;
 
 use std::{
-    fs::OpenOptions,
-    io::Sink,
-    panic,
-    path::PathBuf,
-    sync::LazyLock,
-    thread::{self, sleep},
-    time::{Duration, SystemTime},
+    collections::HashMap, fs::OpenOptions, io::Sink, panic, path::PathBuf, process::ExitCode,
+    sync::LazyLock, thread::sleep, time::Duration,
 };
 
+use chrono::{DateTime, Local};
 use clap::Parser;
 use env_logger::Target;
 use rustic_core::SnapshotOptions;
 
-use crate::{backup::AppState, settings::Settings, utils::report_err};
+use crate::{backup::AppState, utils::report_err};
 
 mod backup;
+mod blocks;
 mod cli;
+mod diff;
+mod game;
+mod items;
+mod level;
+mod nbt;
+mod players;
+mod progress;
+mod region;
+mod scheduler;
 mod settings;
 mod tasks;
 mod ui;
@@ -45,26 +51,26 @@ pub static MINESAVE_DATA_HOME: LazyLock<PathBuf> = LazyLock::new(|| {
         .to_path_buf()
 });
 
-fn main() {
+fn main() -> ExitCode {
     setup_logger();
     rust_i18n::set_locale(&sys_locale::get_locale().unwrap_or_else(|| String::from("en-US")));
 
     let res = panic::catch_unwind(|| {
         AppState::instance().reload();
         let cli = cli::Cli::parse();
-        if let None = cli.command {
-            ui::run_app();
-        } else {
-            match cli.command.unwrap() {
-                cli::Command::Daemon => daemon(),
+        match cli.command {
+            None => {
+                ui::run_app();
+                ExitCode::SUCCESS
             }
+            Some(command) => command.run(cli.json),
         }
     });
 
     AppState::instance().save().unwrap_or_default();
     tasks::wait_all();
 
-    res.unwrap();
+    res.unwrap()
 }
 
 #[inline]
@@ -92,21 +98,39 @@ fn setup_logger() {
 }
 
 fn daemon() {
+    let mut attempts: HashMap<String, DateTime<Local>> = HashMap::new();
     loop {
-        sleep(Duration::from_secs(30));
-        if SystemTime::now().elapsed().unwrap().as_secs()
-            % (Settings::instance().daemon_backup_duration as u64)
-            > 30
+        let now = Local::now();
+        let mut backed_up = vec![];
+        for id in scheduler::due_saves(now) {
+            if attempts
+                .get(&id)
+                .is_some_and(|x| now - *x < scheduler::RETRY_DELAY)
+            {
+                continue;
+            }
+            attempts.insert(id.clone(), now);
+            let task = tasks::spawn(
+                t!("auto-backup").to_string(),
+                tasks::TaskInfo::Backup {
+                    for_id: Some(id.clone()),
+                    options: SnapshotOptions::default().label(t!("auto-backup").to_string()),
+                },
+            );
+            backed_up.push((task, id));
+        }
+        // Only saves that were backed up are pruned, so a failing one keeps its old snapshots
+        let succeeded = tasks::wait_all();
+        for (_, id) in backed_up
+            .into_iter()
+            .filter(|(task, _)| succeeded.contains(task))
         {
-            continue;
+            tasks::spawn(
+                t!("auto-forget").to_string(),
+                tasks::TaskInfo::Forget { id, prune: true },
+            );
         }
-        tasks::spawn(
-            t!("auto-backup").to_string(),
-            tasks::TaskInfo::Backup {
-                for_id: None,
-                options: SnapshotOptions::default().label(t!("auto-backup").to_string()),
-            },
-        );
         tasks::wait_all();
+        sleep(Duration::from_secs(30));
     }
 }
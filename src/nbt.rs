@@ -0,0 +1,451 @@
+use std::{
+    fs,
+    io::{Read, Write},
+    path::Path,
+};
+
+use anyhow::{Result, bail};
+use flate2::{
+    Compression,
+    read::{GzDecoder, ZlibDecoder},
+    write::GzEncoder,
+};
+
+/// Deeper nesting is refused rather than risking a stack overflow on broken files.
+const MAX_DEPTH: usize = 512;
+
+/// A value of Minecraft's Named Binary Tag format.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Tag {
+    Byte(i8),
+    Short(i16),
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    ByteArray(Vec<i8>),
+    String(String),
+    List(Vec<Tag>),
+    /// Entries in file order
+    Compound(Vec<(String, Tag)>),
+    IntArray(Vec<i32>),
+    LongArray(Vec<i64>),
+}
+
+impl Tag {
+    /// The entry `key` of a compound.
+    pub fn get(&self, key: &str) -> Option<&Tag> {
+        match self {
+            Tag::Compound(entries) => entries.iter().find(|x| x.0 == key).map(|x| &x.1),
+            _ => None,
+        }
+    }
+
+    /// Follow `keys` through nested compounds.
+    pub fn path(&self, keys: &[&str]) -> Option<&Tag> {
+        keys.iter().try_fold(self, |tag, key| tag.get(key))
+    }
+
+    /// Any integer type, widened.
+    pub fn as_i64(&self) -> Option<i64> {
+        match *self {
+            Tag::Byte(x) => Some(x.into()),
+            Tag::Short(x) => Some(x.into()),
+            Tag::Int(x) => Some(x.into()),
+            Tag::Long(x) => Some(x),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Tag::String(x) => Some(x),
+            _ => None,
+        }
+    }
+
+    pub fn as_list(&self) -> Option<&[Tag]> {
+        match self {
+            Tag::List(x) => Some(x),
+            _ => None,
+        }
+    }
+
+    pub fn get_mut(&mut self, key: &str) -> Option<&mut Tag> {
+        match self {
+            Tag::Compound(entries) => entries.iter_mut().find(|x| x.0 == key).map(|x| &mut x.1),
+            _ => None,
+        }
+    }
+
+    /// Set the entry `key` of a compound, keeping its position if it exists. Does nothing on
+    /// other tags.
+    pub fn insert(&mut self, key: &str, value: Tag) {
+        if let Tag::Compound(entries) = self {
+            match entries.iter_mut().find(|x| x.0 == key) {
+                Some(entry) => entry.1 = value,
+                None => entries.push((key.to_string(), value)),
+            }
+        }
+    }
+
+    fn id(&self) -> u8 {
+        match self {
+            Tag::Byte(_) => 1,
+            Tag::Short(_) => 2,
+            Tag::Int(_) => 3,
+            Tag::Long(_) => 4,
+            Tag::Float(_) => 5,
+            Tag::Double(_) => 6,
+            Tag::ByteArray(_) => 7,
+            Tag::String(_) => 8,
+            Tag::List(_) => 9,
+            Tag::Compound(_) => 10,
+            Tag::IntArray(_) => 11,
+            Tag::LongArray(_) => 12,
+        }
+    }
+}
+
+/// Read an uncompressed NBT document, returning the name and value of its root tag.
+pub fn read(data: &[u8]) -> Result<(String, Tag)> {
+    let mut reader = Reader { data, pos: 0 };
+    let id = reader.u8()?;
+    if id == 0 {
+        bail!("Empty NBT document");
+    }
+    let name = reader.string()?;
+    let tag = reader.payload(id, 0)?;
+    Ok((name, tag))
+}
+
+/// Read an NBT document compressed with gzip or zlib, or not at all, as found in `level.dat`,
+/// player data and region files.
+pub fn read_compressed(data: &[u8]) -> Result<(String, Tag)> {
+    let mut decompressed = vec![];
+    match data {
+        [0x1f, 0x8b, ..] => {
+            GzDecoder::new(data).read_to_end(&mut decompressed)?;
+        }
+        [0x78, ..] => {
+            ZlibDecoder::new(data).read_to_end(&mut decompressed)?;
+        }
+        _ => return read(data),
+    }
+    read(&decompressed)
+}
+
+pub fn read_file(path: &Path) -> Result<(String, Tag)> {
+    read_compressed(&fs::read(path)?)
+}
+
+/// Write an uncompressed NBT document with a root tag named `name`.
+pub fn write(name: &str, tag: &Tag) -> Result<Vec<u8>> {
+    let mut out = vec![tag.id()];
+    write_string(&mut out, name)?;
+    write_payload(&mut out, tag)?;
+    Ok(out)
+}
+
+/// Write a gzip-compressed NBT document, the way Minecraft stores `level.dat` and player
+/// data.
+pub fn write_file(path: &Path, name: &str, tag: &Tag) -> Result<()> {
+    let mut encoder = GzEncoder::new(vec![], Compression::default());
+    encoder.write_all(&write(name, tag)?)?;
+    fs::write(path, encoder.finish()?)?;
+    Ok(())
+}
+
+fn write_string(out: &mut Vec<u8>, value: &str) -> Result<()> {
+    let bytes = encode_mutf8(value);
+    let Ok(len) = u16::try_from(bytes.len()) else {
+        bail!("NBT string of {} bytes is too long", bytes.len());
+    };
+    out.extend(len.to_be_bytes());
+    out.extend(bytes);
+    Ok(())
+}
+
+/// Java's modified UTF-8: NUL takes two bytes, and supplementary characters are written as
+/// their two UTF-16 surrogates of three bytes each.
+fn encode_mutf8(value: &str) -> Vec<u8> {
+    let mut out = Vec::with_capacity(value.len());
+    for unit in value.encode_utf16() {
+        match unit {
+            0x01..=0x7f => out.push(unit as u8),
+            0 | 0x80..=0x7ff => out.extend([0xc0 | (unit >> 6) as u8, 0x80 | (unit & 0x3f) as u8]),
+            _ => out.extend([
+                0xe0 | (unit >> 12) as u8,
+                0x80 | ((unit >> 6) & 0x3f) as u8,
+                0x80 | (unit & 0x3f) as u8,
+            ]),
+        }
+    }
+    out
+}
+
+/// The reverse of `encode_mutf8`, also taking the four-byte sequences of plain UTF-8. Invalid
+/// sequences and unpaired surrogates, which a `String` cannot hold, are replaced.
+fn decode_mutf8(bytes: &[u8]) -> String {
+    let mut units = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let lead = bytes[i] as u32;
+        let (len, init) = match lead {
+            0x00..=0x7f => (1, lead),
+            0xc0..=0xdf => (2, lead & 0x1f),
+            0xe0..=0xef => (3, lead & 0x0f),
+            0xf0..=0xf7 => (4, lead & 0x07),
+            _ => (0, 0),
+        };
+        let tail = bytes
+            .get(i + 1..i + len)
+            .filter(|x| len > 0 && x.len() + 1 == len && x.iter().all(|x| x & 0xc0 == 0x80));
+        let Some(tail) = tail else {
+            units.push(0xfffd);
+            i += 1;
+            continue;
+        };
+        let code = tail
+            .iter()
+            .fold(init, |acc, x| acc << 6 | (x & 0x3f) as u32);
+        if len == 4 {
+            let c = char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER);
+            units.extend(c.encode_utf16(&mut [0; 2]).iter());
+        } else {
+            units.push(code as u16);
+        }
+        i += len;
+    }
+    String::from_utf16_lossy(&units)
+}
+
+fn write_len(out: &mut Vec<u8>, len: usize) -> Result<()> {
+    let Ok(len) = i32::try_from(len) else {
+        bail!("NBT length {} is too large", len);
+    };
+    out.extend(len.to_be_bytes());
+    Ok(())
+}
+
+fn write_payload(out: &mut Vec<u8>, tag: &Tag) -> Result<()> {
+    match tag {
+        Tag::Byte(x) => out.extend(x.to_be_bytes()),
+        Tag::Short(x) => out.extend(x.to_be_bytes()),
+        Tag::Int(x) => out.extend(x.to_be_bytes()),
+        Tag::Long(x) => out.extend(x.to_be_bytes()),
+        Tag::Float(x) => out.extend(x.to_be_bytes()),
+        Tag::Double(x) => out.extend(x.to_be_bytes()),
+        Tag::ByteArray(x) => {
+            write_len(out, x.len())?;
+            out.extend(x.iter().flat_map(|x| x.to_be_bytes()));
+        }
+        Tag::String(x) => write_string(out, x)?,
+        Tag::List(x) => {
+            let id = x.first().map_or(0, Tag::id);
+            if x.iter().any(|x| x.id() != id) {
+                bail!("NBT list mixes tag types");
+            }
+            out.push(id);
+            write_len(out, x.len())?;
+            x.iter().try_for_each(|x| write_payload(out, x))?;
+        }
+        Tag::Compound(entries) => {
+            for (name, value) in entries {
+                out.push(value.id());
+                write_string(out, name)?;
+                write_payload(out, value)?;
+            }
+            out.push(0);
+        }
+        Tag::IntArray(x) => {
+            write_len(out, x.len())?;
+            out.extend(x.iter().flat_map(|x| x.to_be_bytes()));
+        }
+        Tag::LongArray(x) => {
+            write_len(out, x.len())?;
+            out.extend(x.iter().flat_map(|x| x.to_be_bytes()));
+        }
+    }
+    Ok(())
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl Reader<'_> {
+    fn take<const N: usize>(&mut self) -> Result<[u8; N]> {
+        let Some(bytes) = self.data.get(self.pos..self.pos + N) else {
+            bail!("Unexpected end of NBT data at {}", self.pos);
+        };
+        self.pos += N;
+        Ok(bytes.try_into().expect("Slice has the requested length"))
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.take::<1>()?[0])
+    }
+
+    /// A length prefix, which must fit in the remaining data to be plausible.
+    fn len(&mut self, item_size: usize) -> Result<usize> {
+        let len = i32::from_be_bytes(self.take()?);
+        let len = usize::try_from(len).unwrap_or_default();
+        if len * item_size > self.data.len() - self.pos {
+            bail!("NBT length {} out of bounds at {}", len, self.pos);
+        }
+        Ok(len)
+    }
+
+    fn string(&mut self) -> Result<String> {
+        let len = u16::from_be_bytes(self.take()?) as usize;
+        let Some(bytes) = self.data.get(self.pos..self.pos + len) else {
+            bail!("Unexpected end of NBT data at {}", self.pos);
+        };
+        self.pos += len;
+        Ok(decode_mutf8(bytes))
+    }
+
+    fn payload(&mut self, id: u8, depth: usize) -> Result<Tag> {
+        if depth > MAX_DEPTH {
+            bail!("NBT nested too deeply");
+        }
+        Ok(match id {
+            1 => Tag::Byte(i8::from_be_bytes(self.take()?)),
+            2 => Tag::Short(i16::from_be_bytes(self.take()?)),
+            3 => Tag::Int(i32::from_be_bytes(self.take()?)),
+            4 => Tag::Long(i64::from_be_bytes(self.take()?)),
+            5 => Tag::Float(f32::from_be_bytes(self.take()?)),
+            6 => Tag::Double(f64::from_be_bytes(self.take()?)),
+            7 => {
+                let len = self.len(1)?;
+                Tag::ByteArray(
+                    (0..len)
+                        .map(|_| Ok(i8::from_be_bytes(self.take()?)))
+                        .collect::<Result<_>>()?,
+                )
+            }
+            8 => Tag::String(self.string()?),
+            9 => {
+                let item = self.u8()?;
+                let len = self.len(1)?;
+                if item == 0 && len > 0 {
+                    bail!("NBT list of end tags at {}", self.pos);
+                }
+                Tag::List(
+                    (0..len)
+                        .map(|_| self.payload(item, depth + 1))
+                        .collect::<Result<_>>()?,
+                )
+            }
+            10 => {
+                let mut entries = vec![];
+                loop {
+                    let id = self.u8()?;
+                    if id == 0 {
+                        break;
+                    }
+                    let name = self.string()?;
+                    entries.push((name, self.payload(id, depth + 1)?));
+                }
+                Tag::Compound(entries)
+            }
+            11 => {
+                let len = self.len(4)?;
+                Tag::IntArray(
+                    (0..len)
+                        .map(|_| Ok(i32::from_be_bytes(self.take()?)))
+                        .collect::<Result<_>>()?,
+                )
+            }
+            12 => {
+                let len = self.len(8)?;
+                Tag::LongArray(
+                    (0..len)
+                        .map(|_| Ok(i64::from_be_bytes(self.take()?)))
+                        .collect::<Result<_>>()?,
+                )
+            }
+            id => bail!("Unknown NBT tag {} at {}", id, self.pos),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mutf8_encodes_nul_and_supplementary_characters() {
+        assert_eq!(encode_mutf8("a\0b"), [b'a', 0xc0, 0x80, b'b']);
+        assert_eq!(encode_mutf8("é"), "é".as_bytes());
+        assert_eq!(encode_mutf8("€"), "€".as_bytes());
+        // U+1F600 as the surrogates D83D DE00
+        assert_eq!(
+            encode_mutf8("\u{1f600}"),
+            [0xed, 0xa0, 0xbd, 0xed, 0xb8, 0x80]
+        );
+    }
+
+    #[test]
+    fn mutf8_round_trips() {
+        for value in [
+            "",
+            "New World",
+            "a\0b",
+            "Sélection §6€",
+            "\u{1f600} world \u{10ffff}",
+        ] {
+            assert_eq!(decode_mutf8(&encode_mutf8(value)), value);
+        }
+    }
+
+    #[test]
+    fn mutf8_reads_plain_utf8_and_replaces_invalid_bytes() {
+        assert_eq!(decode_mutf8("\u{1f600}".as_bytes()), "\u{1f600}");
+        assert_eq!(
+            decode_mutf8(&[b'a', 0xff, b'b', 0xe2, 0x82]),
+            "a\u{fffd}b\u{fffd}\u{fffd}"
+        );
+        // An unpaired surrogate
+        assert_eq!(decode_mutf8(&[0xed, 0xa0, 0xbd]), "\u{fffd}");
+    }
+
+    #[test]
+    fn documents_round_trip() {
+        let tag = Tag::Compound(vec![(
+            "Data".to_string(),
+            Tag::Compound(vec![
+                (
+                    "LevelName".to_string(),
+                    Tag::String("M\0n \u{1f600}".to_string()),
+                ),
+                ("version".to_string(), Tag::Int(19133)),
+                ("LastPlayed".to_string(), Tag::Long(1_700_000_000_000)),
+                (
+                    "Names".to_string(),
+                    Tag::List(vec![
+                        Tag::String("ä".to_string()),
+                        Tag::String("\u{10400}".to_string()),
+                    ]),
+                ),
+                ("Bytes".to_string(), Tag::ByteArray(vec![-1, 0, 1])),
+                (
+                    "Longs".to_string(),
+                    Tag::LongArray(vec![i64::MIN, i64::MAX]),
+                ),
+            ]),
+        )]);
+        let data = write("r\u{f6}\u{f6}t", &tag).unwrap();
+        assert_eq!(read(&data).unwrap(), ("r\u{f6}\u{f6}t".to_string(), tag));
+    }
+
+    #[test]
+    fn string_length_counts_encoded_bytes() {
+        let mut out = vec![];
+        write_string(&mut out, "\0\u{1f600}").unwrap();
+        assert_eq!(&out[..2], &8u16.to_be_bytes());
+        assert!(write_string(&mut vec![], &"\0".repeat(u16::MAX as usize / 2 + 1)).is_err());
+    }
+}
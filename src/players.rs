@@ -0,0 +1,484 @@
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, HashMap},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Result, bail};
+use chrono::{DateTime, Local};
+use rustic_core::repofile::SnapshotFile;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    MINESAVE_DATA_HOME,
+    backup::SaveBackupConfiguration,
+    nbt::{self, Tag},
+    utils::report_err,
+};
+
+/// Names of the players who joined, kept by servers and the launcher next to their worlds.
+const USERCACHE: &str = "usercache.json";
+
+/// Directory in the data directory holding the roster of each snapshot, as
+/// `<save id>/<snapshot id>.json`.
+const ROSTERS_DIR: &str = "rosters";
+
+#[derive(Debug, Deserialize)]
+struct CachedUser {
+    name: String,
+    uuid: String,
+}
+
+/// The files holding the progress of a player, relative to the world: inventory, position and
+/// ender chest, then advancements and statistics.
+pub fn player_files(uuid: &str) -> [PathBuf; 3] {
+    [
+        PathBuf::from("playerdata").join(format!("{}.dat", uuid)),
+        PathBuf::from("advancements").join(format!("{}.json", uuid)),
+        PathBuf::from("stats").join(format!("{}.json", uuid)),
+    ]
+}
+
+/// A UUID in its hyphenated, lowercase form, with or without hyphens given.
+pub fn parse_uuid(s: &str) -> Option<String> {
+    let hex: String = s.trim().chars().filter(|x| *x != '-').collect();
+    if hex.len() != 32 || !hex.chars().all(|x| x.is_ascii_hexdigit()) {
+        return None;
+    }
+    let hex = hex.to_lowercase();
+    Some(format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    ))
+}
+
+/// Player names by UUID from the `usercache.json` nearest to the world: in it, in the server
+/// directory holding it, or in the `.minecraft` directory holding `saves/`.
+pub fn usercache(world: &Path) -> BTreeMap<String, String> {
+    let Some(path) = world
+        .ancestors()
+        .take(3)
+        .map(|x| x.join(USERCACHE))
+        .find(|x| x.is_file())
+    else {
+        return BTreeMap::new();
+    };
+    let users: Vec<CachedUser> = match fs::read(&path)
+        .map_err(anyhow::Error::from)
+        .and_then(|x| Ok(serde_json::from_slice(&x)?))
+    {
+        Ok(users) => users,
+        Err(err) => {
+            warn!("Failed to read {}: {:#}", path.display(), err);
+            return BTreeMap::new();
+        }
+    };
+    users
+        .into_iter()
+        .filter_map(|x| Some((parse_uuid(&x.uuid)?, x.name)))
+        .collect()
+}
+
+/// The UUID of a player given by UUID, or by name as found in `usercache.json`.
+pub fn resolve(world: &Path, player: &str) -> Result<String> {
+    if let Some(uuid) = parse_uuid(player) {
+        return Ok(uuid);
+    }
+    match usercache(world)
+        .into_iter()
+        .find(|(_, name)| name.eq_ignore_ascii_case(player.trim()))
+    {
+        Some((uuid, _)) => Ok(uuid),
+        None => bail!(
+            "Player {} is not in {}, give their UUID instead",
+            player,
+            USERCACHE
+        ),
+    }
+}
+
+/// What a world knows about a player who joined it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlayerInfo {
+    pub uuid: String,
+    pub name: Option<String>,
+    /// When the game last saved the player data, while they were playing
+    pub last_seen: Option<DateTime<Local>>,
+    /// In seconds, from the statistics
+    pub play_time: Option<u64>,
+    /// Like `minecraft:the_nether`
+    pub dimension: Option<String>,
+    pub position: Option<[f64; 3]>,
+}
+
+/// The players of a snapshot.
+#[derive(Debug, Clone, Serialize)]
+pub struct SnapshotRoster {
+    pub snapshot: String,
+    pub time: DateTime<Local>,
+    pub label: String,
+    pub players: Vec<PlayerInfo>,
+}
+
+/// The players of a world as it is now, last seen first.
+pub fn roster(world: &Path) -> Result<Vec<PlayerInfo>> {
+    let names = usercache(world);
+    let dir = world.join("playerdata");
+    let mut players = vec![];
+    if !dir.is_dir() {
+        return Ok(players);
+    }
+    for entry in fs::read_dir(&dir)? {
+        let entry = entry?;
+        let Some(uuid) = uuid_of_player_file(&entry.path()) else {
+            continue;
+        };
+        let [_, _, stats] = player_files(&uuid);
+        let last_seen = entry.metadata().and_then(|x| x.modified()).ok();
+        let res = fs::read(entry.path())
+            .map_err(anyhow::Error::from)
+            .and_then(|data| {
+                player_info(
+                    &uuid,
+                    &names,
+                    &data,
+                    fs::read(world.join(stats)).ok().as_deref(),
+                    last_seen.map(DateTime::from),
+                )
+            });
+        match res {
+            Ok(player) => players.push(player),
+            Err(err) => warn!("Failed to read player {}: {:#}", uuid, err),
+        }
+    }
+    players.sort_by_key(|x| (Reverse(x.last_seen), x.uuid.clone()));
+    Ok(players)
+}
+
+/// The players of each snapshot, as recorded when it was taken. Snapshots taken before rosters
+/// were recorded have theirs read from the player data and statistics kept in them, with names
+/// from the `usercache.json` near the world as it is now, and recorded then.
+pub fn snapshot_rosters(
+    save: &SaveBackupConfiguration,
+    snapshots: &[SnapshotFile],
+) -> Result<Vec<SnapshotRoster>> {
+    let stored: Vec<_> = snapshots
+        .iter()
+        .map(|x| stored_roster(save.id(), &x.id.to_string()))
+        .collect();
+    let missing: Vec<SnapshotFile> = snapshots
+        .iter()
+        .zip(&stored)
+        .filter(|(_, players)| players.is_none())
+        .map(|(snapshot, _)| snapshot.clone())
+        .collect();
+    let mut read: HashMap<String, Vec<PlayerInfo>> = HashMap::new();
+    if !missing.is_empty() {
+        for roster in read_rosters(save, &missing)? {
+            if let Err(err) = store_roster(save.id(), &roster.snapshot, &roster.players) {
+                warn!("Failed to record roster of {}: {:#}", roster.snapshot, err);
+            }
+            read.insert(roster.snapshot, roster.players);
+        }
+    }
+    Ok(snapshots
+        .iter()
+        .zip(stored)
+        .map(|(snapshot, players)| {
+            let id = snapshot.id.to_string();
+            SnapshotRoster {
+                players: players.or_else(|| read.remove(&id)).unwrap_or_default(),
+                snapshot: id,
+                time: snapshot.time,
+                label: snapshot.label.clone(),
+            }
+        })
+        .collect())
+}
+
+/// Record the players of a world in a snapshot just taken of it.
+pub fn record_roster(save_id: &str, snapshot: &SnapshotFile, players: &[PlayerInfo]) {
+    if let Err(err) = store_roster(save_id, &snapshot.id.to_string(), players) {
+        warn!("Failed to record roster of {}: {:#}", snapshot.id, err);
+    }
+}
+
+/// Drop the recorded rosters of forgotten snapshots, or of every snapshot without any given.
+pub fn forget_rosters(save_id: &str, snapshots: Option<&[SnapshotFile]>) {
+    let dir = MINESAVE_DATA_HOME.join(ROSTERS_DIR).join(save_id);
+    let res = match snapshots {
+        Some(snapshots) => snapshots.iter().try_for_each(|x| {
+            let path = roster_file(save_id, &x.id.to_string());
+            if path.exists() {
+                fs::remove_file(path)?;
+            }
+            Ok(())
+        }),
+        None if dir.exists() => fs::remove_dir_all(dir),
+        None => Ok(()),
+    };
+    res.inspect_err(report_err("Failed to forget rosters"))
+        .unwrap_or_default();
+}
+
+fn roster_file(save_id: &str, snapshot: &str) -> PathBuf {
+    MINESAVE_DATA_HOME
+        .join(ROSTERS_DIR)
+        .join(save_id)
+        .join(format!("{}.json", snapshot))
+}
+
+fn store_roster(save_id: &str, snapshot: &str, players: &[PlayerInfo]) -> Result<()> {
+    let path = roster_file(save_id, snapshot);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, serde_json::to_vec(players)?)?;
+    Ok(())
+}
+
+fn stored_roster(save_id: &str, snapshot: &str) -> Option<Vec<PlayerInfo>> {
+    let path = roster_file(save_id, snapshot);
+    let data = fs::read(&path).ok()?;
+    serde_json::from_slice(&data)
+        .inspect_err(|err| warn!("Failed to read {}: {:#}", path.display(), err))
+        .ok()
+}
+
+/// The players of each snapshot, from the player data and statistics kept in it.
+fn read_rosters(
+    save: &SaveBackupConfiguration,
+    snapshots: &[SnapshotFile],
+) -> Result<Vec<SnapshotRoster>> {
+    let names = usercache(save.source());
+    let mut modified: Vec<HashMap<PathBuf, DateTime<Local>>> = vec![];
+    let mut paths = vec![];
+    for snapshot in snapshots {
+        let mut times = HashMap::new();
+        for entry in save.list_snapshot(snapshot)? {
+            if let Some(uuid) = uuid_of_player_file(&entry.path) {
+                paths.extend(player_files(&uuid));
+                if let Some(time) = entry.modified {
+                    times.insert(entry.path, time);
+                }
+            }
+        }
+        modified.push(times);
+    }
+    paths.sort();
+    paths.dedup();
+
+    let mut rosters = vec![];
+    save.for_each_files(snapshots, &paths, |i, data| {
+        let files: HashMap<&PathBuf, _> = paths
+            .iter()
+            .zip(data)
+            .filter_map(|(path, data)| Some((path, data?)))
+            .collect();
+        let mut players = vec![];
+        for (path, data) in &files {
+            let Some(uuid) = uuid_of_player_file(path) else {
+                continue;
+            };
+            let [_, _, stats] = player_files(&uuid);
+            match player_info(
+                &uuid,
+                &names,
+                data,
+                files.get(&stats).map(|x| x.as_slice()),
+                modified[i].get(*path).copied(),
+            ) {
+                Ok(player) => players.push(player),
+                Err(err) => warn!("Failed to read player {}: {:#}", uuid, err),
+            }
+        }
+        players.sort_by_key(|x| (Reverse(x.last_seen), x.uuid.clone()));
+        let snapshot = &snapshots[i];
+        rosters.push(SnapshotRoster {
+            snapshot: snapshot.id.to_string(),
+            time: snapshot.time,
+            label: snapshot.label.clone(),
+            players,
+        });
+        Ok(())
+    })?;
+    Ok(rosters)
+}
+
+/// The UUID of `playerdata/<uuid>.dat`.
+fn uuid_of_player_file(path: &Path) -> Option<String> {
+    if path.parent()?.file_name()? != "playerdata" {
+        return None;
+    }
+    parse_uuid(path.file_name()?.to_str()?.strip_suffix(".dat")?)
+}
+
+fn player_info(
+    uuid: &str,
+    names: &BTreeMap<String, String>,
+    playerdata: &[u8],
+    stats: Option<&[u8]>,
+    last_seen: Option<DateTime<Local>>,
+) -> Result<PlayerInfo> {
+    let (_, root) = nbt::read_compressed(playerdata)?;
+    let position = match root.get("Pos").and_then(Tag::as_list) {
+        Some([Tag::Double(x), Tag::Double(y), Tag::Double(z)]) => Some([*x, *y, *z]),
+        _ => None,
+    };
+    // A number before 1.16
+    let dimension = match root.get("Dimension") {
+        Some(Tag::String(x)) => Some(x.clone()),
+        Some(x) => x.as_i64().map(|x| {
+            match x {
+                -1 => "minecraft:the_nether",
+                1 => "minecraft:the_end",
+                _ => "minecraft:overworld",
+            }
+            .to_string()
+        }),
+        None => None,
+    };
+    Ok(PlayerInfo {
+        uuid: uuid.to_string(),
+        name: names.get(uuid).cloned(),
+        last_seen,
+        play_time: stats.and_then(play_time),
+        dimension,
+        position,
+    })
+}
+
+/// Seconds played, counted in ticks under three names over the versions.
+fn play_time(stats: &[u8]) -> Option<u64> {
+    let stats: serde_json::Value = serde_json::from_slice(stats).ok()?;
+    let ticks = stats
+        .pointer("/stats/minecraft:custom/minecraft:play_time")
+        .or(stats.pointer("/stats/minecraft:custom/minecraft:play_one_minute"))
+        .or(stats.get("stat.playOneMinute"))?
+        .as_u64()?;
+    Some(ticks / 20)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uuids_with_or_without_hyphens() {
+        let uuid = "069a79f4-44e9-4726-a5be-fca90e38aaf5";
+        assert_eq!(parse_uuid(uuid).as_deref(), Some(uuid));
+        assert_eq!(
+            parse_uuid(" 069A79F444E94726A5BEFCA90E38AAF5\n").as_deref(),
+            Some(uuid)
+        );
+        assert_eq!(
+            parse_uuid("069a-79f444e9-4726a5be-fca90e38aaf5").as_deref(),
+            Some(uuid)
+        );
+    }
+
+    #[test]
+    fn malformed_uuids() {
+        for uuid in [
+            "",
+            "Notch",
+            "069a79f4-44e9-4726-a5be-fca90e38aaf",
+            "069a79f4-44e9-4726-a5be-fca90e38aaf5a",
+            "069a79f4-44e9-4726-a5be-fca90e38aafg",
+            "069a79f4 44e9 4726 a5be fca90e38aaf5",
+        ] {
+            assert_eq!(parse_uuid(uuid), None, "{:?}", uuid);
+        }
+    }
+
+    #[test]
+    fn player_files_by_uuid() {
+        let uuid = "069a79f4-44e9-4726-a5be-fca90e38aaf5";
+        let [playerdata, _, _] = player_files(uuid);
+        assert_eq!(uuid_of_player_file(&playerdata).as_deref(), Some(uuid));
+        assert_eq!(
+            uuid_of_player_file(Path::new("playerdata/069a79f4.dat_old")),
+            None
+        );
+        assert_eq!(
+            uuid_of_player_file(&PathBuf::from("stats").join(format!("{}.dat", uuid))),
+            None
+        );
+    }
+
+    #[test]
+    fn play_time_of_every_version() {
+        let ticks = |json: &str| play_time(json.as_bytes());
+        assert_eq!(
+            ticks(r#"{"stats":{"minecraft:custom":{"minecraft:play_time":72000}}}"#),
+            Some(3600)
+        );
+        // Before 1.17, and before 1.13
+        assert_eq!(
+            ticks(r#"{"stats":{"minecraft:custom":{"minecraft:play_one_minute":1200}}}"#),
+            Some(60)
+        );
+        assert_eq!(ticks(r#"{"stat.playOneMinute":20}"#), Some(1));
+    }
+
+    #[test]
+    fn missing_or_broken_play_time() {
+        let ticks = |json: &str| play_time(json.as_bytes());
+        assert_eq!(ticks(r#"{"stats":{"minecraft:custom":{}}}"#), None);
+        assert_eq!(ticks(r#"{"stats":{}}"#), None);
+        assert_eq!(ticks("{}"), None);
+        assert_eq!(
+            ticks(r#"{"stats":{"minecraft:custom":{"minecraft:play_time":-1}}}"#),
+            None
+        );
+        assert_eq!(ticks(r#"{"stats":"#), None);
+        assert_eq!(ticks(""), None);
+    }
+
+    #[test]
+    fn player_info_from_playerdata() {
+        let uuid = "069a79f4-44e9-4726-a5be-fca90e38aaf5";
+        let playerdata = |dimension| {
+            let root = Tag::Compound(vec![
+                (
+                    "Pos".to_string(),
+                    Tag::List(vec![Tag::Double(1.5), Tag::Double(64.0), Tag::Double(-3.5)]),
+                ),
+                ("Dimension".to_string(), dimension),
+            ]);
+            nbt::write("", &root).unwrap()
+        };
+        let names = BTreeMap::from([(uuid.to_string(), "Notch".to_string())]);
+        let player = player_info(
+            uuid,
+            &names,
+            &playerdata(Tag::String("minecraft:the_end".to_string())),
+            None,
+            None,
+        )
+        .unwrap();
+        assert_eq!(player.name.as_deref(), Some("Notch"));
+        assert_eq!(player.position, Some([1.5, 64.0, -3.5]));
+        assert_eq!(player.dimension.as_deref(), Some("minecraft:the_end"));
+        assert_eq!(player.play_time, None);
+
+        let stats = br#"{"stats":{"minecraft:custom":{"minecraft:play_time":200}}}"#;
+        let player = player_info(
+            uuid,
+            &BTreeMap::new(),
+            &playerdata(Tag::Int(-1)),
+            Some(stats),
+            None,
+        )
+        .unwrap();
+        assert_eq!(player.name, None);
+        assert_eq!(player.dimension.as_deref(), Some("minecraft:the_nether"));
+        assert_eq!(player.play_time, Some(10));
+        assert!(player_info(uuid, &names, b"not nbt", None, None).is_err());
+    }
+}
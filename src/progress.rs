@@ -0,0 +1,335 @@
+use std::{
+    borrow::Cow,
+    sync::{
+        Arc, Mutex, MutexGuard,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use bytes::Bytes;
+use rustic_core::{
+    ErrorKind, FileType, Id, Progress, ProgressBars, ReadBackend, RepositoryBackends, RusticError,
+    RusticResult, WriteBackend,
+};
+use serde::Serialize;
+
+/// What rustic is busy with, guessed from the titles of its progress bars.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Phase {
+    #[default]
+    Starting,
+    Indexing,
+    Scanning,
+    Packing,
+    Restoring,
+    Other,
+}
+
+impl Phase {
+    fn from_title(title: &str) -> Self {
+        if title.contains("index") {
+            Phase::Indexing
+        } else if title.contains("backing up") {
+            Phase::Packing
+        } else if title.contains("restoring") {
+            Phase::Restoring
+        } else if title.contains("scanning") || title.contains("collecting") {
+            Phase::Scanning
+        } else {
+            Phase::Other
+        }
+    }
+}
+
+/// Progress of the step a task is currently at.
+#[derive(Debug, Clone, Serialize)]
+pub struct ProgressState {
+    pub phase: Phase,
+    pub title: String,
+    pub position: u64,
+    pub length: Option<u64>,
+    /// Whether `position` and `length` count bytes rather than items
+    pub bytes: bool,
+    #[serde(skip)]
+    started: Instant,
+}
+
+impl Default for ProgressState {
+    fn default() -> Self {
+        Self {
+            phase: Phase::default(),
+            title: String::new(),
+            position: 0,
+            length: None,
+            bytes: false,
+            started: Instant::now(),
+        }
+    }
+}
+
+impl ProgressState {
+    pub fn fraction(&self) -> Option<f64> {
+        self.length
+            .filter(|x| *x > 0)
+            .map(|x| (self.position as f64 / x as f64).min(1.0))
+    }
+
+    /// Estimated from the speed of the current step so far.
+    pub fn eta(&self) -> Option<Duration> {
+        let fraction = self.fraction().filter(|x| *x > 0.0)?;
+        Some(self.started.elapsed().mul_f64((1.0 - fraction) / fraction))
+    }
+}
+
+impl std::fmt::Display for ProgressState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.title)?;
+        let unit = if self.bytes { " B" } else { "" };
+        match self.length {
+            Some(length) => write!(f, " {}/{}{}", self.position, length, unit)?,
+            None if self.position > 0 => write!(f, " {}{}", self.position, unit)?,
+            None => {}
+        }
+        if let Some(eta) = self.eta() {
+            write!(f, " (ETA {}s)", eta.as_secs())?;
+        }
+        Ok(())
+    }
+}
+
+/// Progress, warnings and cancellation token shared between a task and the threads working
+/// for it.
+#[derive(Debug, Clone, Default)]
+pub struct TaskHandle {
+    pub progress: Arc<Mutex<ProgressState>>,
+    pub warnings: Arc<Mutex<Vec<String>>>,
+    pub cancelled: Arc<AtomicBool>,
+    /// Set while the task counts its own progress, which rustic's then leaves alone
+    counting: Arc<AtomicBool>,
+}
+
+impl TaskHandle {
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Acquire)
+    }
+
+    /// A handle for work done in parallel for this task, with a progress of its own but the
+    /// same warnings and cancellation.
+    pub fn part(&self) -> Self {
+        Self {
+            progress: Arc::default(),
+            warnings: self.warnings.clone(),
+            cancelled: self.cancelled.clone(),
+            counting: Arc::default(),
+        }
+    }
+
+    /// Count the progress of work spanning several rustic commands, up to `length` items or
+    /// bytes, until `stop_counter`.
+    pub fn start_counter(&self, title: &str, length: u64, bytes: bool) {
+        *lock(&self.progress) = ProgressState {
+            phase: Phase::from_title(title),
+            title: title.to_string(),
+            length: Some(length),
+            bytes,
+            ..Default::default()
+        };
+        self.counting.store(true, Ordering::Release);
+    }
+
+    pub fn inc(&self, inc: u64) {
+        lock(&self.progress).position += inc;
+    }
+
+    pub fn stop_counter(&self) {
+        self.counting.store(false, Ordering::Release);
+    }
+
+    fn is_counting(&self) -> bool {
+        self.counting.load(Ordering::Acquire)
+    }
+}
+
+/// Feeds rustic's progress into the state of a task. Without a task, nothing is reported.
+#[derive(Debug, Clone, Default)]
+pub struct TaskProgressBars {
+    task: Option<TaskHandle>,
+}
+
+impl TaskProgressBars {
+    pub fn new(task: TaskHandle) -> Self {
+        Self { task: Some(task) }
+    }
+
+    fn start(&self, title: Cow<'static, str>, bytes: bool) -> TaskProgress {
+        if let Some(task) = self.task.as_ref().filter(|x| !x.is_counting()) {
+            *lock(&task.progress) = ProgressState {
+                phase: Phase::from_title(&title),
+                title: title.to_string(),
+                bytes,
+                ..Default::default()
+            };
+        }
+        TaskProgress {
+            task: self.task.clone(),
+            hidden: false,
+        }
+    }
+}
+
+impl ProgressBars for TaskProgressBars {
+    type P = TaskProgress;
+
+    fn progress_hidden(&self) -> Self::P {
+        TaskProgress {
+            task: self.task.clone(),
+            hidden: true,
+        }
+    }
+
+    fn progress_spinner(&self, prefix: impl Into<Cow<'static, str>>) -> Self::P {
+        self.start(prefix.into(), false)
+    }
+
+    fn progress_counter(&self, prefix: impl Into<Cow<'static, str>>) -> Self::P {
+        self.start(prefix.into(), false)
+    }
+
+    fn progress_bytes(&self, prefix: impl Into<Cow<'static, str>>) -> Self::P {
+        self.start(prefix.into(), true)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TaskProgress {
+    task: Option<TaskHandle>,
+    hidden: bool,
+}
+
+impl TaskProgress {
+    /// The progress state to write to.
+    fn state(&self) -> Option<&Mutex<ProgressState>> {
+        let task = self.task.as_ref()?;
+        (!self.hidden && !task.is_counting()).then_some(&*task.progress)
+    }
+}
+
+impl Progress for TaskProgress {
+    fn is_hidden(&self) -> bool {
+        self.hidden || self.task.is_none()
+    }
+
+    fn set_length(&self, len: u64) {
+        if let Some(state) = self.state() {
+            lock(state).length = Some(len);
+        }
+    }
+
+    fn set_title(&self, title: &'static str) {
+        if let Some(state) = self.state() {
+            let mut state = lock(state);
+            state.phase = Phase::from_title(title);
+            state.title = title.to_string();
+        }
+    }
+
+    fn inc(&self, inc: u64) {
+        if let Some(state) = self.state() {
+            lock(state).position += inc;
+        }
+    }
+
+    fn finish(&self) {
+        if let Some(state) = self.state() {
+            let mut state = lock(state);
+            if let Some(length) = state.length {
+                state.position = length;
+            }
+        }
+    }
+}
+
+/// Refuses writes to the storage once the task is cancelled, which rustic's progress has no way
+/// to stop. A backup or prune then fails instead of storing anything more, although a backup
+/// still reads through the rest of its source.
+#[derive(Debug)]
+pub struct TaskBackend {
+    inner: Arc<dyn WriteBackend>,
+    task: TaskHandle,
+}
+
+impl TaskBackend {
+    /// Wrap the backends for the task, if any.
+    pub fn wrap(backends: RepositoryBackends, task: Option<TaskHandle>) -> RepositoryBackends {
+        let Some(task) = task else {
+            return backends;
+        };
+        let wrap = |inner| {
+            Arc::new(Self {
+                inner,
+                task: task.clone(),
+            }) as Arc<dyn WriteBackend>
+        };
+        RepositoryBackends::new(wrap(backends.repository()), backends.repo_hot().map(wrap))
+    }
+}
+
+impl ReadBackend for TaskBackend {
+    fn location(&self) -> String {
+        self.inner.location()
+    }
+
+    fn list_with_size(&self, tpe: FileType) -> RusticResult<Vec<(Id, u32)>> {
+        self.inner.list_with_size(tpe)
+    }
+
+    fn list(&self, tpe: FileType) -> RusticResult<Vec<Id>> {
+        self.inner.list(tpe)
+    }
+
+    fn read_full(&self, tpe: FileType, id: &Id) -> RusticResult<Bytes> {
+        self.inner.read_full(tpe, id)
+    }
+
+    fn read_partial(
+        &self,
+        tpe: FileType,
+        id: &Id,
+        cacheable: bool,
+        offset: u32,
+        length: u32,
+    ) -> RusticResult<Bytes> {
+        self.inner.read_partial(tpe, id, cacheable, offset, length)
+    }
+
+    fn needs_warm_up(&self) -> bool {
+        self.inner.needs_warm_up()
+    }
+
+    fn warm_up(&self, tpe: FileType, id: &Id) -> RusticResult<()> {
+        self.inner.warm_up(tpe, id)
+    }
+}
+
+impl WriteBackend for TaskBackend {
+    fn create(&self) -> RusticResult<()> {
+        self.inner.create()
+    }
+
+    fn write_bytes(&self, tpe: FileType, id: &Id, cacheable: bool, buf: Bytes) -> RusticResult<()> {
+        if self.task.is_cancelled() {
+            return Err(RusticError::new(ErrorKind::Other, "Task cancelled"));
+        }
+        self.inner.write_bytes(tpe, id, cacheable, buf)
+    }
+
+    fn remove(&self, tpe: FileType, id: &Id, cacheable: bool) -> RusticResult<()> {
+        self.inner.remove(tpe, id, cacheable)
+    }
+}
+
+fn lock(state: &Mutex<ProgressState>) -> MutexGuard<'_, ProgressState> {
+    state.lock().unwrap_or_else(|e| e.into_inner())
+}
@@ -0,0 +1,456 @@
+use std::{
+    fmt, fs,
+    hash::{DefaultHasher, Hash, Hasher},
+    path::Path,
+    str::FromStr,
+};
+
+use anyhow::{Result, anyhow, bail};
+use serde::{Deserialize, Serialize};
+
+use crate::nbt::{self, Tag};
+
+const SECTOR: usize = 4096;
+/// Chunks in a region file
+const CHUNKS: usize = 1024;
+/// Areas are listed chunk by chunk, down to the tasks restoring them and their history. This
+/// is 2048 blocks square.
+const MAX_AREA_CHUNKS: i64 = 16384;
+
+/// Directories of a dimension keeping per-chunk data in region files: blocks, entities (since
+/// 1.17) and points of interest.
+pub const REGION_DIRS: &[&str] = &["region", "entities", "poi"];
+
+/// A vanilla dimension of a world.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Serialize,
+    Deserialize,
+    clap::ValueEnum,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum Dimension {
+    Overworld,
+    Nether,
+    End,
+}
+
+impl Dimension {
+    pub const ALL: [Dimension; 3] = [Dimension::Overworld, Dimension::Nether, Dimension::End];
+
+    /// Directory of the dimension, relative to the world.
+    pub fn dir(self) -> &'static Path {
+        Path::new(match self {
+            Dimension::Overworld => "",
+            Dimension::Nether => "DIM-1",
+            Dimension::End => "DIM1",
+        })
+    }
+}
+
+impl fmt::Display for Dimension {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Dimension::Overworld => "overworld",
+            Dimension::Nether => "nether",
+            Dimension::End => "end",
+        })
+    }
+}
+
+/// Position of a chunk, in chunks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct ChunkPos {
+    pub x: i32,
+    pub z: i32,
+}
+
+impl ChunkPos {
+    /// The chunk holding a block.
+    pub fn of_block(x: i32, z: i32) -> Self {
+        Self {
+            x: x >> 4,
+            z: z >> 4,
+        }
+    }
+
+    /// The chunks of the region file `r.<x>.<z>.mca`, `None` for other files and regions
+    /// beyond the range of chunk positions.
+    pub fn in_region_file(name: &str) -> Option<Vec<ChunkPos>> {
+        let (x, z) = name
+            .strip_prefix("r.")?
+            .strip_suffix(".mca")?
+            .split_once('.')?;
+        let first = |x: &str| x.parse::<i32>().ok()?.checked_mul(32);
+        let (x, z) = (first(x)?, first(z)?);
+        // The last chunk must fit too
+        x.checked_add(31)?;
+        z.checked_add(31)?;
+        Some(
+            (0..32)
+                .flat_map(|dz| {
+                    (0..32).map(move |dx| ChunkPos {
+                        x: x + dx,
+                        z: z + dz,
+                    })
+                })
+                .collect(),
+        )
+    }
+
+    /// The region file holding the chunk, relative to a region directory.
+    pub fn region_file(self) -> String {
+        format!("r.{}.{}.mca", self.x >> 5, self.z >> 5)
+    }
+
+    fn index(self) -> usize {
+        ((self.x & 31) + (self.z & 31) * 32) as usize
+    }
+}
+
+impl FromStr for ChunkPos {
+    type Err = anyhow::Error;
+
+    /// `x,z`
+    fn from_str(s: &str) -> Result<Self> {
+        let (x, z) = s
+            .split_once(',')
+            .ok_or(anyhow!("Expected x,z but got '{}'", s))?;
+        Ok(Self {
+            x: x.trim().parse()?,
+            z: z.trim().parse()?,
+        })
+    }
+}
+
+impl fmt::Display for ChunkPos {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{},{}", self.x, self.z)
+    }
+}
+
+/// A rectangle of blocks between two corners, both included.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Area {
+    pub from: (i32, i32),
+    pub to: (i32, i32),
+}
+
+impl Area {
+    /// Every chunk the area touches.
+    pub fn chunks(&self) -> Vec<ChunkPos> {
+        let (from, to) = self.corner_chunks();
+        (from.x..=to.x)
+            .flat_map(|x| (from.z..=to.z).map(move |z| ChunkPos { x, z }))
+            .collect()
+    }
+
+    fn chunk_count(&self) -> i64 {
+        let (from, to) = self.corner_chunks();
+        (to.x as i64 - from.x as i64 + 1) * (to.z as i64 - from.z as i64 + 1)
+    }
+
+    fn corner_chunks(&self) -> (ChunkPos, ChunkPos) {
+        (
+            ChunkPos::of_block(self.from.0.min(self.to.0), self.from.1.min(self.to.1)),
+            ChunkPos::of_block(self.from.0.max(self.to.0), self.from.1.max(self.to.1)),
+        )
+    }
+}
+
+impl FromStr for Area {
+    type Err = anyhow::Error;
+
+    /// `x1,z1:x2,z2` in blocks, touching at most `MAX_AREA_CHUNKS` chunks
+    fn from_str(s: &str) -> Result<Self> {
+        let (from, to) = s
+            .split_once(':')
+            .ok_or(anyhow!("Expected x1,z1:x2,z2 but got '{}'", s))?;
+        let corner = |s: &str| {
+            let pos: ChunkPos = s.parse()?;
+            anyhow::Ok((pos.x, pos.z))
+        };
+        let area = Self {
+            from: corner(from)?,
+            to: corner(to)?,
+        };
+        if area.chunk_count() > MAX_AREA_CHUNKS {
+            bail!(
+                "Area of {} chunks is larger than the {} allowed",
+                area.chunk_count(),
+                MAX_AREA_CHUNKS
+            );
+        }
+        Ok(area)
+    }
+}
+
+/// A chunk as stored in a region file, still compressed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chunk {
+    /// When the chunk was last saved, in seconds since the epoch
+    pub timestamp: u32,
+    compression: u8,
+    data: Vec<u8>,
+}
+
+impl Chunk {
+    /// Hash of the stored chunk, which changes whenever the game saved different content.
+    pub fn content_hash(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.compression.hash(&mut hasher);
+        self.data.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// Decompress and parse the chunk.
+    pub fn nbt(&self) -> Result<Tag> {
+        if self.is_external() {
+            bail!("Chunk is kept in an external file");
+        }
+        if self.compression == 4 {
+            bail!("LZ4 compressed chunks are not supported");
+        }
+        Ok(nbt::read_compressed(&self.data)?.1)
+    }
+
+    /// Whether the chunk was too large for the region file and is kept in a `c.<x>.<z>.mcc`
+    /// file next to it.
+    pub fn is_external(&self) -> bool {
+        self.compression & 0x80 != 0
+    }
+}
+
+#[cfg(test)]
+impl Chunk {
+    /// A chunk holding `tag` without compression.
+    pub fn uncompressed(tag: &Tag) -> Self {
+        Self {
+            timestamp: 0,
+            compression: 3,
+            data: nbt::write("", tag).unwrap(),
+        }
+    }
+}
+
+/// External file of a chunk too large for its region file, relative to the region directory.
+pub fn external_file(pos: ChunkPos) -> String {
+    format!("c.{}.{}.mcc", pos.x, pos.z)
+}
+
+/// The content of an Anvil region file (`.mca`), a grid of 32×32 chunks.
+#[derive(Debug, Clone)]
+pub struct Region {
+    chunks: Vec<Option<Chunk>>,
+}
+
+impl Default for Region {
+    fn default() -> Self {
+        Self {
+            chunks: vec![None; CHUNKS],
+        }
+    }
+}
+
+impl Region {
+    /// Chunks pointing outside of the file are skipped, as Minecraft regenerates them too.
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        let mut region = Self::default();
+        // Minecraft leaves empty files behind for regions without chunks
+        if data.is_empty() {
+            return Ok(region);
+        }
+        if data.len() < 2 * SECTOR {
+            bail!("Region file of {} bytes has no header", data.len());
+        }
+        let u32_at = |pos: usize| {
+            data.get(pos..pos + 4)
+                .map(|x| u32::from_be_bytes(x.try_into().expect("Slice of 4 bytes")))
+        };
+        for (i, slot) in region.chunks.iter_mut().enumerate() {
+            let location = u32_at(i * 4).unwrap_or_default();
+            if location == 0 {
+                continue;
+            }
+            let start = (location >> 8) as usize * SECTOR;
+            let Some(len) = u32_at(start).map(|x| x as usize) else {
+                warn!("Chunk {} of region points outside of it", i);
+                continue;
+            };
+            let Some(payload) = data
+                .get(start + 4..start + 4 + len)
+                .filter(|x| !x.is_empty())
+            else {
+                warn!("Chunk {} of region is truncated", i);
+                continue;
+            };
+            *slot = Some(Chunk {
+                timestamp: u32_at(SECTOR + i * 4).unwrap_or_default(),
+                compression: payload[0],
+                data: payload[1..].to_vec(),
+            });
+        }
+        Ok(region)
+    }
+
+    /// An absent file is an empty region.
+    pub fn read(path: &Path) -> Result<Self> {
+        match fs::read(path) {
+            Ok(data) => Self::parse(&data),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    pub fn get(&self, pos: ChunkPos) -> Option<&Chunk> {
+        self.chunks[pos.index()].as_ref()
+    }
+
+    /// Replace a chunk, or remove it so the game generates it again. Returns whether anything
+    /// changed.
+    pub fn set(&mut self, pos: ChunkPos, chunk: Option<Chunk>) -> bool {
+        let slot = &mut self.chunks[pos.index()];
+        let changed = *slot != chunk;
+        *slot = chunk;
+        changed
+    }
+
+    /// Lay the chunks out one after another, each padded to whole sectors.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let mut out = vec![0; 2 * SECTOR];
+        for (i, chunk) in self.chunks.iter().enumerate() {
+            let Some(chunk) = chunk else {
+                continue;
+            };
+            let offset = out.len() / SECTOR;
+            let len = chunk.data.len() + 1;
+            let sectors = (len + 4).div_ceil(SECTOR);
+            if sectors > 255 {
+                bail!("Chunk {} of {} bytes does not fit in a region file", i, len);
+            }
+            let location = (offset as u32) << 8 | sectors as u32;
+            out[i * 4..i * 4 + 4].copy_from_slice(&location.to_be_bytes());
+            out[SECTOR + i * 4..SECTOR + i * 4 + 4].copy_from_slice(&chunk.timestamp.to_be_bytes());
+            out.extend((len as u32).to_be_bytes());
+            out.push(chunk.compression);
+            out.extend(&chunk.data);
+            out.resize((offset + sectors) * SECTOR, 0);
+        }
+        Ok(out)
+    }
+
+    /// Written next to `path` first, so the region file is never left half-written.
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let tmp = path.with_added_extension("tmp");
+        fs::write(&tmp, self.to_bytes()?)?;
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(timestamp: u32, len: usize) -> Chunk {
+        Chunk {
+            timestamp,
+            compression: 2,
+            data: (0..len).map(|x| x as u8).collect(),
+        }
+    }
+
+    #[test]
+    fn chunk_positions_wrap_into_regions() {
+        let pos = ChunkPos { x: -1, z: -1 };
+        assert_eq!(pos.index(), 1023);
+        assert_eq!(pos.region_file(), "r.-1.-1.mca");
+        let pos = ChunkPos { x: -33, z: 32 };
+        assert_eq!(pos.index(), 31);
+        assert_eq!(pos.region_file(), "r.-2.1.mca");
+        assert_eq!(ChunkPos { x: 33, z: 1 }.index(), 33);
+        assert_eq!(ChunkPos::of_block(-1, -17), ChunkPos { x: -1, z: -2 });
+        assert_eq!(ChunkPos::of_block(15, 16), ChunkPos { x: 0, z: 1 });
+    }
+
+    #[test]
+    fn region_file_names() {
+        let chunks = ChunkPos::in_region_file("r.-1.2.mca").unwrap();
+        assert_eq!(chunks.len(), CHUNKS);
+        assert_eq!(chunks[0], ChunkPos { x: -32, z: 64 });
+        assert_eq!(chunks[CHUNKS - 1], ChunkPos { x: -1, z: 95 });
+        assert!(chunks.iter().all(|x| x.region_file() == "r.-1.2.mca"));
+        assert!(ChunkPos::in_region_file("r.0.0.mcc").is_none());
+        assert!(ChunkPos::in_region_file("r.67108864.0.mca").is_none());
+        assert!(ChunkPos::in_region_file("r.0.-67108865.mca").is_none());
+        assert!(ChunkPos::in_region_file("r.-67108864.67108863.mca").is_some());
+    }
+
+    #[test]
+    fn region_round_trips() {
+        let positions = [
+            ChunkPos { x: 0, z: 0 },
+            ChunkPos { x: 31, z: 0 },
+            ChunkPos { x: -1, z: -1 },
+            ChunkPos { x: -32, z: 5 },
+        ];
+        let mut region = Region::default();
+        for (i, pos) in positions.iter().enumerate() {
+            assert!(region.set(*pos, Some(chunk(i as u32 + 1, 100 + i * 5000))));
+        }
+        let parsed = Region::parse(&region.to_bytes().unwrap()).unwrap();
+        for (i, pos) in positions.iter().enumerate() {
+            assert_eq!(parsed.get(*pos), Some(&chunk(i as u32 + 1, 100 + i * 5000)));
+        }
+        assert_eq!(parsed.chunks.iter().flatten().count(), positions.len());
+    }
+
+    #[test]
+    fn chunks_are_padded_to_sectors() {
+        let mut region = Region::default();
+        // Length prefix and compression byte take 5 bytes
+        region.set(ChunkPos { x: 0, z: 0 }, Some(chunk(7, SECTOR - 5)));
+        region.set(ChunkPos { x: 1, z: 0 }, Some(chunk(8, SECTOR - 4)));
+        let data = region.to_bytes().unwrap();
+        assert_eq!(data.len(), 5 * SECTOR);
+        let u32_at = |pos: usize| u32::from_be_bytes(data[pos..pos + 4].try_into().unwrap());
+        assert_eq!(u32_at(0), 2 << 8 | 1);
+        assert_eq!(u32_at(4), 3 << 8 | 2);
+        assert_eq!(u32_at(SECTOR), 7);
+        assert_eq!(u32_at(SECTOR + 4), 8);
+        assert_eq!(u32_at(2 * SECTOR) as usize, SECTOR - 4);
+        assert_eq!(u32_at(3 * SECTOR) as usize, SECTOR - 3);
+        assert!(data[3 * SECTOR + 4 + SECTOR - 3..].iter().all(|x| *x == 0));
+    }
+
+    #[test]
+    fn empty_and_oversized_regions() {
+        assert_eq!(Region::default().to_bytes().unwrap().len(), 2 * SECTOR);
+        assert!(
+            Region::parse(&[])
+                .unwrap()
+                .chunks
+                .iter()
+                .all(Option::is_none)
+        );
+        assert!(Region::parse(&[0; SECTOR]).is_err());
+        let mut region = Region::default();
+        region.set(ChunkPos { x: 0, z: 0 }, Some(chunk(0, 255 * SECTOR)));
+        assert!(region.to_bytes().is_err());
+    }
+
+    #[test]
+    fn chunks_outside_of_the_file_are_skipped() {
+        let mut data = vec![0; 2 * SECTOR];
+        data[..4].copy_from_slice(&(9u32 << 8 | 1).to_be_bytes());
+        let region = Region::parse(&data).unwrap();
+        assert_eq!(region.get(ChunkPos { x: 0, z: 0 }), None);
+    }
+}
@@ -0,0 +1,144 @@
+use std::{fmt::Display, str::FromStr};
+
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, Local, TimeDelta};
+
+use crate::{
+    backup::{AppState, SaveBackupConfiguration},
+    settings::Settings,
+};
+
+/// How long the daemon waits before retrying a failed backup.
+pub const RETRY_DELAY: TimeDelta = TimeDelta::minutes(5);
+
+/// When the daemon backs up a save.
+#[derive(Debug, Clone)]
+pub enum Schedule {
+    /// A fixed delay after the last successful backup
+    Interval(TimeDelta),
+    /// The first time matched by a cron expression after the last successful backup
+    Cron(Box<cron::Schedule>),
+}
+
+impl Schedule {
+    /// Parse a cron expression, with or without the leading seconds field.
+    pub fn cron(expr: &str) -> Result<Self> {
+        let expr = expr.trim();
+        let expr = if expr.split_whitespace().count() == 5 {
+            format!("0 {}", expr)
+        } else {
+            expr.to_string()
+        };
+        let schedule = cron::Schedule::from_str(&expr)
+            .map_err(|e| anyhow!("Invalid cron expression '{}': {}", expr, e))?;
+        Ok(Self::Cron(Box::new(schedule)))
+    }
+
+    /// Per-save settings take precedence over global ones, and cron expressions over intervals.
+    pub fn of(save: &SaveBackupConfiguration, settings: &Settings) -> Result<Self> {
+        if let Some(expr) = &save.backup_cron {
+            Self::cron(expr)
+        } else if let Some(secs) = save.backup_duration {
+            Ok(Self::Interval(TimeDelta::seconds(secs.into())))
+        } else if let Some(expr) = &settings.daemon_backup_cron {
+            Self::cron(expr)
+        } else {
+            Ok(Self::Interval(TimeDelta::seconds(
+                settings.daemon_backup_duration.into(),
+            )))
+        }
+    }
+
+    /// A save that was never backed up is due immediately. Runs missed while the machine was
+    /// off or asleep are caught up with one backup, since this is computed from the last run.
+    pub fn next_run(&self, last: Option<DateTime<Local>>) -> Option<DateTime<Local>> {
+        let Some(last) = last else {
+            return Some(Local::now());
+        };
+        match self {
+            Schedule::Interval(delay) => Some(last + *delay),
+            Schedule::Cron(schedule) => schedule.after(&last).next(),
+        }
+    }
+}
+
+impl Display for Schedule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Schedule::Interval(delay) => write!(f, "every {}s", delay.num_seconds()),
+            Schedule::Cron(schedule) => write!(f, "cron '{}'", schedule.source()),
+        }
+    }
+}
+
+pub fn next_run(save: &SaveBackupConfiguration) -> Option<DateTime<Local>> {
+    let settings = { Settings::instance().clone() };
+    Schedule::of(save, &settings)
+        .inspect_err(|e| error!("Bad schedule for {}: {:?}", save.id(), e))
+        .ok()?
+        .next_run(save.last_backup)
+}
+
+/// Ids of the saves whose next run is not after `now`.
+pub fn due_saves(now: DateTime<Local>) -> Vec<String> {
+    let instance = AppState::instance();
+    instance
+        .saves
+        .values()
+        .filter(|x| instance.is_present(x))
+        .filter(|x| next_run(x).is_some_and(|next| next <= now))
+        .map(|x| x.id().to_string())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn at(day: u32, hour: u32, min: u32) -> DateTime<Local> {
+        Local.with_ymd_and_hms(2025, 1, day, hour, min, 0).unwrap()
+    }
+
+    #[test]
+    fn never_backed_up_is_due_now() {
+        let before = Local::now();
+        for schedule in [
+            Schedule::Interval(TimeDelta::hours(1)),
+            Schedule::cron("0 3 * * *").unwrap(),
+        ] {
+            let next = schedule.next_run(None).unwrap();
+            assert!(before <= next && next <= Local::now());
+        }
+    }
+
+    #[test]
+    fn interval_counts_from_last_run() {
+        let schedule = Schedule::Interval(TimeDelta::minutes(90));
+        assert_eq!(schedule.next_run(Some(at(10, 8, 0))), Some(at(10, 9, 30)));
+        // A last run in the future, after the clock was set back, is not due before it
+        let last = Local::now() + TimeDelta::days(1);
+        assert_eq!(
+            schedule.next_run(Some(last)),
+            Some(last + TimeDelta::minutes(90))
+        );
+    }
+
+    #[test]
+    fn cron_picks_the_first_time_after_last_run() {
+        let schedule = Schedule::cron("*/15 * * * *").unwrap();
+        assert_eq!(schedule.next_run(Some(at(10, 8, 7))), Some(at(10, 8, 15)));
+        assert_eq!(schedule.next_run(Some(at(10, 8, 15))), Some(at(10, 8, 30)));
+        // With seconds, and missed runs caught up with one
+        let schedule = Schedule::cron("0 30 3 * * *").unwrap();
+        assert_eq!(schedule.next_run(Some(at(10, 4, 0))), Some(at(11, 3, 30)));
+        let last = Local::now() + TimeDelta::days(2);
+        assert!(schedule.next_run(Some(last)).unwrap() > last);
+    }
+
+    #[test]
+    fn invalid_cron_is_an_error() {
+        assert!(Schedule::cron("every day").is_err());
+        assert!(Schedule::cron("61 * * * *").is_err());
+    }
+}
@@ -1,8 +1,11 @@
 use crate::utils::report_err;
+use anyhow::{Context, Result};
+use rustic_core::KeepOptions;
 use serde::{Deserialize, Serialize};
 use std::{
     fs::{self, File},
     path::PathBuf,
+    str::FromStr,
     sync::{LazyLock, Mutex, MutexGuard},
 };
 
@@ -28,20 +31,36 @@ const fn daemon_backup_duration() -> u32 {
     3600
 }
 
+#[inline]
+const fn default_backup_concurrency() -> u32 {
+    2
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 #[serde(default)]
-
 pub struct Settings {
     pub language: String,
     #[serde(default = "default_compression_level")]
     pub compression_level: i32,
     #[serde(default = "daemon_backup_duration")]
     pub daemon_backup_duration: u32,
+    /// Overrides `daemon_backup_duration` when set
+    pub daemon_backup_cron: Option<String>,
+    /// How many saves are backed up at once
+    #[serde(default = "default_backup_concurrency")]
+    pub backup_concurrency: u32,
     pub scan_root: Vec<PathBuf>,
     pub sync: bool,
     pub remote: Option<String>,
     pub password: Option<String>,
     pub password_cmd: Option<String>,
+    pub retention: RetentionPolicy,
+    /// Glob or gitignore-style patterns excluded from every backup
+    pub excludes: Vec<String>,
+    /// Back up caches, logs and map renders too, see `backup::REGENERABLE_PATHS`
+    pub include_regenerable: bool,
+    /// What to do when a world to back up or restore is open in Minecraft
+    pub open_world_policy: OpenWorldPolicy,
 }
 impl Settings {
     pub fn instance() -> MutexGuard<'static, Self> {
@@ -59,14 +78,15 @@ impl Settings {
                     warn!("Using default settings");
                     Settings {
                         compression_level: 6,
+                        backup_concurrency: default_backup_concurrency(),
                         ..Default::default()
                     }
                 }
             })
         });
-        return INSTANCE
+        INSTANCE
             .lock()
-            .expect("Failed to lock Settings (This shouldn't happen)");
+            .expect("Failed to lock Settings (This shouldn't happen)")
     }
     pub fn save(&self) {
         if let Ok(file) = File::create(CONFIG_HOME.join("config.json"))
@@ -78,3 +98,71 @@ impl Settings {
         }
     }
 }
+
+/// What to do when a world to back up or restore is open in Minecraft, see `game::check_closed`.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum OpenWorldPolicy {
+    /// Wait until the game closes the world
+    Wait,
+    /// Go on, and warn about it
+    Warn,
+    /// Back up anyway and tag the snapshot `unsafe`, but refuse to restore
+    #[default]
+    Unsafe,
+    /// Refuse to back up or restore
+    Refuse,
+}
+
+impl OpenWorldPolicy {
+    pub const ALL: [Self; 4] = [Self::Wait, Self::Warn, Self::Unsafe, Self::Refuse];
+}
+
+/// Which snapshots are kept when old ones are forgotten. Keeps everything when no rule is set.
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq, Eq, clap::Args)]
+#[serde(default)]
+pub struct RetentionPolicy {
+    /// Keep the last N snapshots
+    #[arg(long)]
+    pub keep_last: Option<i32>,
+    /// Keep the last N hourly snapshots
+    #[arg(long)]
+    pub keep_hourly: Option<i32>,
+    /// Keep the last N daily snapshots
+    #[arg(long)]
+    pub keep_daily: Option<i32>,
+    /// Keep the last N weekly snapshots
+    #[arg(long)]
+    pub keep_weekly: Option<i32>,
+    /// Keep the last N monthly snapshots
+    #[arg(long)]
+    pub keep_monthly: Option<i32>,
+    /// Keep snapshots newer than this, e.g. `7d` or `1month`
+    #[arg(long)]
+    pub keep_within: Option<String>,
+}
+impl RetentionPolicy {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+    pub fn to_keep_options(&self) -> Result<KeepOptions> {
+        let keep_within = self
+            .keep_within
+            .as_deref()
+            .map(humantime::Duration::from_str)
+            .transpose()
+            .with_context(|| {
+                format!(
+                    "Bad duration '{}'",
+                    self.keep_within.as_deref().unwrap_or_default()
+                )
+            })?;
+        Ok(KeepOptions::default()
+            .keep_last(self.keep_last)
+            .keep_hourly(self.keep_hourly)
+            .keep_daily(self.keep_daily)
+            .keep_weekly(self.keep_weekly)
+            .keep_monthly(self.keep_monthly)
+            .keep_within(keep_within))
+    }
+}
@@ -1,78 +1,636 @@
-use anyhow::{Result, anyhow};
+use anyhow::{Result, bail};
+use chrono::{DateTime, Local, TimeDelta};
 use rustic_core::{SnapshotOptions, repofile::SnapshotFile};
+use serde::{Deserialize, Serialize};
 use std::{
-    sync::Mutex,
+    cell::RefCell,
+    fmt::Display,
+    fs::{self, OpenOptions},
+    io::Write,
+    path::PathBuf,
+    sync::{Arc, LazyLock, Mutex, MutexGuard, atomic::Ordering},
     thread::{self, JoinHandle},
 };
 
-use crate::backup::AppState;
+use crate::{
+    MINESAVE_DATA_HOME,
+    backup::{AppState, PRE_RESTORE_LABEL},
+    progress::{ProgressState, TaskHandle, TaskProgressBars},
+    region::{ChunkPos, Dimension},
+    settings::Settings,
+    utils::report_err,
+};
 
-static TASKS: Mutex<Vec<Task>> = Mutex::new(vec![]);
+/// Finished tasks, one JSON document per line.
+const HISTORY_FILE: &str = "tasks.jsonl";
+const HISTORY_LIMIT: usize = 200;
 
-pub fn spawn(name: String, task_info: TaskInfo) {
-    debug!(
-        "task_spawn(id={}, name={})",
-        TASKS.lock().expect("Unable to lock TASKS").len(),
-        name
-    );
-    let worker = match task_info.clone() {
-        TaskInfo::Backup { for_id, options } => thread::spawn(move || {
-            if let Some(id) = for_id {
-                let mut instance = AppState::instance();
-                let save = instance.saves.get_mut(&id).ok_or(anyhow!("Invaild id"))?;
-                save.run_backup(options)?;
-                drop(instance);
-            } else {
-                let mut instance = AppState::instance();
-                for save in instance.saves.values_mut() {
-                    save.run_backup(options.clone())?;
-                }
-                drop(instance);
-            }
-            AppState::instance().save()
-        }),
-        TaskInfo::Recover { id, snapshot } => thread::spawn(move || {
-            let instance = AppState::instance();
-            instance.saves[&id].recover(snapshot)?;
-            Ok(())
-        }),
-    };
-    TASKS.lock().expect("Unable to lock TASKS").push(Task {
-        name,
-        info: task_info,
-        progress: 0,
+thread_local! {
+    /// The task running on this thread, picked up by `current_progress` and `checkpoint`.
+    static CURRENT: RefCell<Option<TaskHandle>> = const { RefCell::new(None) };
+}
+
+/// Error of a task stopped by `cancel`.
+#[derive(Debug, Clone, Copy)]
+pub struct Cancelled;
+
+impl Display for Cancelled {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Task cancelled")
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+pub fn spawn(name: String, task_info: TaskInfo) -> usize {
+    let mut tasks = lock();
+    let id = next_id(&tasks);
+    debug!("task_spawn(id={}, name={})", id, name);
+    let task = Task::new(id, TaskRecord::new(name, task_info.clone()));
+    let handle = task.handle.clone();
+    let record = task.record.clone();
+    let worker = thread::spawn(move || {
+        CURRENT.set(Some(handle.clone()));
+        let res = run(task_info);
+        let summary = res.as_ref().ok().cloned().flatten();
+        finish(&record, &handle, &res, summary);
+        trim(&mut lock());
+        res.map(|_| ())
+    });
+    tasks.push(Task {
         worker: Some(worker),
+        ..task
     });
+    id
+}
+
+/// Run `f` as a task on the current thread, so its progress is reported like a spawned one.
+pub fn track<T>(
+    name: String,
+    task_info: TaskInfo,
+    f: impl FnOnce() -> Result<T>,
+) -> (usize, Result<T>) {
+    let (id, handle, record) = {
+        let mut tasks = lock();
+        let id = next_id(&tasks);
+        debug!("task_track(id={}, name={})", id, name);
+        let task = Task::new(id, TaskRecord::new(name, task_info));
+        let handles = (id, task.handle.clone(), task.record.clone());
+        tasks.push(task);
+        handles
+    };
+    let previous = CURRENT.replace(Some(handle.clone()));
+    let res = f();
+    CURRENT.set(previous);
+    finish(&record, &handle, &res, None);
+    trim(&mut lock());
+    (id, res)
+}
+
+/// Spawn a finished task again. Returns the id of the new task.
+pub fn retry(id: usize) -> Option<usize> {
+    let record = find(&lock(), id)?.record()?;
+    record.finished?;
+    Some(spawn(record.name, record.info))
+}
+
+/// Ask a running task to stop. Returns whether the task was still running.
+pub fn cancel(id: usize) -> bool {
+    let tasks = lock();
+    let Some(task) = find(&tasks, id).filter(|x| x.is_running()) else {
+        return false;
+    };
+    info!("task_cancel(id={})", id);
+    task.handle.cancelled.store(true, Ordering::Release);
+    true
+}
+
+pub fn cancel_all() {
+    for task in lock().iter().filter(|x| x.is_running()) {
+        task.handle.cancelled.store(true, Ordering::Release);
+    }
+}
+
+/// Tell the user about something that went wrong without failing the task running on this
+/// thread. Shown along the task once it finished.
+pub fn warn(message: String) {
+    warn!("{}", message);
+    CURRENT.with_borrow(|x| {
+        if let Some(handle) = x {
+            handle
+                .warnings
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .push(message);
+        }
+    });
+}
+
+/// Fail with `Cancelled` if the task running on this thread was cancelled.
+pub fn checkpoint() -> Result<()> {
+    if CURRENT.with_borrow(|x| x.as_ref().is_some_and(TaskHandle::is_cancelled)) {
+        bail!(Cancelled);
+    }
+    Ok(())
 }
 
-pub fn wait_all() {
-    let mut tasks = TASKS.lock().unwrap();
-    let mut id: usize = 0;
-    while let Some(task) = tasks.pop() {
+/// The task running on the current thread, if any.
+pub fn current() -> Option<TaskHandle> {
+    CURRENT.with_borrow(Clone::clone)
+}
+
+/// Progress bars reporting to the task running on the current thread, if any.
+pub fn current_progress() -> TaskProgressBars {
+    current().map(TaskProgressBars::new).unwrap_or_default()
+}
+
+/// `Cancelled` rather than the error of a rustic command given up because the task running on
+/// this thread was cancelled.
+pub fn or_cancelled<T, E: Into<anyhow::Error>>(res: Result<T, E>) -> Result<T> {
+    if res.is_err() {
+        checkpoint()?;
+    }
+    res.map_err(Into::into)
+}
+
+/// Poll this to render the progress of tasks.
+pub fn list() -> Vec<TaskView> {
+    lock().iter().map(TaskView::from).collect()
+}
+
+/// Wait for every spawned task to finish. Returns the ids of those that succeeded.
+pub fn wait_all() -> Vec<usize> {
+    let mut succeeded = vec![];
+    loop {
+        let Some((id, worker)) = lock()
+            .iter_mut()
+            .find_map(|x| x.worker.take().map(|worker| (x.id, worker)))
+        else {
+            break;
+        };
         info!("waiting #{} to finish", id);
-        if let Err(err) = task.worker.unwrap().join() {
-            error!("#{}: {:?}", id, err);
+        match worker.join() {
+            Err(err) => error!("#{}: {:?}", id, err),
+            Ok(Err(err)) => error!("#{}: {:?}", id, err),
+            Ok(Ok(())) => succeeded.push(id),
         }
         info!("#{} finished", id);
-        id += 1;
     }
+    succeeded
+}
+
+/// Back up several saves with a pool of `Settings::backup_concurrency` workers, carrying on
+/// past failures. Results are in no particular order. The progress of the task counts the
+/// saves done, each worker reports its own to a part of the task.
+pub fn backup_many(
+    ids: Vec<String>,
+    options: SnapshotOptions,
+    skip_unchanged: bool,
+) -> Vec<(String, Result<Option<SnapshotFile>>)> {
+    let handle = current().unwrap_or_default();
+    handle.start_counter("backing up saves", ids.len() as u64, false);
+    let workers = { Settings::instance().backup_concurrency.max(1) } as usize;
+    let workers = workers.min(ids.len());
+    let queue = Mutex::new(ids.into_iter());
+    let results = Mutex::new(vec![]);
+    thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| {
+                CURRENT.set(Some(handle.part()));
+                loop {
+                    let Some(id) = queue.lock().unwrap_or_else(|e| e.into_inner()).next() else {
+                        break;
+                    };
+                    let res = checkpoint()
+                        .and_then(|()| AppState::backup(&id, options.clone(), skip_unchanged));
+                    handle.inc(1);
+                    results
+                        .lock()
+                        .unwrap_or_else(|e| e.into_inner())
+                        .push((id, res));
+                }
+            });
+        }
+    });
+    handle.stop_counter();
+    results.into_inner().unwrap_or_else(|e| e.into_inner())
+}
+
+/// One line per save that did not succeed, after the counts.
+fn summarize(results: &[(String, Result<Option<SnapshotFile>>)]) -> String {
+    let instance = AppState::instance();
+    let name = |id: &String| {
+        instance
+            .saves
+            .get(id)
+            .map_or(id.clone(), |x| x.name.clone())
+    };
+    let unchanged: Vec<_> = results
+        .iter()
+        .filter(|x| matches!(x.1, Ok(None)))
+        .map(|x| name(&x.0))
+        .collect();
+    let failed: Vec<_> = results
+        .iter()
+        .filter_map(|(id, res)| res.as_ref().err().map(|err| (name(id), err)))
+        .collect();
+    let mut summary = format!(
+        "{} succeeded, {} unchanged, {} failed",
+        results.len() - unchanged.len() - failed.len(),
+        unchanged.len(),
+        failed.len()
+    );
+    for name in unchanged {
+        summary += &format!("\n{}: unchanged", name);
+    }
+    for (name, err) in failed {
+        summary += &format!("\n{}: {:#}", name, err);
+    }
+    summary
+}
+
+/// Returns a summary to show along the task, if there is more to say than that it succeeded.
+fn run(task_info: TaskInfo) -> Result<Option<String>> {
+    match task_info {
+        TaskInfo::Backup {
+            for_id: Some(id),
+            options,
+        } => {
+            AppState::backup(&id, options, false)?;
+            Ok(None)
+        }
+        TaskInfo::Backup {
+            for_id: None,
+            options,
+        } => {
+            let ids = AppState::instance().present_ids();
+            let results = backup_many(ids, options, true);
+            let summary = summarize(&results);
+            if results.iter().any(|x| x.1.is_err()) {
+                bail!(summary);
+            }
+            Ok(Some(summary))
+        }
+        TaskInfo::Forget { id, prune } => {
+            let save = AppState::instance().saves[&id].clone();
+            save.forget(prune, false)?;
+            Ok(None)
+        }
+        TaskInfo::Export { id, destination } => {
+            let target = AppState::export(&id, &destination)?;
+            Ok(Some(target.display().to_string()))
+        }
+        TaskInfo::Recover {
+            id,
+            snapshot,
+            in_place: false,
+        } => {
+            let save = AppState::instance().saves[&id].clone();
+            save.recover(*snapshot)?;
+            Ok(None)
+        }
+        TaskInfo::Recover {
+            id,
+            snapshot,
+            in_place: true,
+        } => {
+            let safety = AppState::restore_in_place(&id, *snapshot)?;
+            Ok(safety.map(|x| format!("{}: {}", PRE_RESTORE_LABEL, x.id)))
+        }
+        TaskInfo::RestoreTo {
+            id,
+            snapshot,
+            destination,
+        } => {
+            let save = AppState::instance().saves[&id].clone();
+            save.restore_to(*snapshot, &destination)?;
+            Ok(Some(destination.display().to_string()))
+        }
+        TaskInfo::RestorePaths {
+            id,
+            snapshot,
+            paths,
+        } => {
+            let safety = AppState::restore_paths(&id, *snapshot, &paths)?;
+            Ok(safety.map(|x| format!("{}: {}", PRE_RESTORE_LABEL, x.id)))
+        }
+        TaskInfo::RestoreChunks {
+            id,
+            snapshot,
+            dimension,
+            chunks,
+        } => {
+            let (safety, replaced, removed) =
+                AppState::restore_chunks(&id, *snapshot, dimension, &chunks)?;
+            let mut summary = format!("{} chunk(s) replaced, {} removed", replaced, removed);
+            if let Some(safety) = safety {
+                summary += &format!(", {}: {}", PRE_RESTORE_LABEL, safety.id);
+            }
+            Ok(Some(summary))
+        }
+        TaskInfo::RestorePlayer {
+            id,
+            snapshot,
+            player,
+        } => {
+            let (safety, paths) = AppState::restore_player(&id, *snapshot, &player)?;
+            let mut summary = format!("{} file(s) restored", paths.len());
+            if let Some(safety) = safety {
+                summary += &format!(", {}: {}", PRE_RESTORE_LABEL, safety.id);
+            }
+            Ok(Some(summary))
+        }
+        TaskInfo::RestoreCopy { id, snapshot } => {
+            let copy = AppState::restore_as_copy(&id, *snapshot)?;
+            Ok(Some(
+                AppState::instance().saves[&copy]
+                    .source()
+                    .display()
+                    .to_string(),
+            ))
+        }
+    }
+}
+
+/// Record how a task ended and append it to the history.
+fn finish<T>(
+    record: &Mutex<TaskRecord>,
+    handle: &TaskHandle,
+    res: &Result<T>,
+    summary: Option<String>,
+) {
+    let mut record = record.lock().unwrap_or_else(|e| e.into_inner());
+    record.finished = Some(Local::now());
+    record.summary = summary;
+    record.warnings = handle
+        .warnings
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .clone();
+    if let Err(err) = res {
+        record.error = Some(format!("{:#}", err));
+        record.cancelled = err.is::<Cancelled>();
+    }
+    if let Ok(mut file) = OpenOptions::new()
+        .append(true)
+        .create(true)
+        .open(MINESAVE_DATA_HOME.join(HISTORY_FILE))
+        .inspect_err(report_err("Failed to open task history"))
+        && let Ok(line) =
+            serde_json::to_string(&*record).inspect_err(report_err("Failed to save task history"))
+    {
+        writeln!(file, "{}", line)
+            .inspect_err(report_err("Failed to save task history"))
+            .unwrap_or_default();
+    }
+}
+
+/// Finished tasks of previous runs, keeping at most `HISTORY_LIMIT` of them on disk.
+fn load_history() -> Vec<TaskRecord> {
+    let Ok(content) = fs::read_to_string(MINESAVE_DATA_HOME.join(HISTORY_FILE)) else {
+        return vec![];
+    };
+    let lines: Vec<&str> = content.lines().collect();
+    let lines = &lines[lines.len().saturating_sub(HISTORY_LIMIT)..];
+    if lines.len() < content.lines().count() {
+        fs::write(
+            MINESAVE_DATA_HOME.join(HISTORY_FILE),
+            lines.iter().map(|x| format!("{}\n", x)).collect::<String>(),
+        )
+        .inspect_err(report_err("Failed to trim task history"))
+        .unwrap_or_default();
+    }
+    lines
+        .iter()
+        .filter_map(|x| {
+            serde_json::from_str(x)
+                .inspect_err(report_err("Bad task history entry"))
+                .ok()
+        })
+        .collect()
+}
+
+/// Tasks are kept in the order of their ids, which stay the same once older tasks are trimmed.
+fn next_id(tasks: &[Task]) -> usize {
+    tasks.last().map_or(0, |x| x.id + 1)
+}
+
+fn find(tasks: &[Task], id: usize) -> Option<&Task> {
+    tasks
+        .binary_search_by_key(&id, |x| x.id)
+        .ok()
+        .map(|x| &tasks[x])
+}
+
+/// Forget the oldest finished tasks beyond `HISTORY_LIMIT`, like the history does on disk.
+fn trim(tasks: &mut Vec<Task>) {
+    let mut excess = tasks
+        .iter()
+        .filter(|x| !x.is_running())
+        .count()
+        .saturating_sub(HISTORY_LIMIT);
+    tasks.retain(|x| {
+        let forget = excess > 0 && !x.is_running();
+        excess -= forget as usize;
+        !forget
+    });
+}
+
+fn lock() -> MutexGuard<'static, Vec<Task>> {
+    static TASKS: LazyLock<Mutex<Vec<Task>>> = LazyLock::new(|| {
+        Mutex::new(
+            load_history()
+                .into_iter()
+                .enumerate()
+                .map(|(id, record)| Task::new(id, record))
+                .collect(),
+        )
+    });
+    TASKS.lock().expect("Unable to lock TASKS")
 }
 
 pub struct Task {
-    name: String,
-    info: TaskInfo,
-    progress: u8,
+    id: usize,
+    record: Arc<Mutex<TaskRecord>>,
+    handle: TaskHandle,
     worker: Option<JoinHandle<Result<()>>>,
 }
 
-#[derive(Debug, Clone)]
+impl Task {
+    fn new(id: usize, record: TaskRecord) -> Self {
+        Self {
+            id,
+            record: Arc::new(Mutex::new(record)),
+            handle: TaskHandle::default(),
+            worker: None,
+        }
+    }
+
+    fn record(&self) -> Option<TaskRecord> {
+        self.record.lock().ok().map(|x| x.clone())
+    }
+
+    fn is_running(&self) -> bool {
+        self.record().is_some_and(|x| x.finished.is_none())
+    }
+}
+
+/// What is kept of a task, and persisted in the history once it finished.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TaskRecord {
+    pub name: String,
+    pub info: TaskInfo,
+    pub started: DateTime<Local>,
+    pub finished: Option<DateTime<Local>>,
+    pub error: Option<String>,
+    pub cancelled: bool,
+    #[serde(default)]
+    pub summary: Option<String>,
+    #[serde(default)]
+    pub warnings: Vec<String>,
+}
+
+impl TaskRecord {
+    fn new(name: String, info: TaskInfo) -> Self {
+        Self {
+            name,
+            info,
+            started: Local::now(),
+            finished: None,
+            error: None,
+            cancelled: false,
+            summary: None,
+            warnings: vec![],
+        }
+    }
+}
+
+/// A copy of the state of a task, detached from the registry.
+#[derive(Debug, Clone, Serialize)]
+pub struct TaskView {
+    pub id: usize,
+    pub name: String,
+    pub kind: &'static str,
+    pub running: bool,
+    /// Whether the task was asked to stop, or stopped because of that
+    pub cancelled: bool,
+    pub started: DateTime<Local>,
+    pub finished: Option<DateTime<Local>>,
+    pub error: Option<String>,
+    pub summary: Option<String>,
+    pub warnings: Vec<String>,
+    pub progress: ProgressState,
+}
+
+impl TaskView {
+    pub fn duration(&self) -> TimeDelta {
+        self.finished.unwrap_or_else(Local::now) - self.started
+    }
+}
+
+impl From<&Task> for TaskView {
+    fn from(value: &Task) -> Self {
+        let record = value
+            .record
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone();
+        Self {
+            id: value.id,
+            name: record.name,
+            kind: record.info.kind(),
+            running: record.finished.is_none(),
+            cancelled: record.cancelled || value.handle.is_cancelled(),
+            started: record.started,
+            finished: record.finished,
+            error: record.error,
+            summary: record.summary,
+            warnings: if record.finished.is_some() {
+                record.warnings
+            } else {
+                value
+                    .handle
+                    .warnings
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .clone()
+            },
+            progress: value
+                .handle
+                .progress
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum TaskInfo {
     Backup {
         for_id: Option<String>,
         options: SnapshotOptions,
     },
+    Forget {
+        id: String,
+        prune: bool,
+    },
+    /// Copy the repository of a save out of the data directory
+    Export {
+        id: String,
+        destination: PathBuf,
+    },
     Recover {
         id: String,
-        snapshot: SnapshotFile,
+        snapshot: Box<SnapshotFile>,
+        /// Replace the world instead of restoring next to it
+        #[serde(default)]
+        in_place: bool,
+    },
+    /// Restore as a new world at `destination`, also for saves whose world is gone
+    RestoreTo {
+        id: String,
+        snapshot: Box<SnapshotFile>,
+        destination: PathBuf,
+    },
+    /// Replace some paths of the world, relative to it
+    RestorePaths {
+        id: String,
+        snapshot: Box<SnapshotFile>,
+        paths: Vec<PathBuf>,
+    },
+    /// Copy chunks of a dimension into the region files of the world
+    RestoreChunks {
+        id: String,
+        snapshot: Box<SnapshotFile>,
+        dimension: Dimension,
+        chunks: Vec<ChunkPos>,
     },
+    /// Put back the files of one player, by UUID
+    RestorePlayer {
+        id: String,
+        snapshot: Box<SnapshotFile>,
+        player: String,
+    },
+    /// Restore as a new save next to the world
+    RestoreCopy {
+        id: String,
+        snapshot: Box<SnapshotFile>,
+    },
+}
+
+impl TaskInfo {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            TaskInfo::Backup { .. } => "backup",
+            TaskInfo::Forget { .. } => "forget",
+            TaskInfo::Export { .. } => "export",
+            TaskInfo::Recover { .. } => "recover",
+            TaskInfo::RestoreTo { .. }
+            | TaskInfo::RestorePaths { .. }
+            | TaskInfo::RestoreChunks { .. }
+            | TaskInfo::RestorePlayer { .. }
+            | TaskInfo::RestoreCopy { .. } => "restore",
+        }
+    }
 }
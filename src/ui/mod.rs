@@ -11,7 +11,10 @@ mod utils;
 
 const APP_ID: &str = "io.github.lwb-2021.MineSave";
 pub fn run_app() -> ExitCode {
-    if let Err(_) = gtk4::init().inspect_err(report_err("Failed to init UI")) {
+    if gtk4::init()
+        .inspect_err(report_err("Failed to init UI"))
+        .is_err()
+    {
         return ExitCode::FAILURE;
     }
 
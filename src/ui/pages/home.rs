@@ -1,6 +1,8 @@
+use std::time::Duration;
+
 use gtk4::{
-    Box, Button, GridLayout, Label, Orientation,
-    prelude::{BoxExt, ButtonExt},
+    Box, Button, GridLayout, Label, Orientation, ProgressBar,
+    prelude::{BoxExt, ButtonExt, WidgetExt},
 };
 use rustic_core::SnapshotOptions;
 
@@ -25,7 +27,7 @@ pub fn home() -> Box {
         )
         .build();
     cards.append(&cardify({
-        let backup_button = Button::with_label(&t!("pages.home.quick-backup").to_string());
+        let backup_button = Button::with_label(&t!("pages.home.quick-backup"));
         backup_button.connect_clicked(|_| {
             tasks::spawn(
                 t!("pages.home.quick-backup").to_string(),
@@ -58,6 +60,71 @@ pub fn home() -> Box {
         b.append(&backup_button);
         b
     }));
+    cards.append(&cardify(running_tasks()));
     wrapper.append(&cards);
     wrapper
 }
+
+/// A card showing the progress of running tasks, refreshed twice a second.
+fn running_tasks() -> Box {
+    let b = Box::builder()
+        .width_request(320)
+        .orientation(Orientation::Vertical)
+        .spacing(4)
+        .build();
+    b.append(&title(t!("pages.home.running-tasks")));
+    let list = Box::builder().orientation(Orientation::Vertical).build();
+    b.append(&list);
+
+    let refresh = move || {
+        while let Some(child) = list.first_child() {
+            list.remove(&child);
+        }
+        let running: Vec<_> = tasks::list().into_iter().filter(|x| x.running).collect();
+        if running.is_empty() {
+            list.append(
+                &Label::builder()
+                    .label(t!("pages.home.no-running-tasks"))
+                    .xalign(0.0)
+                    .build(),
+            );
+        }
+        for task in running {
+            let row = Box::builder()
+                .orientation(Orientation::Horizontal)
+                .spacing(8)
+                .build();
+            row.append(
+                &Label::builder()
+                    .label(&task.name)
+                    .hexpand(true)
+                    .xalign(0.0)
+                    .build(),
+            );
+            let cancel_button = Button::builder()
+                .label(t!("messages.cancel").to_string())
+                .sensitive(!task.cancelled)
+                .build();
+            let id = task.id;
+            cancel_button.connect_clicked(move |btn| {
+                tasks::cancel(id);
+                btn.set_sensitive(false);
+            });
+            row.append(&cancel_button);
+            list.append(&row);
+            let bar = ProgressBar::builder()
+                .show_text(true)
+                .text(task.progress.to_string())
+                .build();
+            match task.progress.fraction() {
+                Some(fraction) => bar.set_fraction(fraction),
+                None => bar.pulse(),
+            }
+            list.append(&bar);
+        }
+        gtk4::glib::ControlFlow::Continue
+    };
+    refresh();
+    gtk4::glib::timeout_add_local(Duration::from_millis(500), refresh);
+    b
+}
@@ -6,6 +6,7 @@ use gtk4::{
 mod home;
 mod saves;
 mod settings;
+mod tasks;
 
 pub fn pages(notebook: &mut Notebook) {
     notebook.append_page(
@@ -13,6 +14,10 @@ pub fn pages(notebook: &mut Notebook) {
         Some(&Image::from_icon_name("go-home-symbolic")),
     );
     notebook.append_page(&saves::saves(), Some(&Image::from_icon_name("backup")));
+    notebook.append_page(
+        &tasks::tasks(),
+        Some(&Image::from_icon_name("view-list-symbolic")),
+    );
     notebook.append_page(
         &settings::settings(),
         Some(&Image::from_icon_name("settings")),
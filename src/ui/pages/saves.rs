@@ -1,29 +1,44 @@
 use std::{
     cell::RefCell,
+    collections::{BTreeSet, HashSet},
+    path::{Path, PathBuf},
+    rc::Rc,
     sync::{Arc, Mutex},
     thread,
+    time::Duration,
 };
 
 use gtk4::{
-    Box, Button, Image, Label, Spinner, Window,
-    glib::{MainContext, object::Cast},
-    prelude::{BoxExt, ButtonExt, EditableExt, GtkWindowExt, WidgetExt},
+    Box, Button, CheckButton, Image, Label, ScrolledWindow, Spinner, Window,
+    glib::object::Cast,
+    prelude::{BoxExt, ButtonExt, CheckButtonExt, EditableExt, EntryExt, GtkWindowExt, WidgetExt},
 };
 use rustic_core::{SnapshotOptions, repofile::SnapshotFile};
 
 use crate::{
     MINESAVE_DATA_HOME,
-    backup::AppState,
+    backup::{AppState, SaveBackupConfiguration, TreeEntry},
+    diff::{self, Change, Kind},
+    items::{self, ItemQuery},
+    level::{Difficulty, GameMode, WorldInfo},
+    players::{self, PlayerInfo},
+    region::{Area, ChunkPos, Dimension},
+    scheduler,
     tasks::{self, TaskInfo},
     ui::{
         pages::build_wrapper,
         utils::{cardify, dialog_button_box, dialog_wrapper, title, with_label},
     },
+    utils::report_err,
 };
 
 pub fn saves() -> Box {
     let wrapper = build_wrapper();
+    let present: HashSet<String> = AppState::instance().present_ids().into_iter().collect();
     for (id0, save) in AppState::instance().saves.iter() {
+        if !present.contains(id0) {
+            continue;
+        }
         let save_card = Box::builder()
             .orientation(gtk4::Orientation::Horizontal)
             .build();
@@ -36,7 +51,7 @@ pub fn saves() -> Box {
                 .file(
                     MINESAVE_DATA_HOME
                         .join("resources")
-                        .join(&id0)
+                        .join(id0)
                         .with_extension("png")
                         .to_string_lossy()
                         .to_string(),
@@ -53,13 +68,13 @@ pub fn saves() -> Box {
             .spacing(8)
             .build();
 
-        let backup_button = Button::with_label(&t!("pages.saves.backup").to_string());
-        let recover_button = Button::with_label(&t!("pages.saves.recover").to_string());
+        let backup_button = Button::with_label(&t!("pages.saves.backup"));
+        let recover_button = Button::with_label(&t!("pages.saves.recover"));
 
         let for_id = id0.clone();
 
         backup_button.connect_clicked(move |_| {
-            let cancelled = Arc::new(RefCell::new(true));
+            let cancelled = Rc::new(RefCell::new(true));
 
             let inner = dialog_wrapper();
             let (b1, label_input) = with_label::text_input(t!("pages.saves.label"), String::new());
@@ -80,7 +95,7 @@ pub fn saves() -> Box {
 
             let for_id = for_id.clone();
             dialog.connect_close_request(move |_| {
-                if cancelled.borrow().clone() {
+                if *cancelled.borrow() {
                     return gtk4::glib::Propagation::Proceed;
                 }
 
@@ -100,100 +115,1574 @@ pub fn saves() -> Box {
         let id = id0.clone();
 
         recover_button.connect_clicked(move |_| {
-            let save = &AppState::instance().saves[&id];
-            let cancel_btn = Button::with_label(&t!("messages.cancel").to_string());
-            cancel_btn.connect_clicked(|btn| {
-                let window: Window = btn.root().unwrap().dynamic_cast().unwrap();
-                window.close();
+            let save = AppState::instance().saves[&id].clone();
+            snapshot_dialog(save, true);
+        });
+
+        let history_button = Button::with_label(&t!("pages.saves.chunk-history"));
+        let id = id0.clone();
+        history_button.connect_clicked(move |_| {
+            let save = AppState::instance().saves[&id].clone();
+            area_dialog(
+                t!("pages.saves.chunk-history").to_string(),
+                move |dimension, chunks| history_dialog(save.clone(), dimension, chunks),
+            );
+        });
+
+        let details_button = Button::with_label(&t!("pages.saves.details"));
+        let id = id0.clone();
+        details_button.connect_clicked(move |_| {
+            details_dialog(AppState::instance().saves[&id].clone());
+        });
+
+        let items_button = Button::with_label(&t!("pages.saves.find-items"));
+        let id = id0.clone();
+        items_button.connect_clicked(move |_| {
+            items_dialog(AppState::instance().saves[&id].clone());
+        });
+
+        button_box.append(&Label::builder().hexpand(true).build());
+        button_box.append(&backup_button);
+        button_box.append(&recover_button);
+        button_box.append(&history_button);
+        button_box.append(&items_button);
+        button_box.append(&details_button);
+        save_card_right.append(&title(gtk4::glib::markup_escape_text(save.display_name())));
+        if let Some(info) = &save.info {
+            save_card_right.append(
+                &Label::builder()
+                    .label(world_summary(info))
+                    .tooltip_text(world_details(info))
+                    .wrap(true)
+                    .xalign(0.0)
+                    .build(),
+            );
+        }
+        save_card_right.append(
+            &Label::builder()
+                .label(format!(
+                    "{}: {}",
+                    t!("pages.saves.next-backup"),
+                    scheduler::next_run(save).map_or_else(
+                        || t!("pages.saves.never").to_string(),
+                        |x| x.format("%Y/%m/%d %H:%M").to_string()
+                    )
+                ))
+                .xalign(0.0)
+                .build(),
+        );
+        save_card_right.append(&trash_box(id0.clone()));
+        save_card_right.append(&button_box);
+
+        save_card.append(&save_card_left);
+        save_card.append(&save_card_right);
+        wrapper.append(&cardify(save_card));
+    }
+    if let Some(orphaned) = orphaned_saves() {
+        wrapper.append(&orphaned);
+    }
+    if let Some(archived) = archived_saves() {
+        wrapper.append(&archived);
+    }
+    let import_button = Button::builder()
+        .label(t!("pages.saves.import").to_string())
+        .halign(gtk4::Align::Start)
+        .build();
+    import_button.connect_clicked(|_| {
+        if let Ok(Some(path)) = native_dialog::FileDialogBuilder::default()
+            .open_single_dir()
+            .show()
+            .inspect_err(report_err("Failed to open dialog"))
+            && let Err(err) = AppState::import(&path)
+        {
+            alert_failure(&err);
+        }
+    });
+    wrapper.append(&import_button);
+
+    wrapper
+}
+
+/// A dialog listing the snapshots of a save, loaded in the background, with the ways to
+/// restore each. Only `restore_to` is offered when the world is not `present`.
+fn snapshot_dialog(save: SaveBackupConfiguration, present: bool) {
+    let cancel_btn = Button::with_label(&t!("messages.cancel"));
+    cancel_btn.connect_clicked(|btn| {
+        let window: Window = btn.root().unwrap().dynamic_cast().unwrap();
+        window.close();
+    });
+    let inner = dialog_wrapper();
+    inner.set_valign(gtk4::Align::Fill);
+    let spinner = Spinner::new();
+    inner.append(&spinner);
+    let dialog = Window::builder()
+        .title(t!("pages.saves.backup"))
+        .child(&inner)
+        .modal(true)
+        .build();
+    dialog.present();
+    spinner.start();
+
+    let data = Arc::new(Mutex::new(None));
+    let data_ref = data.clone();
+    let save0 = save.clone();
+
+    thread::spawn(move || {
+        let mut data = data_ref.lock().unwrap();
+        *data = Some(save0.list_backups().unwrap_or_default());
+        drop(data);
+    });
+
+    gtk4::glib::source::idle_add_local(move || {
+        let Ok(data) = data.try_lock() else {
+            return gtk4::glib::ControlFlow::Continue;
+        };
+        let Some(data) = data.as_ref() else {
+            return gtk4::glib::ControlFlow::Continue;
+        };
+        spinner.stop();
+        for snapshot in data {
+            let row = Box::builder()
+                .orientation(gtk4::Orientation::Horizontal)
+                .spacing(8)
+                .build();
+            let btn = build_button_from_snapshot(snapshot);
+            btn.set_hexpand(true);
+            let in_place_btn = Button::with_label(&t!("pages.saves.restore-in-place"));
+            let copy_btn = Button::with_label(&t!("pages.saves.restore-copy"));
+            let paths_btn = Button::with_label(&t!("pages.saves.restore-paths"));
+            let chunks_btn = Button::with_label(&t!("pages.saves.restore-chunks"));
+            let player_btn = Button::with_label(&t!("pages.saves.restore-player"));
+            let restore_to_btn = Button::with_label(&t!("pages.saves.restore-to"));
+            let name = format!(
+                "{}: {}/{}",
+                t!("pages.saves.recover"),
+                save.name,
+                snapshot.label
+            );
+            for (btn, in_place) in [(&btn, false), (&in_place_btn, true)] {
+                let id = save.id().to_string();
+                let name = name.clone();
+                let snapshot = snapshot.clone();
+                let save_name = save.name.clone();
+                btn.connect_clicked(move |_| {
+                    // The world is replaced, even if it can be put back
+                    if in_place
+                        && !native_dialog::MessageDialogBuilder::default()
+                            .set_title(t!("pages.saves.restore-in-place"))
+                            .set_text(t!(
+                                "messages.confirm-restore-in-place",
+                                name = save_name,
+                                time = snapshot.time.format("%Y-%m-%d %H:%M:%S")
+                            ))
+                            .confirm()
+                            .show()
+                            .inspect_err(report_err("Failed to open dialog"))
+                            .unwrap_or_default()
+                    {
+                        return;
+                    }
+                    tasks::spawn(
+                        name.clone(),
+                        TaskInfo::Recover {
+                            id: id.clone(),
+                            snapshot: std::boxed::Box::new(snapshot.clone()),
+                            in_place,
+                        },
+                    );
+                });
+            }
+            let save0 = save.clone();
+            let snapshot0 = snapshot.clone();
+            paths_btn.connect_clicked(move |_| tree_dialog(save0.clone(), snapshot0.clone()));
+            let save0 = save.clone();
+            let snapshot0 = snapshot.clone();
+            chunks_btn.connect_clicked(move |_| {
+                let save = save0.clone();
+                let snapshot = snapshot0.clone();
+                area_dialog(
+                    t!("pages.saves.restore-chunks").to_string(),
+                    move |dimension, chunks| restore_chunks(&save, &snapshot, dimension, chunks),
+                );
             });
-            let inner = dialog_wrapper();
-            inner.set_valign(gtk4::Align::Fill);
+            let save0 = save.clone();
+            let snapshot0 = snapshot.clone();
+            player_btn.connect_clicked(move |_| player_dialog(save0.clone(), snapshot0.clone()));
+            let id = save.id().to_string();
+            let snapshot0 = snapshot.clone();
+            let name0 = name.clone();
+            copy_btn.connect_clicked(move |_| {
+                tasks::spawn(
+                    name0.clone(),
+                    TaskInfo::RestoreCopy {
+                        id: id.clone(),
+                        snapshot: std::boxed::Box::new(snapshot0.clone()),
+                    },
+                );
+            });
+            let id = save.id().to_string();
+            let snapshot0 = snapshot.clone();
+            // The chosen directory exists already, restore into a new one inside it
+            let dir_name = format!(
+                "{} ({})",
+                save.name,
+                snapshot.time.format("%Y-%m-%d %H-%M-%S")
+            );
+            restore_to_btn.connect_clicked(move |_| {
+                if let Ok(Some(parent)) = native_dialog::FileDialogBuilder::default()
+                    .open_single_dir()
+                    .show()
+                    .inspect_err(report_err("Failed to open dialog"))
+                {
+                    tasks::spawn(
+                        name.clone(),
+                        TaskInfo::RestoreTo {
+                            id: id.clone(),
+                            snapshot: std::boxed::Box::new(snapshot0.clone()),
+                            destination: parent.join(&dir_name),
+                        },
+                    );
+                }
+            });
+            row.append(&btn);
+            if present {
+                row.append(&in_place_btn);
+                row.append(&copy_btn);
+                row.append(&paths_btn);
+                row.append(&chunks_btn);
+                row.append(&player_btn);
+            } else {
+                // Without the world there is nothing to restore next to
+                btn.set_sensitive(false);
+            }
+            row.append(&restore_to_btn);
+            let previous = data
+                .iter()
+                .filter(|x| x.time < snapshot.time)
+                .max_by_key(|x| x.time);
+            if let Some(previous) = previous {
+                let compare_btn = Button::with_label(&t!("pages.saves.compare"));
+                let save = save.clone();
+                let (from, to) = (previous.clone(), snapshot.clone());
+                compare_btn
+                    .connect_clicked(move |_| diff_dialog(save.clone(), from.clone(), to.clone()));
+                row.append(&compare_btn);
+            }
+            if previous.is_some() || present {
+                let files_btn = Button::with_label(&t!("pages.saves.changed-files"));
+                let save = save.clone();
+                let (snapshot, previous) = (snapshot.clone(), previous.cloned());
+                files_btn.connect_clicked(move |_| {
+                    files_dialog(save.clone(), snapshot.clone(), previous.clone(), present)
+                });
+                row.append(&files_btn);
+            }
+            inner.append(&row);
+        }
+
+        inner.remove(&spinner);
+        inner.append(&cancel_btn);
+        gtk4::glib::ControlFlow::Break
+    });
+}
+
+/// A browser of the files in a snapshot, to put some of them back into the world after a
+/// pre-restore snapshot.
+fn tree_dialog(save: SaveBackupConfiguration, snapshot: SnapshotFile) {
+    let inner = dialog_wrapper();
+    let spinner = Spinner::new();
+    inner.append(&spinner);
+    let dialog = Window::builder()
+        .title(t!("pages.saves.restore-paths"))
+        .child(&inner)
+        .default_width(560)
+        .default_height(480)
+        .modal(true)
+        .build();
+    dialog.present();
+    spinner.start();
+
+    let data = Arc::new(Mutex::new(None));
+    let data_ref = data.clone();
+    let save0 = save.clone();
+    let snapshot0 = snapshot.clone();
+
+    thread::spawn(move || {
+        let mut data = data_ref.lock().unwrap();
+        *data = Some(save0.list_snapshot(&snapshot0).unwrap_or_default());
+        drop(data);
+    });
+
+    gtk4::glib::source::idle_add_local(move || {
+        let Ok(mut data) = data.try_lock() else {
+            return gtk4::glib::ControlFlow::Continue;
+        };
+        let Some(entries) = data.take() else {
+            return gtk4::glib::ControlFlow::Continue;
+        };
+        spinner.stop();
+        inner.remove(&spinner);
+
+        let browser = TreeBrowser {
+            entries: Rc::new(entries),
+            selected: Rc::new(RefCell::new(BTreeSet::new())),
+            list: Box::builder()
+                .orientation(gtk4::Orientation::Vertical)
+                .spacing(4)
+                .build(),
+            path_label: Label::builder().xalign(0.0).build(),
+            selected_label: Label::builder().xalign(0.0).build(),
+            restore_btn: Button::with_label(&t!("pages.saves.restore-selected")),
+        };
+        inner.append(&browser.path_label);
+        inner.append(
+            &ScrolledWindow::builder()
+                .child(&browser.list)
+                .vexpand(true)
+                .build(),
+        );
+        inner.append(&browser.selected_label);
+
+        let id = save.id().to_string();
+        let name = format!(
+            "{}: {}/{}",
+            t!("pages.saves.recover"),
+            save.name,
+            snapshot.label
+        );
+        let snapshot = snapshot.clone();
+        let selected = browser.selected.clone();
+        browser.restore_btn.connect_clicked(move |btn| {
+            tasks::spawn(
+                name.clone(),
+                TaskInfo::RestorePaths {
+                    id: id.clone(),
+                    snapshot: std::boxed::Box::new(snapshot.clone()),
+                    paths: selected.borrow().iter().cloned().collect(),
+                },
+            );
+            let window: Window = btn.root().unwrap().dynamic_cast().unwrap();
+            window.close();
+        });
+        let cancel_btn = Button::with_label(&t!("messages.cancel"));
+        cancel_btn.connect_clicked(|btn| {
+            let window: Window = btn.root().unwrap().dynamic_cast().unwrap();
+            window.close();
+        });
+        let button_box = Box::builder()
+            .orientation(gtk4::Orientation::Horizontal)
+            .spacing(8)
+            .halign(gtk4::Align::Center)
+            .build();
+        button_box.append(&browser.restore_btn);
+        button_box.append(&cancel_btn);
+        inner.append(&button_box);
+
+        browser.update_selection();
+        browser.show(Path::new(""));
+        gtk4::glib::ControlFlow::Break
+    });
+}
+
+/// The players of a snapshot, any of whom can be restored alone while the others keep their
+/// progress.
+fn player_dialog(save: SaveBackupConfiguration, snapshot: SnapshotFile) {
+    let cancel_btn = Button::with_label(&t!("messages.cancel"));
+    cancel_btn.connect_clicked(|btn| {
+        let window: Window = btn.root().unwrap().dynamic_cast().unwrap();
+        window.close();
+    });
+    let inner = dialog_wrapper();
+    let spinner = Spinner::new();
+    inner.append(&spinner);
+    let dialog = Window::builder()
+        .title(t!("pages.saves.restore-player"))
+        .child(&ScrolledWindow::builder().child(&inner).build())
+        .default_width(560)
+        .default_height(480)
+        .modal(true)
+        .build();
+    dialog.present();
+    spinner.start();
+
+    let data = Arc::new(Mutex::new(None));
+    let data_ref = data.clone();
+    let (save0, snapshot0) = (save.clone(), snapshot.clone());
+
+    thread::spawn(move || {
+        let mut data = data_ref.lock().unwrap();
+        let res = save0.list_snapshot(&snapshot0).map(|entries| {
+            let names = players::usercache(save0.source());
+            entries
+                .into_iter()
+                .filter(|x| x.path.parent() == Some(Path::new("playerdata")))
+                .filter_map(|x| {
+                    let name = x.path.file_name()?.to_str()?.strip_suffix(".dat")?;
+                    let uuid = players::parse_uuid(name)?;
+                    Some((names.get(&uuid).cloned(), uuid))
+                })
+                .collect::<Vec<_>>()
+        });
+        *data = Some(res);
+        drop(data);
+    });
+
+    gtk4::glib::source::idle_add_local(move || {
+        let Ok(mut data) = data.try_lock() else {
+            return gtk4::glib::ControlFlow::Continue;
+        };
+        let Some(res) = data.take() else {
+            return gtk4::glib::ControlFlow::Continue;
+        };
+        spinner.stop();
+        inner.remove(&spinner);
+        let label = |text: String| {
+            Label::builder()
+                .label(text)
+                .wrap(true)
+                .hexpand(true)
+                .xalign(0.0)
+                .build()
+        };
+        match res {
+            Ok(mut players) => {
+                players.sort();
+                if players.is_empty() {
+                    inner.append(&label(t!("pages.saves.no-players").to_string()));
+                }
+                for (name, uuid) in players {
+                    let row = Box::builder()
+                        .orientation(gtk4::Orientation::Horizontal)
+                        .spacing(8)
+                        .build();
+                    row.append(&label(match &name {
+                        Some(name) => format!("{}\n{}", name, uuid),
+                        None => uuid.clone(),
+                    }));
+                    let restore_btn = Button::with_label(&t!("pages.saves.recover"));
+                    let (save, snapshot) = (save.clone(), snapshot.clone());
+                    restore_btn.connect_clicked(move |btn| {
+                        tasks::spawn(
+                            format!(
+                                "{}: {}/{}",
+                                t!("pages.saves.restore-player"),
+                                save.name,
+                                name.as_deref().unwrap_or(&uuid)
+                            ),
+                            TaskInfo::RestorePlayer {
+                                id: save.id().to_string(),
+                                snapshot: std::boxed::Box::new(snapshot.clone()),
+                                player: uuid.clone(),
+                            },
+                        );
+                        let window: Window = btn.root().unwrap().dynamic_cast().unwrap();
+                        window.close();
+                    });
+                    row.append(&restore_btn);
+                    inner.append(&row);
+                }
+            }
+            Err(err) => inner.append(&label(format!("{:#}", err))),
+        }
+        inner.append(&cancel_btn);
+        gtk4::glib::ControlFlow::Break
+    });
+}
+
+/// Ask for a dimension and an area of blocks, and pass the chunks it touches to `on_ok`.
+fn area_dialog(title: String, on_ok: impl Fn(Dimension, Vec<ChunkPos>) + 'static) {
+    let inner = dialog_wrapper();
+    let (b1, dimension_input) = with_label::drop_down(
+        t!("pages.saves.dimension"),
+        &Dimension::ALL
+            .map(|x| match x {
+                Dimension::Overworld => t!("pages.saves.overworld"),
+                Dimension::Nether => t!("pages.saves.nether"),
+                Dimension::End => t!("pages.saves.end"),
+            })
+            .each_ref()
+            .map(|x| x.as_ref()),
+        0,
+    );
+    let (b2, area_input) = with_label::text_input(t!("pages.saves.area"), String::new());
+    area_input.set_placeholder_text(Some("x1,z1:x2,z2"));
+    inner.append(&b1);
+    inner.append(&b2);
+    let cancelled = Rc::new(RefCell::new(true));
+    inner.append(&dialog_button_box(cancelled.clone()));
+
+    let dialog = Window::builder()
+        .title(title)
+        .child(&inner)
+        .modal(true)
+        .build();
+    dialog.present();
+
+    dialog.connect_close_request(move |_| {
+        if *cancelled.borrow() {
+            return gtk4::glib::Propagation::Proceed;
+        }
+        let area: Area = match area_input.text().parse() {
+            Ok(area) => area,
+            Err(err) => {
+                alert_failure(&err);
+                // Closing the window without OK again cancels
+                *cancelled.borrow_mut() = true;
+                return gtk4::glib::Propagation::Stop;
+            }
+        };
+        let dimension = Dimension::ALL
+            .get(dimension_input.selected() as usize)
+            .copied()
+            .unwrap_or(Dimension::Overworld);
+        on_ok(dimension, area.chunks());
+        gtk4::glib::Propagation::Proceed
+    });
+}
+
+fn restore_chunks(
+    save: &SaveBackupConfiguration,
+    snapshot: &SnapshotFile,
+    dimension: Dimension,
+    chunks: Vec<ChunkPos>,
+) {
+    tasks::spawn(
+        format!(
+            "{}: {}/{}",
+            t!("pages.saves.restore-chunks"),
+            save.name,
+            snapshot.label
+        ),
+        TaskInfo::RestoreChunks {
+            id: save.id().to_string(),
+            snapshot: std::boxed::Box::new(snapshot.clone()),
+            dimension,
+            chunks,
+        },
+    );
+}
+
+/// A timeline of the snapshots changing an area, newest first, each of which can be restored
+/// over the area.
+fn history_dialog(save: SaveBackupConfiguration, dimension: Dimension, chunks: Vec<ChunkPos>) {
+    let cancel_btn = Button::with_label(&t!("messages.cancel"));
+    cancel_btn.connect_clicked(|btn| {
+        let window: Window = btn.root().unwrap().dynamic_cast().unwrap();
+        window.close();
+    });
+    let inner = dialog_wrapper();
+    let spinner = Spinner::new();
+    inner.append(&spinner);
+    let dialog = Window::builder()
+        .title(t!("pages.saves.chunk-history"))
+        .child(&ScrolledWindow::builder().child(&inner).build())
+        .default_width(560)
+        .default_height(480)
+        .modal(true)
+        .build();
+    dialog.present();
+    spinner.start();
+
+    let data = Arc::new(Mutex::new(None));
+    let data_ref = data.clone();
+    let save0 = save.clone();
+    let chunks0 = chunks.clone();
+
+    thread::spawn(move || {
+        let mut data = data_ref.lock().unwrap();
+        let res = save0.list_backups().and_then(|mut snapshots| {
+            snapshots.sort_by_key(|x| x.time);
+            let history = save0.area_history(&snapshots, dimension, &chunks0)?;
+            Ok(snapshots.into_iter().zip(history).collect::<Vec<_>>())
+        });
+        *data = Some(res);
+        drop(data);
+    });
+
+    gtk4::glib::source::idle_add_local(move || {
+        let Ok(mut data) = data.try_lock() else {
+            return gtk4::glib::ControlFlow::Continue;
+        };
+        let Some(res) = data.take() else {
+            return gtk4::glib::ControlFlow::Continue;
+        };
+        spinner.stop();
+        inner.remove(&spinner);
+        match res {
+            Ok(history) => {
+                for (snapshot, entry) in history.into_iter().rev() {
+                    let changed = !entry.changed.is_empty() || !entry.removed.is_empty();
+                    let row = Box::builder()
+                        .orientation(gtk4::Orientation::Horizontal)
+                        .spacing(8)
+                        .build();
+                    let mut text = format!(
+                        "{}  {}\n{}",
+                        snapshot.time.format("%Y/%m/%d %H:%M"),
+                        snapshot.label,
+                        if changed {
+                            t!(
+                                "pages.saves.area-changed",
+                                changed = entry.changed.len(),
+                                removed = entry.removed.len()
+                            )
+                        } else {
+                            t!("pages.saves.area-unchanged")
+                        }
+                    );
+                    if let Some(saved) = entry.last_saved {
+                        text += &format!(
+                            "\n{}: {}",
+                            t!("pages.saves.last-saved"),
+                            saved.format("%Y/%m/%d %H:%M")
+                        );
+                    }
+                    let label = Label::builder()
+                        .label(text)
+                        .hexpand(true)
+                        .xalign(0.0)
+                        .sensitive(changed)
+                        .build();
+                    row.append(&label);
+                    let restore_btn = Button::with_label(&t!("pages.saves.restore-area"));
+                    let save = save.clone();
+                    let chunks = chunks.clone();
+                    restore_btn.connect_clicked(move |_| {
+                        restore_chunks(&save, &snapshot, dimension, chunks.clone())
+                    });
+                    row.append(&restore_btn);
+                    inner.append(&row);
+                }
+            }
+            Err(err) => {
+                inner.append(
+                    &Label::builder()
+                        .label(format!("{:#}", err))
+                        .wrap(true)
+                        .build(),
+                );
+            }
+        }
+        inner.append(&cancel_btn);
+        gtk4::glib::ControlFlow::Break
+    });
+}
+
+/// What the world is, and who played in it, now or in a snapshot.
+fn details_dialog(save: SaveBackupConfiguration) {
+    let cancel_btn = Button::with_label(&t!("messages.cancel"));
+    cancel_btn.connect_clicked(|btn| {
+        let window: Window = btn.root().unwrap().dynamic_cast().unwrap();
+        window.close();
+    });
+    let label = |text: String| {
+        Label::builder()
+            .label(text)
+            .wrap(true)
+            .hexpand(true)
+            .xalign(0.0)
+            .build()
+    };
+    let inner = dialog_wrapper();
+    inner.set_valign(gtk4::Align::Fill);
+    inner.append(&title(gtk4::glib::markup_escape_text(save.display_name())));
+    inner.append(&label(save.source().display().to_string()));
+    if let Some(info) = &save.info {
+        inner.append(&label(world_summary(info)));
+        let details = world_details(info);
+        if !details.is_empty() {
+            inner.append(&label(details));
+        }
+    }
+    inner.append(&title(t!("pages.saves.players")));
+    let as_of = Box::builder()
+        .orientation(gtk4::Orientation::Horizontal)
+        .spacing(8)
+        .build();
+    let list = Box::builder()
+        .orientation(gtk4::Orientation::Vertical)
+        .spacing(8)
+        .build();
+    inner.append(&as_of);
+    inner.append(&ScrolledWindow::builder().child(&list).vexpand(true).build());
+    inner.append(&cancel_btn);
+    let dialog = Window::builder()
+        .title(t!("pages.saves.details"))
+        .child(&inner)
+        .default_width(560)
+        .default_height(560)
+        .modal(true)
+        .build();
+    dialog.present();
+
+    // Results of a roster picked before the last one are dropped
+    let generation = Rc::new(RefCell::new(0));
+    let load = {
+        let save = save.clone();
+        let list = list.clone();
+        move |snapshot: Option<SnapshotFile>| {
+            *generation.borrow_mut() += 1;
+            let current = *generation.borrow();
+            while let Some(child) = list.first_child() {
+                list.remove(&child);
+            }
             let spinner = Spinner::new();
-            inner.append(&spinner);
-            let dialog = Window::builder()
-                .title(t!("pages.saves.backup"))
-                .child(&inner)
-                .modal(true)
-                .build();
-            dialog.present();
+            list.append(&spinner);
             spinner.start();
 
             let data = Arc::new(Mutex::new(None));
             let data_ref = data.clone();
-            let id = id.clone();
             let save = save.clone();
-            let save_name0 = save.name.clone();
-
             thread::spawn(move || {
                 let mut data = data_ref.lock().unwrap();
-                *data = Some(save.list_backups().unwrap_or_default());
+                *data = Some(match snapshot {
+                    Some(snapshot) => players::snapshot_rosters(&save, &[snapshot])
+                        .map(|x| x.into_iter().flat_map(|x| x.players).collect()),
+                    None => players::roster(save.source()),
+                });
                 drop(data);
             });
 
+            let (list, generation) = (list.clone(), generation.clone());
             gtk4::glib::source::idle_add_local(move || {
-                while let Err(_) = data.try_lock() {
+                let Ok(mut data) = data.try_lock() else {
+                    return gtk4::glib::ControlFlow::Continue;
+                };
+                let Some(res) = data.take() else {
                     return gtk4::glib::ControlFlow::Continue;
+                };
+                if *generation.borrow() != current {
+                    return gtk4::glib::ControlFlow::Break;
                 }
                 spinner.stop();
-                if let Some(data) = data.lock().unwrap().as_ref() {
-                    for snapshot in data {
-                        let snapshot_card = Box::builder()
-                            .orientation(gtk4::Orientation::Vertical)
-                            .valign(gtk4::Align::Start)
-                            .build();
-
-                        snapshot_card.append(&title(snapshot.label.clone()));
-                        snapshot_card.append(
-                            &Label::builder()
-                                .label(snapshot.time.format("%Y/%m/%d %H:%m").to_string())
-                                .xalign(0.0)
-                                .build(),
-                        );
+                list.remove(&spinner);
+                match res {
+                    Ok(players) if players.is_empty() => {
+                        list.append(&label(t!("pages.saves.no-players").to_string()))
+                    }
+                    Ok(players) => {
+                        for player in players {
+                            list.append(&player_label(&player));
+                        }
+                    }
+                    Err(err) => list.append(&label(format!("{:#}", err))),
+                }
+                gtk4::glib::ControlFlow::Break
+            });
+        }
+    };
+    load(None);
+
+    // The snapshots to pick from are listed in the background too
+    let data = Arc::new(Mutex::new(None));
+    let data_ref = data.clone();
+    thread::spawn(move || {
+        let mut data = data_ref.lock().unwrap();
+        let mut snapshots = save.list_backups().unwrap_or_default();
+        snapshots.sort_by_key(|x| std::cmp::Reverse(x.time));
+        *data = Some(snapshots);
+        drop(data);
+    });
+    gtk4::glib::source::idle_add_local(move || {
+        let Ok(mut data) = data.try_lock() else {
+            return gtk4::glib::ControlFlow::Continue;
+        };
+        let Some(snapshots) = data.take() else {
+            return gtk4::glib::ControlFlow::Continue;
+        };
+        let options: Vec<String> = std::iter::once(t!("pages.saves.live-world").to_string())
+            .chain(
+                snapshots
+                    .iter()
+                    .map(|x| format!("{}  {}", x.time.format("%Y/%m/%d %H:%M"), x.label)),
+            )
+            .collect();
+        let options: Vec<&str> = options.iter().map(|x| x.as_str()).collect();
+        let (b, input) = with_label::drop_down(t!("pages.saves.as-of"), &options, 0);
+        let load = load.clone();
+        input.connect_selected_notify(move |input| {
+            let index = input.selected() as usize;
+            load(index.checked_sub(1).map(|x| snapshots[x].clone()));
+        });
+        as_of.append(&b);
+        gtk4::glib::ControlFlow::Break
+    });
+}
+
+/// Name, last seen, play time and position of a player, with the UUID as tooltip.
+fn player_label(player: &PlayerInfo) -> Label {
+    let mut parts = vec![];
+    if let Some(last_seen) = player.last_seen {
+        parts.push(format!(
+            "{}: {}",
+            t!("pages.saves.last-seen"),
+            last_seen.format("%Y/%m/%d %H:%M")
+        ));
+    }
+    if let Some(play_time) = player.play_time {
+        parts.push(format!(
+            "{}: {}h {:02}m",
+            t!("pages.saves.play-time"),
+            play_time / 3600,
+            play_time / 60 % 60
+        ));
+    }
+    if let Some(dimension) = &player.dimension {
+        let position = player
+            .position
+            .map(|[x, y, z]| format!(" {:.0}, {:.0}, {:.0}", x, y, z))
+            .unwrap_or_default();
+        parts.push(format!("{}{}", dimension, position));
+    }
+    Label::builder()
+        .label(format!(
+            "{}\n{}",
+            player.name.as_deref().unwrap_or(&player.uuid),
+            parts.join(" · ")
+        ))
+        .tooltip_text(&player.uuid)
+        .wrap(true)
+        .hexpand(true)
+        .xalign(0.0)
+        .build()
+}
+
+/// Look for an item in every snapshot, and list the snapshots still holding it, newest first.
+fn items_dialog(save: SaveBackupConfiguration) {
+    let cancel_btn = Button::with_label(&t!("messages.cancel"));
+    cancel_btn.connect_clicked(|btn| {
+        let window: Window = btn.root().unwrap().dynamic_cast().unwrap();
+        window.close();
+    });
+    let (b1, item_input) = with_label::text_input(t!("pages.saves.item"), String::new());
+    item_input.set_placeholder_text(Some("minecraft:elytra"));
+    let (b2, name_input) = with_label::text_input(t!("pages.saves.custom-name"), String::new());
+    let (b3, enchantment_input) =
+        with_label::text_input(t!("pages.saves.enchantment"), String::new());
+    enchantment_input.set_placeholder_text(Some("minecraft:mending"));
+    let search_btn = Button::with_label(&t!("pages.saves.search"));
+    let results = Box::builder()
+        .orientation(gtk4::Orientation::Vertical)
+        .spacing(8)
+        .build();
+    let inner = dialog_wrapper();
+    inner.set_valign(gtk4::Align::Fill);
+    inner.append(&b1);
+    inner.append(&b2);
+    inner.append(&b3);
+    inner.append(&search_btn);
+    inner.append(
+        &ScrolledWindow::builder()
+            .child(&results)
+            .vexpand(true)
+            .build(),
+    );
+    inner.append(&cancel_btn);
+    let dialog = Window::builder()
+        .title(t!("pages.saves.find-items"))
+        .child(&inner)
+        .default_width(560)
+        .default_height(480)
+        .modal(true)
+        .build();
+    dialog.present();
+
+    search_btn.connect_clicked(move |btn| {
+        let item = item_input.text().trim().to_string();
+        if item.is_empty() {
+            return;
+        }
+        let optional =
+            |text: gtk4::glib::GString| Some(text.trim().to_string()).filter(|x| !x.is_empty());
+        let query = ItemQuery::new(
+            &item,
+            optional(name_input.text()).as_deref(),
+            optional(enchantment_input.text()).as_deref(),
+        );
+        while let Some(child) = results.first_child() {
+            results.remove(&child);
+        }
+        btn.set_sensitive(false);
+        let spinner = Spinner::new();
+        results.append(&spinner);
+        spinner.start();
+
+        let data = Arc::new(Mutex::new(None));
+        let data_ref = data.clone();
+        let save0 = save.clone();
+        thread::spawn(move || {
+            let mut data = data_ref.lock().unwrap();
+            let res = save0.list_backups().and_then(|mut snapshots| {
+                snapshots.sort_by_key(|x| x.time);
+                let search = items::search_items(&save0, &snapshots, &query)?;
+                Ok((snapshots, search))
+            });
+            *data = Some(res);
+            drop(data);
+        });
+
+        let (btn, results, save) = (btn.clone(), results.clone(), save.clone());
+        gtk4::glib::source::idle_add_local(move || {
+            let Ok(mut data) = data.try_lock() else {
+                return gtk4::glib::ControlFlow::Continue;
+            };
+            let Some(res) = data.take() else {
+                return gtk4::glib::ControlFlow::Continue;
+            };
+            spinner.stop();
+            results.remove(&spinner);
+            btn.set_sensitive(true);
+            let label = |text: String| {
+                Label::builder()
+                    .label(text)
+                    .wrap(true)
+                    .hexpand(true)
+                    .xalign(0.0)
+                    .build()
+            };
+            let (snapshots, search) = match res {
+                Ok(res) => res,
+                Err(err) => {
+                    results.append(&label(format!("{:#}", err)));
+                    return gtk4::glib::ControlFlow::Break;
+                }
+            };
+            let mut found_any = false;
+            for (snapshot, items) in snapshots.into_iter().zip(search.snapshots).rev() {
+                if items.found.is_empty() {
+                    continue;
+                }
+                found_any = true;
+                let row = Box::builder()
+                    .orientation(gtk4::Orientation::Horizontal)
+                    .spacing(8)
+                    .build();
+                let mut text = format!(
+                    "{}  {}\n{}",
+                    snapshot.time.format("%Y/%m/%d %H:%M"),
+                    snapshot.label,
+                    t!("pages.saves.items-found", count = items.total())
+                );
+                for found in &items.found {
+                    text += &format!("\n{}× {}", found.count, found.location);
+                }
+                row.append(&label(text));
+                let restore_btn = Button::with_label(&t!("pages.saves.restore-paths"));
+                let save = save.clone();
+                restore_btn.connect_clicked(move |_| tree_dialog(save.clone(), snapshot.clone()));
+                row.append(&restore_btn);
+                results.append(&row);
+            }
+            if !found_any {
+                results.append(&label(t!("pages.saves.items-not-found").to_string()));
+            }
+            if !search.skipped.is_empty() {
+                results.append(&label(
+                    t!("pages.saves.skipped-chunks", count = search.skipped.len()).to_string(),
+                ));
+            }
+            gtk4::glib::ControlFlow::Break
+        });
+    });
+}
+
+/// A summary of the blocks, block entities and entities that changed between two snapshots.
+fn diff_dialog(save: SaveBackupConfiguration, from: SnapshotFile, to: SnapshotFile) {
+    let cancel_btn = Button::with_label(&t!("messages.cancel"));
+    cancel_btn.connect_clicked(|btn| {
+        let window: Window = btn.root().unwrap().dynamic_cast().unwrap();
+        window.close();
+    });
+    let inner = dialog_wrapper();
+    let spinner = Spinner::new();
+    inner.append(&spinner);
+    let dialog = Window::builder()
+        .title(t!("pages.saves.compare"))
+        .child(&ScrolledWindow::builder().child(&inner).build())
+        .default_width(560)
+        .default_height(480)
+        .modal(true)
+        .build();
+    dialog.present();
+    spinner.start();
+
+    let data = Arc::new(Mutex::new(None));
+    let data_ref = data.clone();
+    let (from0, to0) = (from.clone(), to.clone());
+
+    thread::spawn(move || {
+        let mut data = data_ref.lock().unwrap();
+        *data = Some(diff::diff_blocks(
+            &save,
+            &from0,
+            &to0,
+            &Dimension::ALL,
+            None,
+        ));
+        drop(data);
+    });
+
+    gtk4::glib::source::idle_add_local(move || {
+        let Ok(mut data) = data.try_lock() else {
+            return gtk4::glib::ControlFlow::Continue;
+        };
+        let Some(res) = data.take() else {
+            return gtk4::glib::ControlFlow::Continue;
+        };
+        spinner.stop();
+        inner.remove(&spinner);
+        let label = |text: String| Label::builder().label(text).wrap(true).xalign(0.0).build();
+        match res {
+            Ok(diff) => {
+                inner.append(&label(
+                    t!(
+                        "pages.saves.changed-chunks",
+                        count = diff.chunks.len(),
+                        from = from.time.format("%Y/%m/%d %H:%M"),
+                        to = to.time.format("%Y/%m/%d %H:%M")
+                    )
+                    .to_string(),
+                ));
+                let mut totals = diff.totals();
+                totals.sort_by_key(|x| std::cmp::Reverse(x.count));
+                for kind in [Kind::Block, Kind::BlockEntity, Kind::Entity] {
+                    let lines: Vec<String> = totals
+                        .iter()
+                        .filter(|x| x.kind == kind)
+                        .map(|x| {
+                            let sign = match x.change {
+                                Change::Added => "+",
+                                Change::Removed => "−",
+                                Change::Changed => "~",
+                            };
+                            format!("{}{}  {}", sign, x.count, x.id)
+                        })
+                        .collect();
+                    if lines.is_empty() {
+                        continue;
+                    }
+                    inner.append(&title(match kind {
+                        Kind::Block => t!("pages.saves.blocks"),
+                        Kind::BlockEntity => t!("pages.saves.block-entities"),
+                        Kind::Entity => t!("pages.saves.entities"),
+                    }));
+                    inner.append(&label(lines.join("\n")));
+                }
+                if !diff.skipped.is_empty() {
+                    inner.append(&label(
+                        t!("pages.saves.skipped-chunks", count = diff.skipped.len()).to_string(),
+                    ));
+                }
+            }
+            Err(err) => inner.append(&label(format!("{:#}", err))),
+        }
+        inner.append(&cancel_btn);
+        gtk4::glib::ControlFlow::Break
+    });
+}
+
+/// The files a snapshot changed since the previous one, or that changed in the world since it
+/// was taken.
+fn files_dialog(
+    save: SaveBackupConfiguration,
+    snapshot: SnapshotFile,
+    previous: Option<SnapshotFile>,
+    present: bool,
+) {
+    let cancel_btn = Button::with_label(&t!("messages.cancel"));
+    cancel_btn.connect_clicked(|btn| {
+        let window: Window = btn.root().unwrap().dynamic_cast().unwrap();
+        window.close();
+    });
+    // `None` is the world as it is now
+    let against: Vec<Option<SnapshotFile>> = previous
+        .into_iter()
+        .map(Some)
+        .chain(present.then_some(None))
+        .collect();
+    let options: Vec<_> = against
+        .iter()
+        .map(|x| match x {
+            Some(_) => t!("pages.saves.previous-snapshot"),
+            None => t!("pages.saves.live-world"),
+        })
+        .collect();
+    let options: Vec<&str> = options.iter().map(|x| x.as_ref()).collect();
+    let (b1, against_input) = with_label::drop_down(t!("pages.saves.compare-to"), &options, 0);
+    let summary = Label::builder().wrap(true).xalign(0.0).build();
+    let list = Box::builder()
+        .orientation(gtk4::Orientation::Vertical)
+        .spacing(4)
+        .build();
+    let inner = dialog_wrapper();
+    inner.set_valign(gtk4::Align::Fill);
+    inner.append(&b1);
+    inner.append(&summary);
+    inner.append(&ScrolledWindow::builder().child(&list).vexpand(true).build());
+    inner.append(&cancel_btn);
+    let dialog = Window::builder()
+        .title(t!("pages.saves.changed-files"))
+        .child(&inner)
+        .default_width(560)
+        .default_height(480)
+        .modal(true)
+        .build();
+    dialog.present();
+
+    // Results of a comparison picked before the last one are dropped
+    let generation = Rc::new(RefCell::new(0));
+    let load = move |index: u32| {
+        *generation.borrow_mut() += 1;
+        let current = *generation.borrow();
+        while let Some(child) = list.first_child() {
+            list.remove(&child);
+        }
+        summary.set_label("");
+        let spinner = Spinner::new();
+        list.append(&spinner);
+        spinner.start();
+
+        let data = Arc::new(Mutex::new(None));
+        let data_ref = data.clone();
+        let (save, snapshot) = (save.clone(), snapshot.clone());
+        let against = against[index as usize].clone();
+        let (from, to) = match &against {
+            Some(previous) => (previous.time, Some(snapshot.time)),
+            None => (snapshot.time, None),
+        };
+        thread::spawn(move || {
+            let mut data = data_ref.lock().unwrap();
+            *data = Some(match against {
+                Some(previous) => save.changed_files(&previous, &snapshot),
+                None => save.changed_files_live(&snapshot),
+            });
+            drop(data);
+        });
 
-                        let btn = Button::builder().child(&snapshot_card).build();
-
-                        let id = id.clone();
-                        let save_name = save_name0.clone();
-                        let snapshot = snapshot.clone();
-                        btn.connect_clicked(move |_| {
-                            tasks::spawn(
-                                format!(
-                                    "{}: {}/{}",
-                                    t!("pages.saves.recover"),
-                                    save_name,
-                                    snapshot.label
-                                ),
-                                TaskInfo::Recover {
-                                    id: id.clone(),
-                                    snapshot: snapshot.clone(),
-                                },
-                            );
-                        });
-                        inner.append(&btn);
+        let (list, summary, generation) = (list.clone(), summary.clone(), generation.clone());
+        gtk4::glib::source::idle_add_local(move || {
+            let Ok(mut data) = data.try_lock() else {
+                return gtk4::glib::ControlFlow::Continue;
+            };
+            let Some(res) = data.take() else {
+                return gtk4::glib::ControlFlow::Continue;
+            };
+            if *generation.borrow() != current {
+                return gtk4::glib::ControlFlow::Break;
+            }
+            spinner.stop();
+            list.remove(&spinner);
+            let changes = match res {
+                Ok(changes) => changes,
+                Err(err) => {
+                    summary.set_label(&format!("{:#}", err));
+                    return gtk4::glib::ControlFlow::Break;
+                }
+            };
+            summary.set_label(&t!(
+                "pages.saves.changed-files-between",
+                count = changes.len(),
+                from = from.format("%Y/%m/%d %H:%M"),
+                to = to.map_or(t!("pages.saves.live-world").to_string(), |x| x
+                    .format("%Y/%m/%d %H:%M")
+                    .to_string())
+            ));
+            for change in changes {
+                let row = Box::builder()
+                    .orientation(gtk4::Orientation::Horizontal)
+                    .spacing(8)
+                    .build();
+                let (sign, size) = match (change.before, change.after) {
+                    (None, Some(after)) => ("+", gtk4::glib::format_size(after).to_string()),
+                    (Some(before), None) => ("−", gtk4::glib::format_size(before).to_string()),
+                    (before, after) => {
+                        let (before, after) =
+                            (before.unwrap_or_default(), after.unwrap_or_default());
+                        let delta = gtk4::glib::format_size(before.abs_diff(after));
+                        let sign = if after < before { "−" } else { "+" };
+                        ("~", format!("{}{}", sign, delta))
                     }
+                };
+                row.append(&Label::new(Some(sign)));
+                row.append(
+                    &Label::builder()
+                        .label(change.path.to_string_lossy())
+                        .hexpand(true)
+                        .xalign(0.0)
+                        .build(),
+                );
+                row.append(&Label::new(Some(&size)));
+                list.append(&row);
+            }
+            gtk4::glib::ControlFlow::Break
+        });
+    };
+    load(0);
+    against_input.connect_selected_notify(move |input| load(input.selected()));
+}
+
+/// The state of `tree_dialog`: the whole snapshot, the directory shown and the paths picked.
+#[derive(Clone)]
+struct TreeBrowser {
+    entries: Rc<Vec<TreeEntry>>,
+    selected: Rc<RefCell<BTreeSet<PathBuf>>>,
+    list: Box,
+    path_label: Label,
+    selected_label: Label,
+    restore_btn: Button,
+}
 
-                    inner.remove(&spinner);
-                    inner.append(&cancel_btn);
+impl TreeBrowser {
+    fn show(&self, dir: &Path) {
+        while let Some(child) = self.list.first_child() {
+            self.list.remove(&child);
+        }
+        self.path_label
+            .set_label(&format!("/{}", dir.to_string_lossy()));
+        if let Some(parent) = dir.parent() {
+            let up = Button::builder()
+                .label("..")
+                .halign(gtk4::Align::Start)
+                .build();
+            let this = self.clone();
+            let parent = parent.to_path_buf();
+            up.connect_clicked(move |_| this.show(&parent));
+            self.list.append(&up);
+        }
+        let mut children: Vec<_> = self
+            .entries
+            .iter()
+            .filter(|x| x.path.parent() == Some(dir))
+            .collect();
+        children.sort_by_key(|x| (!x.dir, x.path.clone()));
+        for entry in children {
+            let row = Box::builder()
+                .orientation(gtk4::Orientation::Horizontal)
+                .spacing(8)
+                .build();
+            let check = CheckButton::builder()
+                .active(self.selected.borrow().contains(&entry.path))
+                .build();
+            let this = self.clone();
+            let path = entry.path.clone();
+            check.connect_toggled(move |check| {
+                if check.is_active() {
+                    this.selected.borrow_mut().insert(path.clone());
+                } else {
+                    this.selected.borrow_mut().remove(&path);
                 }
-                gtk4::glib::ControlFlow::Break
+                this.update_selection();
             });
+            row.append(&check);
+            let name = entry
+                .path
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_string();
+            if entry.dir {
+                let open = Button::builder()
+                    .label(format!("{}/", name))
+                    .has_frame(false)
+                    .build();
+                let this = self.clone();
+                let path = entry.path.clone();
+                open.connect_clicked(move |_| this.show(&path));
+                row.append(&open);
+            } else {
+                row.append(
+                    &Label::builder()
+                        .label(name)
+                        .hexpand(true)
+                        .xalign(0.0)
+                        .build(),
+                );
+                row.append(&Label::new(Some(&gtk4::glib::format_size(entry.size))));
+            }
+            self.list.append(&row);
+        }
+    }
+
+    fn update_selection(&self) {
+        let count = self.selected.borrow().len();
+        self.selected_label
+            .set_label(&t!("pages.saves.selected-paths", count = count));
+        self.restore_btn.set_sensitive(count > 0);
+    }
+}
+
+/// Saves kept after their world vanished, whose snapshots can be restored anywhere. `None`
+/// when there is none.
+fn archived_saves() -> Option<Box> {
+    let mut archived: Vec<_> = AppState::instance()
+        .saves
+        .values()
+        .filter(|x| x.archived)
+        .cloned()
+        .collect();
+    if archived.is_empty() {
+        return None;
+    }
+    archived.sort_by(|a, b| a.name.cmp(&b.name));
+    let b = Box::builder()
+        .orientation(gtk4::Orientation::Vertical)
+        .spacing(8)
+        .build();
+    b.append(&title(t!("pages.saves.archived")));
+    for save in archived {
+        let card = Box::builder()
+            .orientation(gtk4::Orientation::Vertical)
+            .spacing(4)
+            .build();
+        card.append(&title(gtk4::glib::markup_escape_text(save.display_name())));
+        card.append(
+            &Label::builder()
+                .label(format!(
+                    "{}: {}",
+                    t!("pages.saves.last-location"),
+                    save.source().display()
+                ))
+                .wrap(true)
+                .xalign(0.0)
+                .build(),
+        );
+        let recover_button = Button::builder()
+            .label(t!("pages.saves.recover").to_string())
+            .halign(gtk4::Align::End)
+            .build();
+        recover_button.connect_clicked(move |_| snapshot_dialog(save.clone(), false));
+        card.append(&recover_button);
+        b.append(&cardify(card));
+    }
+    Some(b)
+}
+
+/// Saves whose world vanished, to archive, export or delete. `None` when there is none.
+fn orphaned_saves() -> Option<Box> {
+    let orphaned: Vec<_> = AppState::instance()
+        .orphaned()
+        .into_iter()
+        .cloned()
+        .collect();
+    if orphaned.is_empty() {
+        return None;
+    }
+    let b = Box::builder()
+        .orientation(gtk4::Orientation::Vertical)
+        .spacing(8)
+        .build();
+    b.append(&title(t!("pages.saves.orphaned")));
+    for save in orphaned {
+        let card = Box::builder()
+            .orientation(gtk4::Orientation::Vertical)
+            .spacing(4)
+            .build();
+        card.append(&title(gtk4::glib::markup_escape_text(save.display_name())));
+        card.append(
+            &Label::builder()
+                .label(format!(
+                    "{}: {}",
+                    t!("pages.saves.last-location"),
+                    save.source().display()
+                ))
+                .wrap(true)
+                .xalign(0.0)
+                .build(),
+        );
+        let button_box = Box::builder()
+            .orientation(gtk4::Orientation::Horizontal)
+            .halign(gtk4::Align::End)
+            .spacing(8)
+            .build();
+        let archive_button = Button::with_label(&t!("pages.saves.archive"));
+        let export_button = Button::with_label(&t!("pages.saves.export"));
+        let delete_button = Button::with_label(&t!("pages.saves.delete"));
+        let card = cardify(card);
+
+        let id = save.id().to_string();
+        let card0 = card.clone();
+        archive_button.connect_clicked(move |_| match AppState::instance().archive(&id) {
+            Ok(()) => card0.set_visible(false),
+            Err(err) => alert_failure(&err),
         });
+        let id = save.id().to_string();
+        let name = save.name.clone();
+        export_button.connect_clicked(move |_| {
+            if let Ok(Some(destination)) = native_dialog::FileDialogBuilder::default()
+                .open_single_dir()
+                .show()
+                .inspect_err(report_err("Failed to open dialog"))
+            {
+                tasks::spawn(
+                    format!("{}: {}", t!("pages.saves.export"), name),
+                    TaskInfo::Export {
+                        id: id.clone(),
+                        destination,
+                    },
+                );
+            }
+        });
+        let id = save.id().to_string();
+        let name = save.name.clone();
+        let card0 = card.clone();
+        delete_button.connect_clicked(move |_| {
+            // Repositories are never deleted without asking
+            let confirmed = native_dialog::MessageDialogBuilder::default()
+                .set_title(t!("pages.saves.delete"))
+                .set_text(t!("messages.confirm-delete-save", name = name))
+                .confirm()
+                .show()
+                .inspect_err(report_err("Failed to open dialog"))
+                .unwrap_or_default();
+            if !confirmed {
+                return;
+            }
+            match AppState::instance().delete_save(&id) {
+                Ok(()) => card0.set_visible(false),
+                Err(err) => alert_failure(&err),
+            }
+        });
+        button_box.append(&archive_button);
+        button_box.append(&export_button);
+        button_box.append(&delete_button);
+        card.append(&button_box);
+        b.append(&card);
+    }
+    Some(b)
+}
 
-        button_box.append(&Label::builder().hexpand(true).build());
-        button_box.append(&backup_button);
-        button_box.append(&recover_button);
-        save_card_right.append(&title(&save.name));
-        save_card_right.append(&button_box);
+/// Game mode, difficulty, version and when the world was last played, as known.
+fn world_summary(info: &WorldInfo) -> String {
+    let mut parts = vec![];
+    if let Some(game_mode) = info.game_mode {
+        parts.push(match game_mode {
+            GameMode::Survival => t!("pages.saves.survival"),
+            GameMode::Creative => t!("pages.saves.creative"),
+            GameMode::Adventure => t!("pages.saves.adventure"),
+            GameMode::Spectator => t!("pages.saves.spectator"),
+        });
+    }
+    if info.hardcore {
+        parts.push(t!("pages.saves.hardcore"));
+    } else if let Some(difficulty) = info.difficulty {
+        parts.push(match difficulty {
+            Difficulty::Peaceful => t!("pages.saves.peaceful"),
+            Difficulty::Easy => t!("pages.saves.easy"),
+            Difficulty::Normal => t!("pages.saves.normal"),
+            Difficulty::Hard => t!("pages.saves.hard"),
+        });
+    }
+    if let Some(version) = &info.version_name {
+        parts.push(version.clone().into());
+    }
+    if let Some(last_played) = info.last_played {
+        parts.push(
+            format!(
+                "{}: {}",
+                t!("pages.saves.last-played"),
+                last_played.format("%Y/%m/%d %H:%M")
+            )
+            .into(),
+        );
+    }
+    parts.join(" · ")
+}
 
-        save_card.append(&save_card_left);
-        save_card.append(&save_card_right);
-        wrapper.append(&cardify(save_card));
+/// Seed, day and datapacks, for the tooltip of the summary.
+fn world_details(info: &WorldInfo) -> String {
+    let mut lines = vec![];
+    if let Some(seed) = info.seed {
+        lines.push(format!("{}: {}", t!("pages.saves.seed"), seed));
+    }
+    if let Some(day_time) = info.day_time {
+        lines.push(format!("{}: {}", t!("pages.saves.day"), day_time / 24000));
     }
+    if !info.datapacks.is_empty() {
+        lines.push(format!(
+            "{}: {}",
+            t!("pages.saves.datapacks"),
+            info.datapacks.join(", ")
+        ));
+    }
+    lines.join("\n")
+}
 
-    wrapper
+/// Lets the user confirm or undo the in-place restores of a save, while the replaced worlds
+/// are still in the trash. Refreshed every two seconds.
+fn trash_box(id: String) -> Box {
+    let b = Box::builder()
+        .orientation(gtk4::Orientation::Horizontal)
+        .spacing(8)
+        .build();
+    let refresh = {
+        let b = b.clone();
+        move || {
+            while let Some(child) = b.first_child() {
+                b.remove(&child);
+            }
+            let count = AppState::instance()
+                .saves
+                .get(&id)
+                .map_or(0, |x| x.trash.len());
+            if count == 0 {
+                return gtk4::glib::ControlFlow::Continue;
+            }
+            b.append(
+                &Label::builder()
+                    .label(t!("pages.saves.replaced-worlds", count = count))
+                    .hexpand(true)
+                    .xalign(0.0)
+                    .build(),
+            );
+            let confirm_button = Button::with_label(&t!("pages.saves.confirm-restore"));
+            let undo_button = Button::with_label(&t!("pages.saves.undo-restore"));
+            let id0 = id.clone();
+            confirm_button.connect_clicked(move |btn| {
+                btn.set_sensitive(false);
+                if let Err(err) = AppState::empty_trash(&id0) {
+                    alert_failure(&err);
+                }
+            });
+            let id0 = id.clone();
+            undo_button.connect_clicked(move |btn| {
+                btn.set_sensitive(false);
+                if let Err(err) = AppState::undo_restore(&id0) {
+                    alert_failure(&err);
+                }
+            });
+            b.append(&confirm_button);
+            b.append(&undo_button);
+            gtk4::glib::ControlFlow::Continue
+        }
+    };
+    refresh();
+    gtk4::glib::timeout_add_local(Duration::from_secs(2), refresh);
+    b
+}
+
+fn alert_failure(err: &anyhow::Error) {
+    native_dialog::MessageDialogBuilder::default()
+        .set_title(t!("messages.action-failed"))
+        .set_text(format!("{:#}", err))
+        .alert()
+        .show()
+        .inspect_err(report_err("Failed to open dialog"))
+        .unwrap_or_default();
 }
 
-fn build_button_from_snapshot(_snapshot: &SnapshotFile) -> Button {
-    let btn = Button::builder().build();
-    btn
+fn build_button_from_snapshot(snapshot: &SnapshotFile) -> Button {
+    let snapshot_card = Box::builder()
+        .orientation(gtk4::Orientation::Vertical)
+        .valign(gtk4::Align::Start)
+        .build();
+
+    snapshot_card.append(&title(snapshot.label.clone()));
+    snapshot_card.append(
+        &Label::builder()
+            .label(snapshot.time.format("%Y/%m/%d %H:%M").to_string())
+            .xalign(0.0)
+            .build(),
+    );
+
+    Button::builder().child(&snapshot_card).build()
 }
@@ -1,15 +1,16 @@
 use gtk4::{
-    Box, Button, Label, TextView,
+    Box, Button, Entry, Label, TextView,
     prelude::{BoxExt, ButtonExt, EditableExt, EntryExt, TextBufferExt, TextViewExt},
 };
 use native_dialog::DialogBuilder;
-use std::path::PathBuf;
+use std::{num::ParseIntError, path::PathBuf};
 
 #[cfg(target_os = "windows")]
 use std::io::Write;
 
 use crate::{
-    settings::Settings,
+    scheduler,
+    settings::{OpenWorldPolicy, RetentionPolicy, Settings},
     ui::{
         pages::build_wrapper,
         utils::{title, with_label},
@@ -21,7 +22,7 @@ pub fn settings() -> Box {
     let wrapper = build_wrapper();
     let save_button = Button::builder()
         .halign(gtk4::Align::End)
-        .label(&t!("pages.settings.save").to_string())
+        .label(t!("pages.settings.save").to_string())
         .build();
 
     let (b1, compression_level_input) = with_label::text_input(
@@ -32,6 +33,17 @@ pub fn settings() -> Box {
         t!("pages.settings.daemon-backup-duration"),
         Settings::instance().daemon_backup_duration.to_string(),
     );
+    let (b5, daemon_backup_cron_input_box) = with_label::text_input(
+        t!("pages.settings.daemon-backup-cron"),
+        Settings::instance()
+            .daemon_backup_cron
+            .clone()
+            .unwrap_or_default(),
+    );
+    let (b9, backup_concurrency_input) = with_label::text_input(
+        t!("pages.settings.backup-concurrency"),
+        Settings::instance().backup_concurrency.to_string(),
+    );
     let (b3, pass_input_box) = with_label::text_input(
         t!("pages.settings.password"),
         Settings::instance().password.clone().unwrap_or_default(),
@@ -46,6 +58,49 @@ pub fn settings() -> Box {
             .unwrap_or_default(),
     );
 
+    let retention = Settings::instance().retention.clone();
+    let retention_inputs = [
+        ("pages.settings.keep-last", retention.keep_last),
+        ("pages.settings.keep-hourly", retention.keep_hourly),
+        ("pages.settings.keep-daily", retention.keep_daily),
+        ("pages.settings.keep-weekly", retention.keep_weekly),
+        ("pages.settings.keep-monthly", retention.keep_monthly),
+    ]
+    .map(|(label, value)| {
+        let (b, input) =
+            with_label::text_input(t!(label), value.map(|x| x.to_string()).unwrap_or_default());
+        (label, b, input)
+    });
+    let (b7, keep_within_input) = with_label::text_input(
+        t!("pages.settings.keep-within"),
+        retention.keep_within.unwrap_or_default(),
+    );
+
+    let (b8, include_regenerable_switch) = with_label::switch(
+        t!("pages.settings.include-regenerable"),
+        Settings::instance().include_regenerable,
+    );
+    let open_world_policy = Settings::instance().open_world_policy;
+    let (b10, open_world_policy_input) = with_label::drop_down(
+        t!("pages.settings.open-world-policy"),
+        &OpenWorldPolicy::ALL
+            .map(|x| match x {
+                OpenWorldPolicy::Wait => t!("pages.settings.open-world-wait"),
+                OpenWorldPolicy::Warn => t!("pages.settings.open-world-warn"),
+                OpenWorldPolicy::Unsafe => t!("pages.settings.open-world-unsafe"),
+                OpenWorldPolicy::Refuse => t!("pages.settings.open-world-refuse"),
+            })
+            .each_ref()
+            .map(|x| x.as_ref()),
+        OpenWorldPolicy::ALL
+            .iter()
+            .position(|x| *x == open_world_policy)
+            .unwrap_or_default() as u32,
+    );
+    let excludes_input: TextView = TextView::builder().build();
+    let excludes_input_buffer = excludes_input.buffer();
+    excludes_input_buffer.set_text(&Settings::instance().excludes.join("\n"));
+
     let (b6, sync_switch) =
         with_label::switch(t!("pages.settings.sync"), Settings::instance().sync);
 
@@ -63,7 +118,7 @@ pub fn settings() -> Box {
 
     let add_scan_root_button = Button::builder()
         .halign(gtk4::Align::Start)
-        .label(&t!("pages.settings.add-scan-root").to_string())
+        .label(t!("pages.settings.add-scan-root").to_string())
         .build();
     add_scan_root_button.connect_clicked(move |_| {
         if let Ok(result) = native_dialog::FileDialogBuilder::default()
@@ -96,7 +151,7 @@ pub fn settings() -> Box {
 
     let enable_auto_backup_button = Button::builder()
         .halign(gtk4::Align::Start)
-        .label(&t!("pages.settings.enable-auto-backup").to_string())
+        .label(t!("pages.settings.enable-auto-backup").to_string())
         .build();
 
     #[cfg(target_os = "windows")]
@@ -142,6 +197,8 @@ pub fn settings() -> Box {
     wrapper.append(&title(t!("pages.settings.basic")));
     wrapper.append(&b1);
     wrapper.append(&b2);
+    wrapper.append(&b5);
+    wrapper.append(&b9);
     wrapper.append(&b3);
     wrapper.append(&b4);
     wrapper.append(&enable_auto_backup_button);
@@ -149,7 +206,7 @@ pub fn settings() -> Box {
         &Label::builder()
             .halign(gtk4::Align::Start)
             .justify(gtk4::Justification::Left)
-            .label(&t!("pages.settings.scan-root").to_string())
+            .label(t!("pages.settings.scan-root").to_string())
             .build(),
     );
     wrapper.append(&scan_root_input);
@@ -157,6 +214,25 @@ pub fn settings() -> Box {
     wrapper.append(&add_scan_root_button);
 
     wrapper.append(&title(t!("pages.settings.advanced")));
+    wrapper.append(
+        &Label::builder()
+            .halign(gtk4::Align::Start)
+            .label(t!("pages.settings.retention").to_string())
+            .build(),
+    );
+    for (_, b, _) in &retention_inputs {
+        wrapper.append(b);
+    }
+    wrapper.append(&b7);
+    wrapper.append(&b10);
+    wrapper.append(&b8);
+    wrapper.append(
+        &Label::builder()
+            .halign(gtk4::Align::Start)
+            .label(t!("pages.settings.excludes").to_string())
+            .build(),
+    );
+    wrapper.append(&excludes_input);
 
     wrapper.append(&title(t!("pages.settings.experimental")));
     wrapper.append(&b6);
@@ -187,6 +263,34 @@ pub fn settings() -> Box {
                 .alert();
         }
 
+        let cron = daemon_backup_cron_input_box.text();
+        if cron.trim().is_empty() {
+            instance.daemon_backup_cron = None;
+        } else if scheduler::Schedule::cron(&cron).is_ok() {
+            instance.daemon_backup_cron = Some(cron.trim().to_string());
+        } else {
+            DialogBuilder::message()
+                .set_title(t!("messages.failed-check"))
+                .set_text(t!(
+                    "messages.cron-wanted",
+                    entry = t!("pages.settings.daemon-backup-cron")
+                ))
+                .alert();
+        }
+
+        match backup_concurrency_input.text().trim().parse() {
+            Ok(concurrency) if concurrency > 0 => instance.backup_concurrency = concurrency,
+            _ => {
+                DialogBuilder::message()
+                    .set_title(t!("messages.failed-check"))
+                    .set_text(t!(
+                        "messages.int-wanted",
+                        entry = t!("pages.settings.backup-concurrency")
+                    ))
+                    .alert();
+            }
+        }
+
         let password = pass_input_box.text();
         let password_cmd = pass_cmd_input_box.text();
         if !password.is_empty() && !password_cmd.is_empty() {
@@ -224,6 +328,51 @@ pub fn settings() -> Box {
             .split("\n")
             .map(PathBuf::from)
             .collect::<Vec<PathBuf>>();
+        // Keep the whole policy as it was when any of it is wrong
+        if let [
+            Ok(keep_last),
+            Ok(keep_hourly),
+            Ok(keep_daily),
+            Ok(keep_weekly),
+            Ok(keep_monthly),
+        ] = retention_inputs
+            .each_ref()
+            .map(|(label, _, input)| optional_int(input, label))
+        {
+            let keep_within = keep_within_input.text().trim().to_string();
+            let retention = RetentionPolicy {
+                keep_last,
+                keep_hourly,
+                keep_daily,
+                keep_weekly,
+                keep_monthly,
+                keep_within: (!keep_within.is_empty()).then_some(keep_within),
+            };
+            if let Err(err) = retention.to_keep_options() {
+                DialogBuilder::message()
+                    .set_title(t!("messages.failed-check"))
+                    .set_text(format!("{}: {:#}", t!("pages.settings.keep-within"), err))
+                    .alert();
+            } else {
+                instance.retention = retention;
+            }
+        }
+
+        if let Some(policy) = OpenWorldPolicy::ALL.get(open_world_policy_input.selected() as usize)
+        {
+            instance.open_world_policy = *policy;
+        }
+        instance.include_regenerable = include_regenerable_switch.state();
+        instance.excludes = excludes_input_buffer
+            .text(
+                &excludes_input_buffer.start_iter(),
+                &excludes_input_buffer.end_iter(),
+                true,
+            )
+            .split("\n")
+            .map(|x| x.trim().to_string())
+            .filter(|x| !x.is_empty())
+            .collect();
         instance.sync = sync_switch.state();
         instance.save();
     });
@@ -231,3 +380,17 @@ pub fn settings() -> Box {
     wrapper.append(&save_button);
     wrapper
 }
+
+/// Read an optional integer from `input`, complaining about anything else.
+fn optional_int(input: &Entry, label: &str) -> Result<Option<i32>, ParseIntError> {
+    let text = input.text();
+    if text.trim().is_empty() {
+        return Ok(None);
+    }
+    text.trim().parse().map(Some).inspect_err(|_| {
+        DialogBuilder::message()
+            .set_title(t!("messages.failed-check"))
+            .set_text(t!("messages.int-wanted", entry = t!(label)))
+            .alert();
+    })
+}
@@ -0,0 +1,161 @@
+use std::time::Duration;
+
+use gtk4::{
+    Box, Button, Label, Orientation, PolicyType, ProgressBar, ScrolledWindow,
+    prelude::{BoxExt, ButtonExt, WidgetExt},
+};
+
+use crate::{
+    tasks::{self, TaskView},
+    ui::{
+        pages::build_wrapper,
+        utils::{cardify, title},
+    },
+};
+
+/// Running and finished tasks, newest first, refreshed every second.
+pub fn tasks() -> Box {
+    let wrapper: Box = build_wrapper();
+    wrapper.append(&title(t!("pages.tasks.title")));
+    let list = Box::builder()
+        .orientation(Orientation::Vertical)
+        .spacing(8)
+        .build();
+    wrapper.append(
+        &ScrolledWindow::builder()
+            .hscrollbar_policy(PolicyType::Never)
+            .vexpand(true)
+            .child(&list)
+            .build(),
+    );
+
+    let refresh = move || {
+        while let Some(child) = list.first_child() {
+            list.remove(&child);
+        }
+        let tasks = tasks::list();
+        if tasks.is_empty() {
+            list.append(
+                &Label::builder()
+                    .label(t!("pages.tasks.no-tasks"))
+                    .xalign(0.0)
+                    .build(),
+            );
+        }
+        for task in tasks.iter().rev() {
+            list.append(&cardify(task_card(task)));
+        }
+        gtk4::glib::ControlFlow::Continue
+    };
+    refresh();
+    gtk4::glib::timeout_add_local(Duration::from_secs(1), refresh);
+    wrapper
+}
+
+fn task_card(task: &TaskView) -> Box {
+    let b = Box::builder()
+        .orientation(Orientation::Vertical)
+        .spacing(4)
+        .build();
+    let header = Box::builder()
+        .orientation(Orientation::Horizontal)
+        .spacing(8)
+        .build();
+    header.append(
+        &Label::builder()
+            .use_markup(true)
+            .label(format!(
+                "<b>{}</b>",
+                gtk4::glib::markup_escape_text(&task.name)
+            ))
+            .hexpand(true)
+            .xalign(0.0)
+            .build(),
+    );
+    let status = if task.running {
+        t!("pages.tasks.running")
+    } else if task.cancelled {
+        t!("pages.tasks.cancelled")
+    } else if task.error.is_some() {
+        t!("pages.tasks.failed")
+    } else {
+        t!("pages.tasks.succeeded")
+    };
+    header.append(&Label::new(Some(&status)));
+
+    let id = task.id;
+    if task.running {
+        let cancel_button = Button::builder()
+            .label(t!("messages.cancel").to_string())
+            .sensitive(!task.cancelled)
+            .build();
+        cancel_button.connect_clicked(move |btn| {
+            tasks::cancel(id);
+            btn.set_sensitive(false);
+        });
+        header.append(&cancel_button);
+    } else {
+        let retry_button = Button::with_label(&t!("pages.tasks.retry"));
+        retry_button.connect_clicked(move |btn| {
+            tasks::retry(id);
+            btn.set_sensitive(false);
+        });
+        header.append(&retry_button);
+    }
+    b.append(&header);
+
+    b.append(
+        &Label::builder()
+            .label(format!(
+                "{}\t{}\t{}: {}s",
+                task.kind,
+                task.started.format("%Y/%m/%d %H:%M:%S"),
+                t!("pages.tasks.duration"),
+                task.duration().num_seconds()
+            ))
+            .xalign(0.0)
+            .build(),
+    );
+    if task.running {
+        let bar = ProgressBar::builder()
+            .show_text(true)
+            .text(task.progress.to_string())
+            .build();
+        match task.progress.fraction() {
+            Some(fraction) => bar.set_fraction(fraction),
+            None => bar.pulse(),
+        }
+        b.append(&bar);
+    }
+    if let Some(summary) = &task.summary {
+        b.append(
+            &Label::builder()
+                .label(summary)
+                .wrap(true)
+                .selectable(true)
+                .xalign(0.0)
+                .build(),
+        );
+    }
+    for warning in &task.warnings {
+        b.append(
+            &Label::builder()
+                .label(format!("⚠ {}", warning))
+                .wrap(true)
+                .selectable(true)
+                .xalign(0.0)
+                .build(),
+        );
+    }
+    if let Some(error) = &task.error {
+        b.append(
+            &Label::builder()
+                .label(error)
+                .wrap(true)
+                .selectable(true)
+                .xalign(0.0)
+                .build(),
+        );
+    }
+    b
+}
@@ -1,4 +1,4 @@
-use std::{cell::RefCell, sync::Arc};
+use std::{cell::RefCell, rc::Rc};
 
 use gtk4::{
     Box, Button, CssProvider, Label, Window,
@@ -40,14 +40,14 @@ pub fn dialog_wrapper() -> Box {
     b
 }
 
-pub fn dialog_button_box(cancelled: Arc<RefCell<bool>>) -> Box {
+pub fn dialog_button_box(cancelled: Rc<RefCell<bool>>) -> Box {
     let wrapper = Box::builder()
         .orientation(gtk4::Orientation::Horizontal)
         .spacing(8)
         .halign(gtk4::Align::Center)
         .build();
-    let ok = Button::with_label(&t!("messages.ok").to_string());
-    let cancel = Button::with_label(&t!("messages.cancel").to_string());
+    let ok = Button::with_label(&t!("messages.ok"));
+    let cancel = Button::with_label(&t!("messages.cancel"));
     let cancelled1 = cancelled.clone();
     ok.connect_clicked(move |btn| {
         *cancelled1.borrow_mut() = false;
@@ -56,7 +56,7 @@ pub fn dialog_button_box(cancelled: Arc<RefCell<bool>>) -> Box {
     });
     let cancelled2 = cancelled.clone();
     cancel.connect_clicked(move |btn| {
-        *cancelled2.borrow_mut() = false;
+        *cancelled2.borrow_mut() = true;
         let w: Window = btn.root().unwrap().dynamic_cast().unwrap();
         w.close();
     });
@@ -68,7 +68,7 @@ pub fn dialog_button_box(cancelled: Arc<RefCell<bool>>) -> Box {
 pub mod with_label {
     use std::borrow::Cow;
 
-    use gtk4::{Box, Entry, Label, Switch, prelude::BoxExt};
+    use gtk4::{Box, DropDown, Entry, Label, Switch, prelude::BoxExt};
 
     pub fn text_input(label: Cow<str>, init_state: String) -> (Box, Entry) {
         let b: Box = Box::builder()
@@ -92,9 +92,21 @@ pub mod with_label {
         (b, switch)
     }
 
+    pub fn drop_down(label: Cow<str>, options: &[&str], selected: u32) -> (Box, DropDown) {
+        let b: Box = Box::builder()
+            .orientation(gtk4::Orientation::Horizontal)
+            .spacing(8)
+            .build();
+        b.append(&build_label(label));
+        let drop_down = DropDown::from_strings(options);
+        drop_down.set_selected(selected);
+        b.append(&drop_down);
+        (b, drop_down)
+    }
+
     fn build_label(label: Cow<str>) -> Label {
         Label::builder()
-            .label(&label.to_string())
+            .label(label.to_string())
             .width_chars(16)
             .xalign(0.0)
             .build()
@@ -1,3 +1,8 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
 pub fn report_err<E>(msg: &'static str) -> Box<dyn Fn(&E)>
 where
     E: std::error::Error,
@@ -8,3 +13,47 @@ where
 fn fun<T, F: Fn(&T)>(f: F) -> F {
     f
 }
+
+/// Copy a directory tree. `to` must not exist yet.
+pub fn copy_dir(from: &Path, to: &Path) -> io::Result<()> {
+    for entry in walkdir::WalkDir::new(from) {
+        let entry = entry?;
+        let target = to.join(
+            entry
+                .path()
+                .strip_prefix(from)
+                .expect("Walked paths are under the root"),
+        );
+        if entry.file_type().is_dir() {
+            fs::create_dir_all(target)?;
+        } else {
+            fs::copy(entry.path(), target)?;
+        }
+    }
+    Ok(())
+}
+
+/// Removes a directory when dropped, including while unwinding, unless it is kept.
+pub struct DirGuard(Option<PathBuf>);
+
+impl DirGuard {
+    pub fn new(path: PathBuf) -> Self {
+        Self(Some(path))
+    }
+    pub fn keep(mut self) -> PathBuf {
+        self.0.take().unwrap()
+    }
+}
+
+impl Drop for DirGuard {
+    fn drop(&mut self) {
+        if let Some(path) = self.0.take()
+            && path.exists()
+        {
+            debug!("remove_dir(path={:?})", path);
+            fs::remove_dir_all(path)
+                .inspect_err(report_err("Failed to remove directory"))
+                .unwrap_or_default();
+        }
+    }
+}